@@ -0,0 +1,46 @@
+use crate::commands::history::UndoTree;
+use crate::pane::View;
+use crate::project_search::Match;
+use crate::Document;
+
+/// The kind of generated text that a buffer shows. Showing the same kind of text again replaces
+/// the contents of that buffer rather than opening another one.
+pub enum Output {
+    /// The current key bindings
+    Bindings,
+    /// The results of a project-wide search, with the match that each row lists
+    Matches(Vec<Match>),
+}
+
+/// An open document together with the editing state that belongs to it.
+///
+/// The buffer that is being edited has its document and history moved into the
+/// [Editor](crate::Editor) while it is active, so the buffer itself only holds an empty
+/// placeholder in the meantime.
+#[derive(Default)]
+pub struct Buffer {
+    /// The document held by this buffer
+    pub document: Document,
+    /// History of commands executed on the document
+    pub command_history: UndoTree,
+    /// The view onto the document as it was when the buffer was last shown
+    pub view: View,
+    /// The kind of generated text that the document shows, if it is not a file
+    pub output: Option<Output>,
+}
+
+impl Buffer {
+    /// Constructs a buffer for a document, with the cursor at its start.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - the [Document] to hold
+    /// * `command_history` - the history of commands executed on the document
+    pub fn new(document: Document, command_history: UndoTree) -> Self {
+        Self {
+            document,
+            command_history,
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Command, CommandRecord};
+use crate::Editor;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BytesCommand {
+    /// The offset of the first byte to replace
+    offset: usize,
+    /// The bytes that are replaced
+    removed: Vec<u8>,
+    /// The bytes that replace them
+    inserted: Vec<u8>,
+}
+
+impl BytesCommand {
+    pub fn new(offset: usize, removed: Vec<u8>, inserted: Vec<u8>) -> Self {
+        BytesCommand {
+            offset,
+            removed,
+            inserted,
+        }
+    }
+}
+
+impl Command for BytesCommand {
+    fn execute(&mut self, editor: &mut Editor) {
+        editor.replace_bytes(self.offset, self.removed.len(), &self.inserted);
+    }
+
+    fn undo(&mut self, editor: &mut Editor) {
+        editor.replace_bytes(self.offset, self.inserted.len(), &self.removed);
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord::Bytes(self.clone()))
+    }
+}
@@ -1,6 +1,9 @@
-use super::Command;
+use serde::{Deserialize, Serialize};
+
+use super::{Command, CommandRecord};
 use crate::{Editor, Position};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct DeleteCommand {
     position: Position,
     content: String,
@@ -17,10 +20,14 @@ impl DeleteCommand {
 
 impl Command for DeleteCommand {
     fn execute(&mut self, editor: &mut Editor) {
-        editor.delete_chars_at(&self.position, self.content.len());
+        editor.delete_chars_at(&self.position, self.content.chars().count());
     }
 
     fn undo(&mut self, editor: &mut Editor) {
         editor.insert_string_at(&self.position, &self.content, false);
     }
+
+    fn record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord::Delete(self.clone()))
+    }
 }
@@ -1,7 +1,9 @@
-use super::{BoxedCommand, Command};
+use serde::{Deserialize, Serialize};
+
+use super::{BoxedCommand, Command, CommandRecord};
 use crate::Editor;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum CommandType {
     PASTE,
     INSERT,
@@ -49,6 +51,30 @@ impl CommandGroup {
     pub fn add(&mut self, command: BoxedCommand) {
         self.commands.push(command);
     }
+
+    /// Returns a [`CommandGroup`] made up of previously recorded commands.
+    ///
+    /// # Arguments
+    ///
+    /// * `records` - the [`CommandRecords`](CommandRecord) to turn back into commands
+    /// * `command_type` - the [`CommandType`] of the new [`CommandGroup`]
+    pub fn from_records(records: Vec<CommandRecord>, command_type: CommandType) -> Self {
+        CommandGroup {
+            commands: records
+                .into_iter()
+                .map(CommandRecord::into_command)
+                .collect(),
+            command_type,
+        }
+    }
+
+    /// Gets the records of every command in this [`CommandGroup`] that can be persisted.
+    pub fn records(&self) -> Vec<CommandRecord> {
+        self.commands
+            .iter()
+            .filter_map(|command| command.borrow().record())
+            .collect()
+    }
 }
 
 impl Command for CommandGroup {
@@ -0,0 +1,621 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::group::{CommandGroup, CommandType};
+use super::{BoxedCommand, Command, CommandRecord};
+use crate::config;
+use crate::document::{extend_hash, HASH_SEED};
+use crate::Editor;
+
+/// A state in the [`UndoTree`], reached by executing its [`CommandGroup`] on top of its parent state.
+struct Node {
+    /// The commands that lead from the parent state to this state
+    group: CommandGroup,
+    /// The id of the parent state, or `None` if the parent is the root (the unmodified document)
+    parent: Option<usize>,
+    /// The ids of the states that branch off of this state
+    children: Vec<usize>,
+    /// The child that was most recently entered, which is the one that redo moves to
+    last_child: Option<usize>,
+    /// When this state was created
+    time: SystemTime,
+}
+
+/// The serializable form of a [`Node`].
+#[derive(Serialize, Deserialize)]
+struct NodeRecord {
+    id: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    last_child: Option<usize>,
+    time: SystemTime,
+    command_type: CommandType,
+    commands: Vec<CommandRecord>,
+}
+
+/// The serializable form of an [`UndoTree`], tagged with the hash of the file contents that
+/// its current state corresponds to.
+#[derive(Serialize, Deserialize)]
+struct TreeRecord {
+    hash: u64,
+    nodes: Vec<NodeRecord>,
+    current: Option<usize>,
+    root_last_child: Option<usize>,
+    next_id: usize,
+}
+
+/// A single movement through the [`UndoTree`].
+#[derive(PartialEq, Eq, Debug)]
+enum Step {
+    /// Undo the state with the given id, moving to its parent
+    Undo(usize),
+    /// Redo the state with the given id, moving into it from its parent
+    Redo(usize),
+}
+
+/// The history of commands, stored as a tree so that undone changes are never lost.
+///
+/// Every change creates a new state as a child of the current state. Undoing moves to the
+/// parent state and redoing moves back down to the most recently visited child. When a new
+/// change is made after undoing, it starts a new branch instead of discarding the old one;
+/// abandoned branches can still be reached with [`UndoTree::earlier`] and [`UndoTree::later`],
+/// which walk through the states in the order that they were created.
+pub struct UndoTree {
+    /// All states in the tree, keyed by id (ids increase in order of creation)
+    nodes: HashMap<usize, Node>,
+    /// The id of the current state, or `None` if at the root
+    current: Option<usize>,
+    /// The most recently entered child of the root
+    root_last_child: Option<usize>,
+    /// The id that the next state will be given
+    next_id: usize,
+    /// The maximum depth of the tree; the oldest changes are forgotten past this depth
+    limit: usize,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new(usize::MAX)
+    }
+}
+
+impl UndoTree {
+    /// Constructs an empty [`UndoTree`].
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - the maximum number of changes that can be undone in a row
+    pub fn new(limit: usize) -> Self {
+        Self {
+            nodes: HashMap::new(),
+            current: None,
+            root_last_child: None,
+            next_id: 1,
+            limit: limit.max(1),
+        }
+    }
+
+    /// Adds a [`CommandGroup`] as a new state that branches off of the current state.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - the [`CommandGroup`] that has already been executed
+    pub fn push(&mut self, group: CommandGroup) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.nodes.insert(
+            id,
+            Node {
+                group,
+                parent: self.current,
+                children: Vec::new(),
+                last_child: None,
+                time: SystemTime::now(),
+            },
+        );
+        self.link_to_parent(id);
+        self.current = Some(id);
+
+        while self.depth(self.current) > self.limit {
+            self.forget_oldest();
+        }
+    }
+
+    /// Adds the command to the current state if that state is a leaf made up of commands of
+    /// the same type. Otherwise, the command is pushed as a new state.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - the [`BoxedCommand`] that has already been executed
+    /// * `command_type` - the [`CommandType`] of `command`
+    pub fn merge_or_push(&mut self, command: BoxedCommand, command_type: CommandType) {
+        if let Some(node) = self.current.and_then(|id| self.nodes.get_mut(&id)) {
+            if node.children.is_empty() && node.group.command_type == command_type {
+                node.group.add(command);
+                return;
+            }
+        }
+
+        self.push(CommandGroup::from_command(command, command_type));
+    }
+
+    /// Undoes the current state, moving to its parent. Returns whether anything was undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `editor` - the [`Editor`] that the commands operate on
+    pub fn undo(&mut self, editor: &mut Editor) -> bool {
+        if let Some(id) = self.current {
+            self.apply(&[Step::Undo(id)], editor);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Redoes the most recently visited child of the current state. Returns whether anything
+    /// was redone.
+    ///
+    /// # Arguments
+    ///
+    /// * `editor` - the [`Editor`] that the commands operate on
+    pub fn redo(&mut self, editor: &mut Editor) -> bool {
+        if let Some(id) = self.last_child_of(self.current) {
+            self.apply(&[Step::Redo(id)], editor);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the state that was created just before the current one, which may lie on a
+    /// different branch. Returns whether the current state changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `editor` - the [`Editor`] that the commands operate on
+    pub fn earlier(&mut self, editor: &mut Editor) -> bool {
+        if let Some(current) = self.current {
+            let target = self.nodes.keys().filter(|id| **id < current).max().copied();
+            self.apply(&self.steps_to(target), editor);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Moves to the state that was created just after the current one, which may lie on a
+    /// different branch. Returns whether the current state changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `editor` - the [`Editor`] that the commands operate on
+    pub fn later(&mut self, editor: &mut Editor) -> bool {
+        let current = self.current.unwrap_or(0);
+        if let Some(target) = self.nodes.keys().filter(|id| **id > current).min().copied() {
+            self.apply(&self.steps_to(Some(target)), editor);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Gets how long ago the current state was created, or `None` if at the root.
+    pub fn current_age(&self) -> Option<Duration> {
+        self.current
+            .and_then(|id| self.nodes.get(&id))
+            .map(|node| node.time.elapsed().unwrap_or_default())
+    }
+
+    /// Loads the persisted history of a file. Returns `None` if there is no history for the
+    /// file, or if the history is stale because the file has changed since it was saved (in
+    /// which case the stale history is deleted).
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the name of the file
+    /// * `hash` - the hash of the file's current contents
+    /// * `limit` - the maximum number of changes that can be undone in a row
+    pub fn load(filename: &str, hash: u64, limit: usize) -> Option<Self> {
+        Self::load_from(&store_path(filename)?, hash, limit)
+    }
+
+    /// Persists the history of a file so that it can be restored when the file is reopened.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the name of the file
+    /// * `hash` - the hash of the file's contents as of the current state
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is nowhere to store the history or if I/O error encountered
+    /// while writing it
+    pub fn save(&self, filename: &str, hash: u64) -> Result<(), Error> {
+        let store = store_path(filename)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no directory to store history in"))?;
+        self.save_to(&store, hash)
+    }
+
+    /// Loads a history from the given store. See [`UndoTree::load`].
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - the path of the file that the history is stored in
+    /// * `hash` - the hash of the file's current contents
+    /// * `limit` - the maximum number of changes that can be undone in a row
+    fn load_from(store: &Path, hash: u64, limit: usize) -> Option<Self> {
+        let contents = fs::read_to_string(store).ok()?;
+        let record = match serde_json::from_str::<TreeRecord>(&contents) {
+            Ok(record) if record.hash == hash => record,
+            _ => {
+                let _ = fs::remove_file(store);
+                return None;
+            }
+        };
+
+        let nodes = record
+            .nodes
+            .into_iter()
+            .map(|node| {
+                let group = CommandGroup::from_records(node.commands, node.command_type);
+                (
+                    node.id,
+                    Node {
+                        group,
+                        parent: node.parent,
+                        children: node.children,
+                        last_child: node.last_child,
+                        time: node.time,
+                    },
+                )
+            })
+            .collect();
+
+        Some(Self {
+            nodes,
+            current: record.current,
+            root_last_child: record.root_last_child,
+            next_id: record.next_id,
+            limit: limit.max(1),
+        })
+    }
+
+    /// Writes the history to the given store. See [`UndoTree::save`].
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - the path of the file to store the history in
+    /// * `hash` - the hash of the file's contents as of the current state
+    fn save_to(&self, store: &Path, hash: u64) -> Result<(), Error> {
+        let record = TreeRecord {
+            hash,
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(id, node)| NodeRecord {
+                    id: *id,
+                    parent: node.parent,
+                    children: node.children.clone(),
+                    last_child: node.last_child,
+                    time: node.time,
+                    command_type: node.group.command_type,
+                    commands: node.group.records(),
+                })
+                .collect(),
+            current: self.current,
+            root_last_child: self.root_last_child,
+            next_id: self.next_id,
+        };
+
+        if let Some(dir) = store.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(store, serde_json::to_string(&record)?)
+    }
+
+    /// Executes or undoes the [`CommandGroups`](CommandGroup) along a path through the tree,
+    /// updating the current state as it goes.
+    ///
+    /// # Arguments
+    ///
+    /// * `steps` - the path to take
+    /// * `editor` - the [`Editor`] that the commands operate on
+    fn apply(&mut self, steps: &[Step], editor: &mut Editor) {
+        for step in steps {
+            match *step {
+                Step::Undo(id) => {
+                    if let Some(node) = self.nodes.get_mut(&id) {
+                        node.group.undo(editor);
+                        self.current = node.parent;
+                    }
+                }
+                Step::Redo(id) => {
+                    if let Some(node) = self.nodes.get_mut(&id) {
+                        node.group.execute(editor);
+                        let parent = node.parent;
+                        self.set_last_child(parent, id);
+                        self.current = Some(id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Computes the path from the current state to the target state: undo up to their
+    /// closest common ancestor, then redo down to the target.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - the id of the target state, or `None` for the root
+    fn steps_to(&self, target: Option<usize>) -> Vec<Step> {
+        let from = self.ancestors(self.current);
+        let to = self.ancestors(target);
+
+        // Both paths end at the root, so everything past the closest common ancestor is shared
+        let common = from.iter().find(|id| to.contains(id)).copied();
+        let up = from.iter().take_while(|id| Some(**id) != common);
+        let down: Vec<usize> = to
+            .iter()
+            .take_while(|id| Some(**id) != common)
+            .copied()
+            .collect();
+
+        up.map(|id| Step::Undo(*id))
+            .chain(down.into_iter().rev().map(Step::Redo))
+            .collect()
+    }
+
+    /// Gets the ids of a state and all of its ancestors (excluding the root), nearest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the state, or `None` for the root
+    fn ancestors(&self, mut id: Option<usize>) -> Vec<usize> {
+        let mut ancestors = Vec::new();
+        while let Some(node_id) = id {
+            ancestors.push(node_id);
+            id = self.nodes.get(&node_id).and_then(|node| node.parent);
+        }
+        ancestors
+    }
+
+    /// Gets the depth of a state, where the root has a depth of 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the state, or `None` for the root
+    fn depth(&self, id: Option<usize>) -> usize {
+        self.ancestors(id).len()
+    }
+
+    /// Forgets the oldest change on the current branch: the root's child on the path to the
+    /// current state is folded into the root, and every other branch off of the root (which
+    /// can no longer be reached) is dropped.
+    fn forget_oldest(&mut self) {
+        let Some(&oldest) = self.ancestors(self.current).last() else {
+            return;
+        };
+
+        let root_children: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in root_children {
+            if id != oldest {
+                self.remove_subtree(id);
+            }
+        }
+
+        if let Some(node) = self.nodes.remove(&oldest) {
+            for child in &node.children {
+                if let Some(child) = self.nodes.get_mut(child) {
+                    child.parent = None;
+                }
+            }
+            self.root_last_child = node.last_child;
+        }
+    }
+
+    /// Removes a state and all of its descendants from the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the state to remove
+    fn remove_subtree(&mut self, id: usize) {
+        if let Some(node) = self.nodes.remove(&id) {
+            for child in node.children {
+                self.remove_subtree(child);
+            }
+        }
+    }
+
+    /// Registers a newly created state as a child of its parent.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the new state
+    fn link_to_parent(&mut self, id: usize) {
+        let parent = self.nodes[&id].parent;
+        if let Some(parent_node) = parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            parent_node.children.push(id);
+        }
+        self.set_last_child(parent, id);
+    }
+
+    /// Gets the most recently entered child of a state.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the state, or `None` for the root
+    fn last_child_of(&self, id: Option<usize>) -> Option<usize> {
+        match id {
+            Some(id) => self.nodes.get(&id).and_then(|node| node.last_child),
+            None => self.root_last_child,
+        }
+    }
+
+    /// Sets the most recently entered child of a state.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the id of the state, or `None` for the root
+    /// * `child` - the id of the child
+    fn set_last_child(&mut self, id: Option<usize>, child: usize) {
+        match id {
+            Some(id) => {
+                if let Some(node) = self.nodes.get_mut(&id) {
+                    node.last_child = Some(child);
+                }
+            }
+            None => self.root_last_child = Some(child),
+        }
+    }
+}
+
+/// How many characters of a file's name are kept in the name of the file that stores its
+/// history, which keeps the latter within the limits of file systems
+const STORE_NAME_LEN: usize = 64;
+
+/// Gets the path of the file that stores the history of a file, e.g.
+/// `~/.local/share/ferro/undo/foo.rs-3f2c9a0b1d4e5f67.json`. See [`store_name`].
+///
+/// # Arguments
+///
+/// * `filename` - the name of the file
+fn store_path(filename: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(filename).ok()?;
+    Some(config::data_dir()?.join("undo").join(store_name(&path)))
+}
+
+/// Gets the name of the file that stores the history of a file, which is made up of the
+/// (shortened) name of the file and a hash of its absolute path, so that files with the same
+/// name in different directories do not share a history.
+///
+/// # Arguments
+///
+/// * `path` - the absolute path of the file
+fn store_name(path: &Path) -> String {
+    let name: String = path
+        .file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .chars()
+                .take(STORE_NAME_LEN)
+                .collect()
+        })
+        .unwrap_or_default();
+    let hash = extend_hash(HASH_SEED, path.as_os_str().as_encoded_bytes());
+    format!("{name}-{hash:016x}.json")
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::path::Path;
+
+    use super::{store_name, Step, UndoTree};
+    use crate::commands::copy::CopyCommand;
+    use crate::commands::group::{CommandGroup, CommandType};
+    use crate::commands::insert::InsertCommand;
+    use crate::test_util::TempDir;
+    use crate::Position;
+
+    #[test]
+    fn branches() {
+        let mut tree = UndoTree::new(10);
+        tree.push(CommandGroup::new(CommandType::INSERT)); // 1
+        tree.push(CommandGroup::new(CommandType::DELETE)); // 2
+        assert_eq!(tree.steps_to(None), vec![Step::Undo(2), Step::Undo(1)]);
+
+        // Undo 2 and start a new branch from 1
+        tree.current = Some(1);
+        tree.push(CommandGroup::new(CommandType::PASTE)); // 3
+        assert_eq!(tree.nodes.len(), 3);
+        assert_eq!(tree.nodes[&1].children, vec![2, 3]);
+        assert_eq!(tree.last_child_of(Some(1)), Some(3));
+        assert_eq!(tree.steps_to(Some(2)), vec![Step::Undo(3), Step::Redo(2)]);
+        assert_eq!(tree.steps_to(Some(3)), Vec::new());
+    }
+
+    #[test]
+    fn merge() {
+        let command = || Box::new(RefCell::new(CopyCommand::new()));
+
+        let mut tree = UndoTree::new(10);
+        tree.merge_or_push(command(), CommandType::INSERT);
+        tree.merge_or_push(command(), CommandType::INSERT);
+        tree.merge_or_push(command(), CommandType::DELETE);
+        assert_eq!(tree.nodes.len(), 2);
+
+        // Changes made after moving back to a state with children start a new branch
+        tree.current = Some(1);
+        tree.merge_or_push(command(), CommandType::INSERT);
+        assert_eq!(tree.nodes.len(), 3);
+        assert_eq!(tree.current, Some(3));
+    }
+
+    #[test]
+    fn limit() {
+        let mut tree = UndoTree::new(2);
+        tree.push(CommandGroup::new(CommandType::INSERT)); // 1
+        tree.current = None;
+        tree.push(CommandGroup::new(CommandType::INSERT)); // 2
+        tree.push(CommandGroup::new(CommandType::INSERT)); // 3
+        assert_eq!(tree.nodes.len(), 3);
+
+        // State 2 is folded into the root, and the branch containing 1 becomes unreachable
+        tree.push(CommandGroup::new(CommandType::INSERT)); // 4
+        assert_eq!(tree.nodes.len(), 2);
+        assert_eq!(tree.nodes[&3].parent, None);
+        assert_eq!(tree.steps_to(None), vec![Step::Undo(4), Step::Undo(3)]);
+        assert_eq!(tree.last_child_of(None), Some(3));
+    }
+
+    #[test]
+    fn persist() {
+        let dir = TempDir::new("undo");
+        let store = dir.join("undo.json");
+        let insert = |x: usize, content: &str| {
+            CommandGroup::from_command(
+                Box::new(RefCell::new(InsertCommand::new(
+                    Position { x, y: 0 },
+                    content.to_string(),
+                ))),
+                CommandType::INSERT,
+            )
+        };
+
+        let mut tree = UndoTree::new(10);
+        tree.push(insert(0, "foo"));
+        tree.current = None;
+        tree.push(insert(0, "bar"));
+        tree.save_to(&store, 42).unwrap();
+
+        let loaded = UndoTree::load_from(&store, 42, 10).unwrap();
+        assert_eq!(loaded.current, Some(2));
+        assert_eq!(loaded.last_child_of(None), Some(2));
+        assert_eq!(loaded.nodes[&1].group.records().len(), 1);
+        assert_eq!(loaded.steps_to(Some(1)), vec![Step::Undo(2), Step::Redo(1)]);
+
+        // A history saved for different contents is dropped
+        assert!(UndoTree::load_from(&store, 7, 10).is_none());
+        assert!(!store.exists());
+    }
+
+    #[test]
+    fn store_names() {
+        let name = |path: &str| store_name(Path::new(path));
+        assert_ne!(name("/a%b/c"), name("/a/b%c"));
+        assert!(name("/src/main.rs").starts_with("main.rs-"));
+        assert!(name(&format!("/{}", "x".repeat(300))).len() < 100);
+    }
+}
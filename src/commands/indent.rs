@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Command, CommandRecord};
+use crate::document::IndentChange;
+use crate::Editor;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IndentCommand {
+    /// Whether to indent with tabs (or spaces)
+    tabs: bool,
+    /// Whether the document was indented with tabs before the command was executed
+    previous_tabs: bool,
+    /// The changes made to the indentation of rows
+    changes: Vec<IndentChange>,
+}
+
+impl IndentCommand {
+    pub fn new(tabs: bool) -> Self {
+        IndentCommand {
+            tabs,
+            previous_tabs: tabs,
+            changes: Vec::new(),
+        }
+    }
+}
+
+impl Command for IndentCommand {
+    fn execute(&mut self, editor: &mut Editor) {
+        (self.previous_tabs, self.changes) = editor.reindent(self.tabs);
+    }
+
+    fn undo(&mut self, editor: &mut Editor) {
+        editor.restore_indentation(&self.changes, self.previous_tabs);
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord::Indent(self.clone()))
+    }
+}
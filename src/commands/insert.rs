@@ -1,6 +1,9 @@
-use super::Command;
+use serde::{Deserialize, Serialize};
+
+use super::{Command, CommandRecord};
 use crate::{Editor, Position};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct InsertCommand {
     position: Position,
     content: String,
@@ -18,6 +21,10 @@ impl Command for InsertCommand {
     }
 
     fn undo(&mut self, editor: &mut Editor) {
-        editor.delete_chars_at(&self.position, self.content.len());
+        editor.delete_chars_at(&self.position, self.content.chars().count());
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord::Insert(self.clone()))
     }
 }
@@ -1,10 +1,15 @@
 use std::cell::RefCell;
 
+use serde::{Deserialize, Serialize};
+
 use crate::editor::Editor;
 
+pub mod bytes;
 pub mod copy;
 pub mod delete;
 pub mod group;
+pub mod history;
+pub mod indent;
 pub mod insert;
 pub mod paste;
 
@@ -22,6 +27,35 @@ pub trait Command {
     ///
     /// * `editor` - the [Editor] that the command operates on
     fn undo(&mut self, editor: &mut Editor);
+
+    /// Describes the command so that it can be persisted. Commands that do not modify the
+    /// document have nothing to persist.
+    fn record(&self) -> Option<CommandRecord> {
+        None
+    }
 }
 
 pub type BoxedCommand = Box<RefCell<dyn Command>>;
+
+/// A serializable form of a [`Command`] that modifies the document.
+#[derive(Serialize, Deserialize)]
+pub enum CommandRecord {
+    Insert(insert::InsertCommand),
+    Delete(delete::DeleteCommand),
+    Paste(paste::PasteCommand),
+    Indent(indent::IndentCommand),
+    Bytes(bytes::BytesCommand),
+}
+
+impl CommandRecord {
+    /// Converts the record back into the command that it describes.
+    pub fn into_command(self) -> BoxedCommand {
+        match self {
+            CommandRecord::Insert(command) => Box::new(RefCell::new(command)),
+            CommandRecord::Delete(command) => Box::new(RefCell::new(command)),
+            CommandRecord::Paste(command) => Box::new(RefCell::new(command)),
+            CommandRecord::Indent(command) => Box::new(RefCell::new(command)),
+            CommandRecord::Bytes(command) => Box::new(RefCell::new(command)),
+        }
+    }
+}
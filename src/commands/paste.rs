@@ -1,6 +1,9 @@
-use super::Command;
+use serde::{Deserialize, Serialize};
+
+use super::{Command, CommandRecord};
 use crate::{Editor, Position};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PasteCommand {
     position: Position,
     clipboard: Option<String>,
@@ -28,7 +31,11 @@ impl Command for PasteCommand {
 
     fn undo(&mut self, editor: &mut Editor) {
         if let Some(clipboard_contents) = &self.clipboard {
-            editor.delete_chars_at(&self.position, clipboard_contents.len());
+            editor.delete_chars_at(&self.position, clipboard_contents.chars().count());
         }
     }
+
+    fn record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord::Paste(self.clone()))
+    }
 }
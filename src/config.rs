@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::{env, io};
+
+use crate::document::DEFAULT_SPACES_PER_TAB;
+use crate::keymap::Keymap;
+use serde::Deserialize;
+
+const QUIT_TIMES: u8 = 2;
+const HISTORY_LIMIT: usize = 10;
+const MESSAGE_TIMEOUT: u64 = 5;
+
+/// The user's configuration, loaded from `$XDG_CONFIG_HOME/ferro/config.toml` (or
+/// `config.json`). Settings that are missing from the file keep their default values.
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How many more times the quit command needs to be inputted to quit with unsaved changes
+    pub quit_times: u8,
+    /// How many recent changes are kept in the undo history
+    pub history_limit: usize,
+    /// The number of spaces per indent, for files whose indent cannot be detected
+    pub spaces_per_tab: usize,
+    /// How long (in seconds) status messages are displayed for
+    pub message_timeout: u64,
+    /// How keys edit text, i.e. directly, through vim-style modes or with Emacs bindings
+    pub mode: EditingMode,
+    /// The name of the color theme, or `None` for the "dark" or "light" theme to match the
+    /// terminal's background
+    pub theme: Option<String>,
+    /// Keys bound to editor actions, by the name of the action (e.g. `quit = "Ctrl+W"` or
+    /// `save = ["Ctrl+S", "Ctrl+X Ctrl+S"]`)
+    pub keys: BTreeMap<String, KeyBindings>,
+}
+
+/// How keys edit text.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EditingMode {
+    /// Typed characters are inserted, and commands are bound to modified keys
+    #[default]
+    Default,
+    /// Typed characters are commands in normal mode, as in vim, and only insert mode inserts
+    /// them
+    Vim,
+    /// Typed characters are inserted, and commands are bound to Emacs keys
+    Emacs,
+}
+
+/// The key sequences bound to an action, which can be given as one string or as a list.
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(untagged)]
+pub enum KeyBindings {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyBindings {
+    /// Iterates over the descriptions of the bound key sequences.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        match self {
+            KeyBindings::One(keys) => std::slice::from_ref(keys),
+            KeyBindings::Many(keys) => keys.as_slice(),
+        }
+        .iter()
+        .map(String::as_str)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            quit_times: QUIT_TIMES,
+            history_limit: HISTORY_LIMIT,
+            spaces_per_tab: DEFAULT_SPACES_PER_TAB,
+            message_timeout: MESSAGE_TIMEOUT,
+            mode: EditingMode::default(),
+            theme: None,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the user's configuration, falling back to the default configuration if there is
+    /// no configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a description of the problem if the configuration file cannot be
+    /// read or parsed
+    pub fn load() -> Result<Self, String> {
+        let Some(dir) = config_dir() else {
+            return Ok(Self::default());
+        };
+
+        for (name, json) in [("config.toml", false), ("config.json", true)] {
+            let path = dir.join(name);
+            match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    return Self::parse(&contents, json)
+                        .map_err(|error| format!("{}: {error}", path.display()));
+                }
+                Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+                Err(error) => return Err(format!("{}: {error}", path.display())),
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Parses a configuration from TOML or JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the configuration to parse
+    /// * `json` - whether the configuration is JSON rather than TOML
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a description of the problem if the configuration is invalid
+    pub fn parse(contents: &str, json: bool) -> Result<Self, String> {
+        let config: Self = if json {
+            serde_json::from_str(contents).map_err(|error| error.to_string())?
+        } else {
+            toml::from_str(contents).map_err(|error| error.message().to_string())?
+        };
+
+        Keymap::new(config.mode, &config.keys)?;
+        Ok(config)
+    }
+}
+
+/// Gets the directory that holds ferro's configuration, e.g. `~/.config/ferro`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("ferro"))
+}
+
+/// Gets the directory that holds ferro's data, e.g. `~/.local/share/ferro`.
+pub fn data_dir() -> Option<PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+    Some(data_dir.join("ferro"))
+}
+
+/// Lists the files in a directory that have one of the given extensions, sorted by path so that
+/// they are always loaded in the same order.
+///
+/// # Arguments
+///
+/// * `dir` - the directory to list, if any
+/// * `extensions` - the extensions of the files to list, e.g. `["toml"]`
+pub fn files_with_extensions(dir: Option<&Path>, extensions: &[&str]) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = dir
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extensions.iter().any(|wanted| extension == *wanted))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{files_with_extensions, Config, EditingMode, KeyBindings};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(
+            "quit_times = 0\nmessage_timeout = 3\n\n[keys]\nsearch = \"Ctrl+F\"\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(config.quit_times, 0);
+        assert_eq!(config.message_timeout, 3);
+        assert_eq!(config.history_limit, Config::default().history_limit);
+        assert_eq!(
+            config.keys["search"],
+            KeyBindings::One("Ctrl+F".to_string())
+        );
+
+        assert_eq!(config.theme, None);
+        let json = Config::parse(
+            r#"{"spaces_per_tab": 2, "mode": "vim", "theme": "light"}"#,
+            true,
+        )
+        .unwrap();
+        assert_eq!(json.spaces_per_tab, 2);
+        assert_eq!(json.mode, EditingMode::Vim);
+        assert_eq!(json.theme.as_deref(), Some("light"));
+        let emacs = Config::parse("mode = \"emacs\"", false).unwrap();
+        assert_eq!(emacs.mode, EditingMode::Emacs);
+
+        assert!(Config::parse("quit_times = \"never\"", false).is_err());
+        assert!(Config::parse("colour = \"red\"", false).is_err());
+        assert!(Config::parse("[keys]\nsave = \"Ctrl+Save\"", false).is_err());
+        assert!(Config::parse("[keys]\nfly = \"F3\"", false).is_err());
+
+        let chords = Config::parse(
+            "[keys]\nsave = [\"Ctrl+X Ctrl+S\", \"F2\"]\ncut = \"Ctrl+K\"",
+            false,
+        )
+        .unwrap();
+        assert_eq!(chords.keys["save"].iter().count(), 2);
+    }
+
+    #[test]
+    fn list_files() {
+        let dir = TempDir::new("config-files");
+        for name in ["b.toml", "a.toml", "c.json", "d.tmLanguage"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let names = |extensions: &[&str]| -> Vec<String> {
+            files_with_extensions(Some(dir.path()), extensions)
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .collect()
+        };
+        assert_eq!(names(&["toml"]), ["a.toml", "b.toml"]);
+        assert_eq!(names(&["tmLanguage", "json"]), ["c.json", "d.tmLanguage"]);
+        assert!(files_with_extensions(None, &["toml"]).is_empty());
+    }
+}
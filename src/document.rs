@@ -1,45 +1,240 @@
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{Error, Write};
+use std::io::{Error, ErrorKind};
 
+use ropey::{Rope, RopeBuilder};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::encoding::Encoding;
+use crate::hex::{self, BYTES_PER_ROW};
+use crate::loader::{self, Loader, Poll, LAZY_THRESHOLD};
+use crate::row::leading_spaces;
+use crate::search::Pattern;
 use crate::FileType;
 use crate::Position;
 use crate::Row;
 use crate::SearchDirection;
 
-const DEFAULT_SPACES_PER_TAB: usize = 4;
+pub(crate) const DEFAULT_SPACES_PER_TAB: usize = 4;
+/// The initial value of the hash of a file's contents.
+pub(crate) const HASH_SEED: u64 = 0xcbf2_9ce4_8422_2325;
+/// The number of matches of a pattern after which counting them stops.
+const MAX_COUNTED_MATCHES: usize = 1000;
+/// The number of rows that are searched each time the matches of a pattern are counted.
+const MAX_COUNTED_ROWS: usize = 100_000;
+
+/// How many matches of a pattern a document has, as counted by [`Document::count_matches`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct MatchCount {
+    /// The number of matches that start before the position counted from, unless it is past
+    /// the last match that was counted
+    pub before: Option<usize>,
+    /// The number of matches that were counted
+    pub total: usize,
+    /// Whether counting stopped before the end of the document
+    pub capped: bool,
+}
+
+/// The matches of a pattern that were last counted, which are kept until the document changes.
+/// Counting continues from where it stopped when the rows that were not searched yet are needed.
+struct CountedMatches {
+    /// The pattern that was matched
+    pattern: Pattern,
+    /// The positions of the matches, in order
+    positions: Vec<Position>,
+    /// The number of rows, from the start of the document, that have been searched
+    rows: usize,
+    /// Whether counting stopped after [`MAX_COUNTED_MATCHES`] matches
+    capped: bool,
+}
+
+impl CountedMatches {
+    /// Starts counting the matches of a pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [`Pattern`] to count matches of
+    fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            positions: Vec::new(),
+            rows: 0,
+            capped: false,
+        }
+    }
+
+    /// Counts the matches that start before a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the [`Position`] to count the previous matches of
+    /// * `complete` - whether the whole document has been searched
+    fn count(&self, at: &Position, complete: bool) -> MatchCount {
+        let before = self.positions.partition_point(|position| position < at);
+        let known = before < self.positions.len() || complete || (!self.capped && at.y < self.rows);
+        MatchCount {
+            before: Some(before).filter(|_| known),
+            total: self.positions.len(),
+            capped: !complete,
+        }
+    }
+}
+
+/// The style of line endings used in a document.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// Detects the style of line endings used in a file from its first line ending.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the contents of the file, or the start of them
+    fn detect(contents: &str) -> Self {
+        match contents.find('\n') {
+            Some(index) if contents[..index].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    /// Gets the characters that end a line in this style.
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// Gets the name of this style.
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// The formatting of a file, which is detected when it is opened.
+#[derive(Default)]
+struct Format {
+    /// Whether the file is indented with tabs rather than spaces
+    indent_with_tabs: bool,
+    /// The style of line endings used in the file
+    line_ending: LineEnding,
+    /// Whether the last line of the file is followed by a line ending
+    final_newline: bool,
+    /// The character encoding of the file
+    encoding: Encoding,
+}
+
+/// A change made to the indentation of a row, e.g. by [`Document::reindent`].
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IndentChange {
+    /// The index of the row
+    pub y: usize,
+    /// The indentation of the row before the change
+    pub before: String,
+    /// The indentation of the row after the change
+    pub after: String,
+}
 
 /// The document that is currently being edited.
+///
+/// The text of the document is stored in a [`Rope`], so that edits take logarithmic time no
+/// matter how large the document is. [Rows](Row) are only materialized from the text once they
+/// are needed (e.g. to be rendered or edited), and are kept in sync with the text from then on.
+///
+/// Large files are opened lazily: their text is loaded on a background thread, and until it has
+/// been loaded, rows are read directly from the file and the document cannot be edited.
+///
+/// The line endings, final newline and tabs of the file are kept as they are, so that saving the
+/// document only changes what was edited.
+///
+/// Binary files are shown as a hex dump instead, whose rows are rendered from the file's bytes.
 #[derive(Default)]
 pub struct Document {
     /// The filename of this document
     pub filename: Option<String>,
-    /// The [Rows](Row) in the document
-    rows: Vec<Row>,
+    /// The text of the document, in which every row is terminated by a newline
+    text: Rope,
+    /// The [Rows](Row) that have been materialized from the text, keyed by index
+    rows: BTreeMap<usize, Row>,
     /// Whether the document is dirty
     dirty: bool,
     /// The [filetype](FileType) of the document
     file_type: FileType,
-    /// The number of spaces that each tab should be replaced with
+    /// The number of spaces per indent, which is also the number of columns between tab stops
     spaces_per_tab: usize,
+    /// Whether the indent was detected from the document's contents
+    indent_detected: bool,
+    /// The formatting of the document's file, which is kept when it is saved
+    format: Format,
     /// Indices of rows with selections
     selections: HashSet<usize>,
+    /// The hash of the file's contents as of when it was last opened or saved
+    hash: Option<u64>,
+    /// The loader of the file's text, if it is still being loaded
+    loader: Option<Loader>,
+    /// Whether the document was opened lazily, in which case only visible rows are highlighted
+    lazy: bool,
+    /// The raw contents of the document, if it is binary and shown as a hex dump
+    bytes: Option<Vec<u8>>,
+    /// The matches that were last counted, so that moving between them does not count them again
+    counted_matches: Option<CountedMatches>,
 }
 
 impl Document {
     /// Constructs a blank document.
     pub fn default() -> Self {
         Document {
-            rows: Vec::new(),
+            text: Rope::new(),
+            rows: BTreeMap::new(),
             filename: None,
             dirty: false,
             file_type: FileType::default(),
             spaces_per_tab: DEFAULT_SPACES_PER_TAB,
+            indent_detected: false,
+            format: Format {
+                indent_with_tabs: false,
+                line_ending: LineEnding::default(),
+                final_newline: true,
+                encoding: Encoding::default(),
+            },
             selections: HashSet::new(),
+            hash: None,
+            loader: None,
+            lazy: false,
+            bytes: None,
+            counted_matches: None,
         }
     }
 
-    /// Creates a Document from the specified file.
+    /// Creates an unnamed Document that holds the given text, e.g. to show generated output.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the text of the Document
+    pub fn from_text(contents: &str) -> Self {
+        let mut text = RopeBuilder::new();
+        for line in contents.lines() {
+            text.append(line);
+            text.append("\n");
+        }
+
+        Self {
+            text: text.finish(),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a Document from the specified file, detecting its encoding. Files of at least
+    /// [`LAZY_THRESHOLD`] bytes are opened [lazily](Document::open_lazy).
     ///
     /// # Arguments
     ///
@@ -50,37 +245,196 @@ impl Document {
     /// Will return `Err` if I/O error encountered while attempting to read file
     /// specified by `filename`
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
-        let file_type = FileType::from(filename);
+        if fs::metadata(filename)?.len() >= LAZY_THRESHOLD {
+            return Self::open_lazy(filename);
+        }
+        Self::open_as(filename, None)
+    }
 
-        let mut rows: Vec<Row> = contents.lines().map(Row::from).collect();
+    /// Creates a Document from the specified file, decoding it with the given encoding. Files
+    /// that are detected to be binary are [opened as hex](Document::open_hex) if no encoding is
+    /// given.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file from which the Document is created
+    /// * `encoding` - the [`Encoding`] of the file, or `None` to detect it
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered while attempting to read file
+    /// specified by `filename`, or if it is not valid in the given encoding
+    pub fn open_as(filename: &str, encoding: Option<Encoding>) -> Result<Self, std::io::Error> {
+        let bytes = fs::read(filename)?;
+        if encoding.is_none() && hex::is_binary(&bytes) {
+            return Ok(Self::from_bytes(filename, bytes));
+        }
+        let encoding = encoding.unwrap_or_else(|| Encoding::detect(&bytes));
+        let contents = encoding.decode(&bytes)?;
+        let file_type = FileType::detect(filename, contents.lines().next().unwrap_or_default());
+
+        let indent = Self::calculate_indent(contents.lines());
 
-        let spaces_per_tab = Self::calculate_indent(&rows);
-        for row in rows.iter_mut() {
-            row.replace_tabs_with_spaces(spaces_per_tab);
+        let mut text = RopeBuilder::new();
+        for line in contents.lines() {
+            text.append(line);
+            text.append("\n");
         }
 
         Ok(Self {
-            rows,
+            text: text.finish(),
+            rows: BTreeMap::new(),
             filename: Some(filename.to_string()),
             dirty: false,
             file_type,
-            spaces_per_tab: spaces_per_tab,
+            spaces_per_tab: indent.unwrap_or(DEFAULT_SPACES_PER_TAB),
+            indent_detected: indent.is_some(),
+            format: Format {
+                indent_with_tabs: Self::uses_tabs(contents.lines()),
+                line_ending: LineEnding::detect(&contents),
+                final_newline: contents.is_empty() || contents.ends_with('\n'),
+                encoding,
+            },
+            selections: HashSet::new(),
+            hash: Some(hash(&bytes)),
+            loader: None,
+            lazy: false,
+            bytes: None,
+            counted_matches: None,
+        })
+    }
+
+    /// Creates a Document that shows the specified file as a hex dump, so that its bytes can be
+    /// edited directly.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file from which the Document is created
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered while attempting to read file
+    /// specified by `filename`
+    pub fn open_hex(filename: &str) -> Result<Self, std::io::Error> {
+        Ok(Self::from_bytes(filename, fs::read(filename)?))
+    }
+
+    /// Creates a Document that shows the contents of a file as a hex dump.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file
+    /// * `bytes` - the contents of the file
+    fn from_bytes(filename: &str, bytes: Vec<u8>) -> Self {
+        Self {
+            filename: Some(filename.to_string()),
+            hash: Some(hash(&bytes)),
+            bytes: Some(bytes),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a Document from the specified file without reading it up front. The file is
+    /// loaded on a background thread, whose progress is taken by [`Document::poll_loader`].
+    ///
+    /// The indent and line endings of the file are detected from its first few lines only.
+    /// Files that are not encoded in UTF-8 are read up front instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file from which the Document is created
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered while attempting to open file
+    /// specified by `filename`
+    pub fn open_lazy(filename: &str) -> Result<Self, std::io::Error> {
+        let (sample, final_newline) = loader::sample(filename)?;
+        let sample = match std::str::from_utf8(&sample) {
+            Ok(sample) => sample,
+            // The sample may end partway through a character
+            Err(error) if error.error_len().is_none() => {
+                std::str::from_utf8(&sample[..error.valid_up_to()]).unwrap_or_default()
+            }
+            Err(_) => return Self::open_as(filename, None),
+        };
+        if Encoding::detect(sample.as_bytes()) != Encoding::Utf8
+            || hex::is_binary(sample.as_bytes())
+        {
+            return Self::open_as(filename, None);
+        }
+        let indent = Self::calculate_indent(sample.lines());
+        let loader = Loader::start(filename)?;
+
+        Ok(Self {
+            text: Rope::new(),
+            rows: BTreeMap::new(),
+            filename: Some(filename.to_string()),
+            dirty: false,
+            file_type: FileType::detect(filename, sample.lines().next().unwrap_or_default()),
+            spaces_per_tab: indent.unwrap_or(DEFAULT_SPACES_PER_TAB),
+            indent_detected: indent.is_some(),
+            format: Format {
+                indent_with_tabs: Self::uses_tabs(sample.lines()),
+                line_ending: LineEnding::detect(sample),
+                final_newline,
+                encoding: Encoding::Utf8,
+            },
             selections: HashSet::new(),
+            hash: None,
+            loader: Some(loader),
+            lazy: true,
+            bytes: None,
+            counted_matches: None,
         })
     }
 
+    /// Takes the progress made loading the document's file, if it is being loaded lazily.
+    /// Returns whether the document has changed as a result. If the file turns out not to be
+    /// valid UTF-8 past the start that was sampled, it is read up front with its encoding
+    /// detected instead.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file could not be loaded entirely, in which case the document
+    /// stays read-only
+    pub fn poll_loader(&mut self) -> Result<bool, std::io::Error> {
+        let Some(loader) = &mut self.loader else {
+            return Ok(false);
+        };
+
+        match loader.poll() {
+            Poll::Pending(changed) => Ok(changed),
+            Poll::Finished(text, hash) => {
+                self.text = text;
+                self.hash = Some(hash);
+                self.loader = None;
+                Ok(true)
+            }
+            Poll::Failed(error) if error.kind() == ErrorKind::InvalidData => {
+                let filename = self.filename.clone().unwrap_or_default();
+                let spaces_per_tab = self.spaces_per_tab;
+                *self = Self::open_as(&filename, None)?;
+                if !self.indent_detected {
+                    self.spaces_per_tab = spaces_per_tab;
+                }
+                Ok(true)
+            }
+            Poll::Failed(error) => Err(error),
+        }
+    }
+
     /// Computes the number of spaces for indentation in the file based on a majority
-    /// algorithm.
+    /// algorithm, if the file is indented at all.
     ///
     /// # Arguments
     ///
-    /// * `rows` - the [Rows](Row) to calculate the indent from
-    fn calculate_indent(rows: &Vec<Row>) -> usize {
+    /// * `lines` - the lines to calculate the indent from
+    fn calculate_indent<'a>(lines: impl Iterator<Item = &'a str>) -> Option<usize> {
         let mut indent_counts = HashMap::new();
         let mut prev_indent = 0;
-        for row in rows.iter() {
-            if let Some(indent) = row.get_leading_spaces() {
+        for line in lines {
+            if let Some(indent) = leading_spaces(line) {
                 let indent_diff = indent.abs_diff(prev_indent);
                 if indent_diff > 1 {
                     let count = indent_counts.entry(indent_diff).or_insert(0);
@@ -94,7 +448,25 @@ impl Document {
             .into_iter()
             .max_by(|a, b| a.1.cmp(&b.1))
             .map(|(k, _)| k)
-            .unwrap_or(DEFAULT_SPACES_PER_TAB)
+    }
+
+    /// Checks whether a file is indented with tabs rather than spaces, based on which of them
+    /// more lines start with.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - the lines of the file
+    fn uses_tabs<'a>(lines: impl Iterator<Item = &'a str>) -> bool {
+        let (tabs, spaces) = lines.fold((0, 0), |(tabs, spaces), line| {
+            if line.starts_with('\t') {
+                (tabs + 1, spaces)
+            } else if line.starts_with(' ') {
+                (tabs, spaces + 1)
+            } else {
+                (tabs, spaces)
+            }
+        });
+        tabs > spaces
     }
 
     /// Inserts a newline character ('\n') at the given position.
@@ -102,76 +474,125 @@ impl Document {
     /// # Arguments
     ///
     /// * `at` - the [Position] to insert the newline character at
-    fn insert_newline(&mut self, at: &Position) -> usize {
-        if at.y > self.rows.len() {
+    /// * `auto_indent` - whether to indent the new row like the row that is split
+    fn insert_newline(&mut self, at: &Position, auto_indent: bool) -> usize {
+        let len = self.len();
+        if at.y > len {
             return 0;
         }
 
-        if at.y == self.rows.len() {
-            if let Some(prev_row) = self.rows.last() {
-                let indent = prev_row.get_leading_spaces().unwrap_or(0);
-                self.rows.push(Row::from(" ".repeat(indent).as_str()));
-                indent
-            } else {
-                self.rows.push(Row::default());
-                0
-            }
+        if at.y == len {
+            let indent = len
+                .checked_sub(1)
+                .filter(|_| auto_indent)
+                .and_then(|y| self.line(y))
+                .map(|row| row.leading_whitespace().to_string())
+                .unwrap_or_default();
+            self.text.append(Rope::from(format!("{indent}\n")));
+            indent.chars().count()
         } else {
-            let current_row = &mut self.rows[at.y];
-            let indent = current_row.get_leading_spaces().unwrap_or(0);
+            self.materialize(at.y);
+            let char_index = self.char_index(at);
+            let current_row = self.materialize(at.y);
+            // Whitespace after the split moves to the new row, so it is not indented any further
+            let indent: String = if auto_indent {
+                current_row
+                    .leading_whitespace()
+                    .chars()
+                    .take(at.x)
+                    .collect()
+            } else {
+                String::new()
+            };
 
             let mut new_row = current_row.split(at.x);
-            for _ in 0..indent {
-                new_row.insert(0, ' ');
+            for c in indent.chars().rev() {
+                new_row.insert(0, c);
             }
 
+            self.text.insert(char_index, &format!("\n{indent}"));
+            self.shift_rows(at.y.saturating_add(1), true);
             self.rows.insert(at.y.saturating_add(1), new_row);
-            indent
+            indent.chars().count()
         }
     }
 
-    /// Inserts a character at the given position
+    /// Inserts a character at the given position, indenting new rows like the rows that they
+    /// are split from and expanding tabs to spaces unless the document is indented with tabs.
+    /// Returns the length of the indent of a new row, or the spaces added for a tab.
     ///
     /// # Arguments
     ///
     /// * `at` - the [Position] to insert the character at
     /// * `c` - the character to insert
     pub fn insert(&mut self, at: &mut Position, c: char) -> usize {
-        if at.y > self.rows.len() {
+        self.insert_char(at, c, true)
+    }
+
+    /// Inserts a character at the given position without indenting new rows or expanding tabs,
+    /// e.g. to restore text exactly as it was. The position is moved past the character, which
+    /// stays on the same grapheme if the character combines with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the [Position] to insert the character at
+    /// * `c` - the character to insert
+    pub fn insert_verbatim(&mut self, at: &mut Position, c: char) {
+        let (rows, len) = (self.len(), self.row_len(at.y).unwrap_or(0));
+        self.insert_char(at, c, false);
+        if c == '\n' && self.len() > rows {
+            *at = Position { x: 0, y: at.y + 1 };
+        } else {
+            at.x += self.row_len(at.y).unwrap_or(0).saturating_sub(len);
+        }
+    }
+
+    /// Inserts a character at the given position.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the [Position] to insert the character at
+    /// * `c` - the character to insert
+    /// * `auto_indent` - whether to indent new rows like the rows that they are split from, and
+    ///   to expand tabs to spaces
+    fn insert_char(&mut self, at: &mut Position, c: char, auto_indent: bool) -> usize {
+        if at.y > self.len() || self.is_loading() || self.is_binary() {
             return 0;
         }
 
         self.dirty = true;
+        self.counted_matches = None;
         let indent = if c == '\n' {
-            self.insert_newline(&at)
-        } else if c == '\t' {
+            self.insert_newline(at, auto_indent)
+        } else if c == '\t' && auto_indent && !self.format.indent_with_tabs {
             for _ in 0..self.spaces_per_tab {
                 self.insert(at, ' ');
             }
-            self.spaces_per_tab as usize - 1
-        } else if at.y == self.rows.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
+            self.spaces_per_tab - 1
+        } else if at.y == self.len() {
+            self.text.append(Rope::from(format!("{c}\n")));
             0
         } else {
-            let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
+            // The row is materialized first, so that its index of graphemes is kept
+            self.materialize(at.y);
+            let char_index = self.char_index(at);
+            self.materialize(at.y).insert(at.x, c);
+            self.text.insert_char(char_index, c);
             0
         };
 
-        self.unhighlight_rows(at.y);
-        return indent;
+        self.unhighlight_row(at.y);
+        indent
     }
 
-    /// Unhighlights all rows starting from the given index.
+    /// Unhighlights the row at the given index. The rows after it are highlighted again only
+    /// if the state of the highlighter at their start changes as a result.
     ///
     /// # Arguments
     ///
-    /// * `start` - the index of the row from which to start unhighlighting
-    fn unhighlight_rows(&mut self, start: usize) {
-        let start = start.saturating_sub(1);
-        for row in self.rows.iter_mut().skip(start) {
+    /// * `index` - the index of the row to unhighlight
+    fn unhighlight_row(&mut self, index: usize) {
+        if let Some(row) = self.rows.get_mut(&index) {
             row.is_highlighted = false;
         }
     }
@@ -182,47 +603,256 @@ impl Document {
     ///
     /// * `at` - the [Position] to delete the character at
     pub fn delete(&mut self, at: &Position) {
-        let len = self.rows.len();
-        if at.y >= len {
+        let len = self.len();
+        if at.y >= len || self.is_loading() || self.is_binary() {
             return;
         }
 
         self.dirty = true;
-        if at.x == self.rows[at.y].len() && at.y + 1 < len {
-            let next_row = self.rows.remove(at.y + 1);
-            let row = &mut self.rows[at.y];
-            row.append(&next_row);
+        self.counted_matches = None;
+        if Some(at.x) == self.row_len(at.y) && at.y + 1 < len {
+            let newline_index = self.text.line_to_char(at.y + 1) - 1;
+            self.text.remove(newline_index..=newline_index);
+
+            let next_row = self.rows.remove(&(at.y + 1));
+            match (self.rows.get_mut(&at.y), next_row) {
+                (Some(row), Some(next_row)) => row.append(&next_row),
+                _ => {
+                    self.rows.remove(&at.y);
+                }
+            }
+            self.shift_rows(at.y + 2, false);
+        } else {
+            self.materialize(at.y);
+            let start = self.char_index(at);
+            let end = self.char_index(&Position {
+                x: at.x.saturating_add(1),
+                y: at.y,
+            });
+            self.materialize(at.y).delete(at.x);
+            self.text.remove(start..end);
+        }
+
+        self.unhighlight_row(at.y);
+    }
+
+    /// Converts the indentation of every row between tabs and spaces, and indents any new rows
+    /// the same way. Returns the changes made to rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `tabs` - whether to indent with tabs (or spaces)
+    pub fn reindent(&mut self, tabs: bool) -> Vec<IndentChange> {
+        if self.is_loading() || self.is_binary() {
+            return Vec::new();
+        }
+
+        self.format.indent_with_tabs = tabs;
+        let tab_width = self.spaces_per_tab;
+        let changes: Vec<IndentChange> = (0..self.len())
+            .filter_map(|y| {
+                let indent = Row::from(self.line(y)?.leading_whitespace());
+                let width = indent.column(indent.len(), tab_width);
+                let after = if tabs {
+                    "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+                } else {
+                    " ".repeat(width)
+                };
+                let before = indent.to_string();
+                (before != after).then_some(IndentChange { y, before, after })
+            })
+            .collect();
+
+        for change in &changes {
+            self.replace_indentation(change.y, &change.before, &change.after);
+        }
+        changes
+    }
+
+    /// Reverts changes made to the indentation of rows by [`Document::reindent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `changes` - the changes to revert
+    /// * `tabs` - whether the document was indented with tabs before the changes
+    pub fn restore_indentation(&mut self, changes: &[IndentChange], tabs: bool) {
+        self.format.indent_with_tabs = tabs;
+        for change in changes {
+            self.replace_indentation(change.y, &change.after, &change.before);
+        }
+    }
+
+    /// Replaces the indentation at the start of a row.
+    ///
+    /// # Arguments
+    ///
+    /// * `y` - the index of the row
+    /// * `before` - the current indentation of the row
+    /// * `after` - the indentation to replace it with
+    fn replace_indentation(&mut self, y: usize, before: &str, after: &str) {
+        let start = self.text.line_to_char(y);
+        self.text.remove(start..start + before.chars().count());
+        self.text.insert(start, after);
+        self.rows.remove(&y);
+        self.unhighlight_row(y);
+        self.dirty = true;
+        self.counted_matches = None;
+    }
+
+    /// Moves the materialized rows at or after an index down by one row when a row is
+    /// inserted, or up by one row when a row is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - the index of the first row to move
+    /// * `inserted` - whether a row was inserted (or removed)
+    fn shift_rows(&mut self, from: usize, inserted: bool) {
+        let moved = self.rows.split_off(&from);
+        self.rows.extend(moved.into_iter().map(|(index, row)| {
+            if inserted {
+                (index + 1, row)
+            } else {
+                (index - 1, row)
+            }
+        }));
+    }
+
+    /// Materializes the row at the given index from the document's text, if it hasn't been
+    /// already.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the row's index, which must be within the document
+    fn materialize(&mut self, index: usize) -> &mut Row {
+        if !self.rows.contains_key(&index) {
+            let row = Row::from(self.contents(index).unwrap_or_default().as_ref());
+            self.rows.insert(index, row);
+        }
+        self.rows.entry(index).or_default()
+    }
+
+    /// Gets the contents of a row in the document, reading it from the document's file if
+    /// the file is still being loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the row's index
+    fn contents(&self, index: usize) -> Option<Cow<'_, str>> {
+        if let Some(bytes) = &self.bytes {
+            hex::render_row(bytes, index).map(Cow::Owned)
+        } else if let Some(loader) = &self.loader {
+            loader.line(index).map(Cow::Owned)
+        } else if index < self.len() {
+            Some(line_contents(&self.text, index))
         } else {
-            let row = &mut self.rows[at.y];
-            row.delete(at.x);
+            None
         }
+    }
 
-        self.unhighlight_rows(at.y);
+    /// Replaces a range of bytes in a binary document. Returns the bytes that were replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - the offset of the first byte to replace
+    /// * `len` - the number of bytes to replace
+    /// * `replacement` - the bytes to replace them with
+    pub fn replace_bytes(&mut self, offset: usize, len: usize, replacement: &[u8]) -> Vec<u8> {
+        let Some(bytes) = &mut self.bytes else {
+            return Vec::new();
+        };
+
+        let offset = offset.min(bytes.len());
+        let end = offset.saturating_add(len).min(bytes.len());
+        let replaced = bytes
+            .splice(offset..end, replacement.iter().copied())
+            .collect();
+
+        // Inserting or removing bytes moves every byte after them into a different row
+        let first_row = offset / BYTES_PER_ROW;
+        if replacement.len() == end - offset {
+            self.rows.remove(&first_row);
+        } else {
+            self.rows.split_off(&first_row);
+        }
+        self.dirty = true;
+        self.counted_matches = None;
+        replaced
+    }
+
+    /// Gets the byte at the given offset in a binary document.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - the offset of the byte
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.bytes.as_ref()?.get(offset).copied()
+    }
+
+    /// Gets whether the document is binary and shown as a hex dump.
+    pub fn is_binary(&self) -> bool {
+        self.bytes.is_some()
+    }
+
+    /// Converts a position within the document into an index into the document's text.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the [Position] to convert; positions past the end of a row are clamped to
+    ///   the end of the row
+    fn char_index(&self, at: &Position) -> usize {
+        match self.line(at.y) {
+            Some(row) => self.text.line_to_char(at.y) + row.char_index(at.x),
+            None => self.text.len_chars(),
+        }
     }
 
     /// Writes the document to file.
     pub fn save(&mut self) -> Result<(), Error> {
-        if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
-            self.file_type = FileType::from(filename);
-            for row in &mut self.rows {
-                file.write_all(row.to_string().as_bytes())?;
-                file.write_all(b"\n")?;
+        if self.is_loading() {
+            return Err(Error::new(
+                std::io::ErrorKind::WouldBlock,
+                "file is still being loaded",
+            ));
+        }
+        let Some(filename) = &self.filename else {
+            return Ok(());
+        };
+
+        if let Some(bytes) = &self.bytes {
+            fs::write(filename, bytes)?;
+            self.hash = Some(hash(bytes));
+        } else {
+            let mut contents = String::with_capacity(self.text.len_bytes());
+            self.file_type = FileType::detect(filename, &line_contents(&self.text, 0));
+            let len = self.len();
+            for index in 0..len {
+                contents.push_str(&line_contents(&self.text, index));
+                if index + 1 < len || self.format.final_newline {
+                    contents.push_str(self.format.line_ending.as_str());
+                }
             }
-            self.dirty = false;
+            let bytes = self.format.encoding.encode(&contents)?;
+            fs::write(filename, &bytes)?;
+            self.hash = Some(hash(&bytes));
         }
+        self.dirty = false;
         Ok(())
     }
 
-    /// Finds the position of the next occurence of a string within the document.
+    /// Finds the position and length of the next match of a pattern within the document.
     ///
     /// # Arguments
     ///
-    /// * `query` - the string to find
+    /// * `pattern` - the [Pattern] to find
     /// * `at` - the [Position] to start finding from
     /// * `direction` - the [SearchDirection] to use
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
-        if at.y >= self.rows.len() {
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, usize)> {
+        if at.y >= self.len() {
             return None;
         }
 
@@ -235,23 +865,23 @@ impl Document {
         };
 
         let end = if direction == SearchDirection::Forward {
-            self.rows.len()
+            self.len()
         } else {
             at.y.saturating_add(1)
         };
 
         for _ in start..end {
-            if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(&query, position.x, direction) {
+            if let Some(row) = self.line(position.y) {
+                if let Some((x, len)) = row.find(pattern, position.x, direction) {
                     position.x = x;
-                    return Some(position);
+                    return Some((position, len));
                 }
                 if direction == SearchDirection::Forward {
                     position.y = position.y.saturating_add(1);
                     position.x = 0;
                 } else {
                     position.y = position.y.saturating_sub(1);
-                    position.x = self.rows[position.y].len();
+                    position.x = self.row_len(position.y).unwrap_or(0);
                 }
             } else {
                 return None;
@@ -260,6 +890,136 @@ impl Document {
         None
     }
 
+    /// Finds the position and length of the next match of a pattern within the document like
+    /// [`Document::find`], but wraps around to the start (or end) of the document if there are no
+    /// more matches in the given direction. Also returns whether the search wrapped around.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [`Pattern`] to find
+    /// * `at` - the [`Position`] to start finding from
+    /// * `direction` - the [`SearchDirection`] to use
+    pub fn find_wrapping(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, usize, bool)> {
+        if let Some((position, len)) = self.find(pattern, at, direction) {
+            return Some((position, len, false));
+        }
+
+        let last = self.len().saturating_sub(1);
+        let from = if direction == SearchDirection::Forward {
+            Position::default()
+        } else {
+            Position {
+                x: self.row_len(last).unwrap_or(0),
+                y: last,
+            }
+        };
+        let (position, len) = self.find(pattern, &from, direction)?;
+        Some((position, len, true))
+    }
+
+    /// Expands a replacement for a match of a pattern that was found by [`Document::find`], see
+    /// [`Pattern::expand`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [`Pattern`] that matched
+    /// * `at` - the [`Position`] of the match
+    /// * `matched` - the text that was matched
+    /// * `replacement` - the replacement to expand
+    pub fn expand(
+        &self,
+        pattern: &Pattern,
+        at: &Position,
+        matched: &str,
+        replacement: &str,
+    ) -> String {
+        self.line(at.y).map_or_else(
+            || replacement.to_string(),
+            |row| row.expand(pattern, at.x, matched, replacement),
+        )
+    }
+
+    /// Counts the matches of a pattern within the document, up to [`MAX_COUNTED_MATCHES`] of
+    /// them. At most [`MAX_COUNTED_ROWS`] rows are searched each time, so counting the matches
+    /// of the same pattern again (e.g. to move to the next match) continues where the last count
+    /// stopped rather than searching the document again, until the document changes. While the
+    /// file is loading, the rows that have been loaded are counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [`Pattern`] to count matches of
+    /// * `at` - the [`Position`] to count the previous matches of
+    pub fn count_matches(&mut self, pattern: &Pattern, at: &Position) -> MatchCount {
+        let mut counted = match self.counted_matches.take() {
+            Some(counted) if counted.pattern == *pattern => counted,
+            _ => CountedMatches::new(pattern.clone()),
+        };
+        self.find_matches(&mut counted);
+        let complete = !counted.capped && counted.rows >= self.len() && !self.is_loading();
+        let count = counted.count(at, complete);
+        self.counted_matches = Some(counted);
+        count
+    }
+
+    /// Finds the matches of a pattern in up to [`MAX_COUNTED_ROWS`] rows after the ones that
+    /// have been searched, stopping after [`MAX_COUNTED_MATCHES`] matches. The rows are read
+    /// from the document's text, as materializing them would be slow for large files.
+    ///
+    /// # Arguments
+    ///
+    /// * `counted` - the [`CountedMatches`] to add the matches to
+    fn find_matches(&self, counted: &mut CountedMatches) {
+        let end = self
+            .len()
+            .min(counted.rows.saturating_add(MAX_COUNTED_ROWS));
+        while !counted.capped && counted.rows < end {
+            let y = counted.rows;
+            let Some(line) = self.contents(y) else {
+                break;
+            };
+            let mut x = 0;
+            let mut offsets = line
+                .grapheme_indices(true)
+                .map(|(offset, _)| offset)
+                .peekable();
+            for found in counted.pattern.find_all(&line) {
+                if counted.positions.len() == MAX_COUNTED_MATCHES {
+                    counted.capped = true;
+                    break;
+                }
+                while offsets.next_if(|&offset| offset < found.start).is_some() {
+                    x += 1;
+                }
+                counted.positions.push(Position { x, y });
+            }
+            counted.rows += 1;
+        }
+    }
+
+    /// Selects every match of a pattern within the document, and returns how many there are.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [`Pattern`] to select matches of
+    pub fn select_all(&mut self, pattern: &Pattern) -> usize {
+        let mut count = 0;
+        for y in 0..self.len() {
+            let Some(matches) = self.line(y).map(|row| row.find_all(pattern)) else {
+                break;
+            };
+            for (x, len) in matches {
+                self.add_selection(Position { x, y }, len);
+                count += 1;
+            }
+        }
+        count
+    }
+
     /// Finds the position of the next word in the document.
     ///
     /// A word is defined as a sequence of alphanumeric characters.
@@ -269,7 +1029,7 @@ impl Document {
     /// * `at` - the position to start looking from
     /// * `direction` - the [SearchDirection] to use
     pub fn find_next_word(&self, at: &Position, direction: SearchDirection) -> Option<Position> {
-        if at.y >= self.rows.len() {
+        if at.y >= self.len() {
             return None;
         }
 
@@ -283,12 +1043,12 @@ impl Document {
         let x_next = if direction == SearchDirection::Forward {
             0
         } else {
-            self.rows[y_next].len()
+            self.row_len(y_next).unwrap_or(0)
         };
 
-        if let Some(x) = self.rows[y].find_next_word(at.x, direction) {
+        if let Some(x) = self.line(y)?.find_next_word(at.x, direction) {
             Some(Position { x, y })
-        } else if y_next < self.rows.len() && (direction == SearchDirection::Forward || y > 0) {
+        } else if y_next < self.len() && (direction == SearchDirection::Forward || y > 0) {
             Some(Position {
                 x: x_next,
                 y: y_next,
@@ -298,36 +1058,59 @@ impl Document {
         }
     }
 
-    /// Computes the highlight of all rows in the document.
+    /// Computes the highlight of all rows in the document, materializing them as needed.
+    /// Documents that were opened lazily are only highlighted from `from` onwards.
+    ///
+    /// Every row keeps the state that the highlighter ended it in (e.g. within a multiline
+    /// comment), so rows whose state at their start is unchanged since they were last
+    /// highlighted are not highlighted again.
     ///
     /// # Arguments
     ///
-    /// * `word` - the word to highlight, if any
+    /// * `pattern` - the search pattern to highlight matches of, if any
+    /// * `from` - the index of the first visible row
     /// * `until` - the index to stop highlighting at
-    pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
-        let mut look_for_multiline_close = None;
+    pub fn highlight(&mut self, pattern: &Option<Pattern>, from: usize, until: Option<usize>) {
         let until = if let Some(until) = until {
-            if until.saturating_add(1) < self.rows.len() {
+            if until.saturating_add(1) < self.len() {
                 until.saturating_add(1)
             } else {
-                self.rows.len()
+                self.len()
             }
         } else {
-            self.rows.len()
+            self.len()
         };
-        for row in &mut self.rows[..until] {
-            row.highlight(
-                self.file_type.highlighting_options(),
-                word,
-                &mut look_for_multiline_close,
-            );
+        let from = if self.lazy { from.min(until) } else { 0 };
+        for index in from..until {
+            self.materialize(index);
+        }
+
+        // Rows before `from` are only known if they have been highlighted before
+        let mut state = from
+            .checked_sub(1)
+            .and_then(|previous| self.rows.get(&previous))
+            .and_then(Row::end_state)
+            .cloned()
+            .unwrap_or_default();
+        // Grammars are too slow for large files, which are highlighted with the options instead
+        let grammar = self.file_type.grammar().filter(|_| !self.lazy);
+        for row in self.rows.range_mut(from..until).map(|(_, row)| row) {
+            state = if let Some(grammar) = grammar {
+                row.highlight_with_grammar(grammar, pattern, &state)
+            } else {
+                row.highlight(self.file_type.highlighting_options(), pattern, &state)
+            };
+            if self.bytes.is_some() {
+                row.highlight_gutter(hex::GUTTER_WIDTH);
+            }
         }
     }
 
-    /// Re-computes all highlighting.
+    /// Marks all highlighting to be re-computed the next time that rows are highlighted.
     pub fn refresh_highlighting(&mut self) {
-        self.unhighlight_rows(0);
-        self.highlight(&None, None);
+        for row in self.rows.values_mut() {
+            row.is_highlighted = false;
+        }
     }
 
     /// Adds a selection in the document.
@@ -337,13 +1120,13 @@ impl Document {
     /// * `at` - the position of the selection within the document
     /// * `len` - the length of the selection
     pub fn add_selection(&mut self, at: Position, len: usize) {
-        self.rows[at.y].add_selection(at.x, len);
+        self.materialize(at.y).add_selection(at.x, len);
         self.selections.insert(at.y);
     }
 
     /// Resets all selections made in the document.
     pub fn reset_selections(&mut self) {
-        self.rows.iter_mut().for_each(|row| row.reset_selections());
+        self.rows.values_mut().for_each(Row::reset_selections);
         self.selections.clear();
     }
 
@@ -351,25 +1134,51 @@ impl Document {
     pub fn update_and_get_selections(&mut self) -> Vec<(Position, String)> {
         self.selections
             .iter()
-            .map(|y| {
-                let row_selections = self.rows[*y].update_and_get_selections();
+            .filter_map(|y| Some((*y, self.rows.get_mut(y)?.update_and_get_selections())))
+            .flat_map(|(y, row_selections)| {
                 row_selections
-                    .iter()
-                    .map(|(x, s)| (Position { x: *x, y: *y }, s.clone()))
-                    .collect::<Vec<(Position, String)>>()
-                // (Position { x, y: *y }, row_selections)
+                    .into_iter()
+                    .map(move |(x, s)| (Position { x, y }, s))
             })
-            .flatten()
             .collect()
     }
 
-    /// Gets a row in the document.
+    /// Gets a row in the document, if it has been materialized (e.g. by [`Document::highlight`]).
     ///
     /// # Arguments
     ///
     /// * `index` - the row's index
     pub fn row(&self, index: usize) -> Option<&Row> {
-        self.rows.get(index)
+        self.rows.get(&index)
+    }
+
+    /// Gets a row in the document, materializing a temporary copy of it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the row's index
+    pub fn line(&self, index: usize) -> Option<Cow<'_, Row>> {
+        if let Some(row) = self.rows.get(&index) {
+            Some(Cow::Borrowed(row))
+        } else {
+            Some(Cow::Owned(Row::from(self.contents(index)?.as_ref())))
+        }
+    }
+
+    /// Gets the length of a row in the document.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the row's index
+    pub fn row_len(&self, index: usize) -> Option<usize> {
+        if let Some(bytes) = &self.bytes {
+            let start = index.saturating_mul(BYTES_PER_ROW);
+            (start < bytes.len()).then(|| (bytes.len() - start).min(BYTES_PER_ROW))
+        } else if let Some(row) = self.rows.get(&index) {
+            Some(row.len())
+        } else {
+            Some(self.contents(index)?.graphemes(true).count())
+        }
     }
 
     /// Gets the character at the specified position in the document.
@@ -379,7 +1188,7 @@ impl Document {
     /// * `pos` - the position of the character to retrieve
     pub fn get_char_in_doc(&self, pos: Position) -> Option<String> {
         let Position { y, x } = pos;
-        if let Some(row) = self.rows.get(y) {
+        if let Some(row) = self.line(y) {
             if x >= row.len() {
                 Some("\n".into())
             } else {
@@ -397,51 +1206,40 @@ impl Document {
     /// * `start` - the start position of the range
     /// * `end` - the end position of the range
     pub fn get_doc_content_as_string(&self, start: Position, end: Position) -> String {
-        let Position {
-            y: start_y,
-            x: start_x,
-        } = start;
-        let Position { y: end_y, x: end_x } = end;
-
-        if start_y == end_y {
-            if let Some(row) = self.rows.get(start_y) {
-                return row
-                    .to_graphemes()
-                    .skip(start_x)
-                    .take(end_x - start_x)
-                    .collect();
-            } else {
-                return "".into();
-            }
-        }
-
-        (start_y..=end_y)
-            .map(|r| {
-                if let Some(row) = self.rows.get(r) {
-                    let row_contents = row.to_graphemes();
-                    if r == start_y {
-                        let s: String = row_contents.skip(start_x).collect();
-                        format!("{}\n", s)
-                    } else if r == end_y {
-                        row_contents.take(end_x).collect()
-                    } else {
-                        format!("{}\n", row_contents.collect::<String>())
-                    }
-                } else {
-                    "\n".into()
-                }
-            })
-            .collect::<String>()
+        let start = self.char_index(&start);
+        let end = self.char_index(&end).max(start);
+        self.text.slice(start..end).to_string()
     }
 
-    /// Gets the number of rows in the document.
+    /// Gets the number of rows in the document. While the document is being loaded, this is
+    /// the number of rows that have been indexed so far.
     pub fn len(&self) -> usize {
-        self.rows.len()
+        match (&self.bytes, &self.loader) {
+            (Some(bytes), _) => hex::len(bytes),
+            (None, Some(loader)) => loader.len(),
+            (None, None) => self.text.len_lines() - 1,
+        }
     }
 
     /// Gets whether the document is entirely empty.
     pub fn is_empty(&self) -> bool {
-        self.rows.is_empty()
+        match (&self.bytes, &self.loader) {
+            (Some(bytes), _) => bytes.is_empty(),
+            (None, Some(loader)) => loader.len() == 0,
+            (None, None) => self.text.len_chars() == 0,
+        }
+    }
+
+    /// Gets whether the document's file is still being loaded, in which case the document
+    /// cannot be edited.
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    /// Gets how much of the document's file has been loaded, as a percentage, if it is still
+    /// being loaded.
+    pub fn loading_progress(&self) -> Option<u64> {
+        self.loader.as_ref().map(Loader::progress)
     }
 
     /// Gets whether the document is dirty.
@@ -453,14 +1251,112 @@ impl Document {
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
+
+    /// Sets the number of spaces per indent, unless it was detected from the document's
+    /// contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `spaces_per_tab` - the number of spaces per indent
+    pub fn set_default_indent(&mut self, spaces_per_tab: usize) {
+        if !self.indent_detected {
+            self.spaces_per_tab = spaces_per_tab;
+        }
+    }
+
+    /// Gets the number of columns between tab stops.
+    pub fn tab_width(&self) -> usize {
+        self.spaces_per_tab
+    }
+
+    /// Gets whether the document is indented with tabs rather than spaces.
+    pub fn indents_with_tabs(&self) -> bool {
+        self.format.indent_with_tabs
+    }
+
+    /// Gets the style of line endings used in the document.
+    pub fn line_ending(&self) -> LineEnding {
+        self.format.line_ending
+    }
+
+    /// Sets the style of line endings that the document is saved with.
+    ///
+    /// # Arguments
+    ///
+    /// * `line_ending` - the [`LineEnding`] to use
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.format.line_ending != line_ending {
+            self.format.line_ending = line_ending;
+            self.dirty = true;
+        }
+    }
+
+    /// Gets the character encoding that the document is saved in.
+    pub fn encoding(&self) -> Encoding {
+        self.format.encoding
+    }
+
+    /// Sets the character encoding that the document is saved in.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - the [`Encoding`] to use
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        if self.format.encoding != encoding {
+            self.format.encoding = encoding;
+            self.dirty = true;
+        }
+    }
+
+    /// Gets the hash of the file's contents as of when it was last opened or saved.
+    pub fn hash(&self) -> Option<u64> {
+        self.hash
+    }
+}
+
+/// Gets the contents of a row in a document's text, without its trailing newline.
+///
+/// # Arguments
+///
+/// * `text` - the document's text
+/// * `index` - the row's index, which must be within the document
+fn line_contents(text: &Rope, index: usize) -> Cow<'_, str> {
+    let line = text.line(index);
+    line.slice(..line.len_chars().saturating_sub(1)).into()
+}
+
+/// Computes a hash of file contents that is stable across runs (64-bit FNV-1a).
+///
+/// # Arguments
+///
+/// * `bytes` - the contents to hash
+fn hash(bytes: &[u8]) -> u64 {
+    extend_hash(HASH_SEED, bytes)
+}
+
+/// Extends a hash of file contents by more bytes, so that files can be hashed as they are read.
+///
+/// # Arguments
+///
+/// * `hash` - the hash of the preceding contents, or [`HASH_SEED`]
+/// * `bytes` - the contents to extend the hash by
+pub(crate) fn extend_hash(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{Document, Position, Row, SearchDirection};
+    use crate::highlighting::State;
+    use crate::loader::SAMPLE_SIZE;
+    use crate::search::{Pattern, SearchOptions};
+    use crate::{Document, Encoding, FileType, LineEnding, Position, Row, SearchDirection};
+    use ropey::Rope;
     use std::{env, fs, path::PathBuf};
 
-    use super::DEFAULT_SPACES_PER_TAB;
+    use super::{line_contents, DEFAULT_SPACES_PER_TAB, MAX_COUNTED_ROWS};
+    use crate::test_util::TempDir;
 
     #[test]
     fn edit() {
@@ -474,7 +1370,7 @@ mod test {
 
         doc.delete(&pos);
         pos = Position { x: 0, y: 0 };
-        assert!(&doc.rows[0].to_string().is_empty());
+        assert!(&doc.line(0).unwrap().to_string().is_empty());
 
         let input = "Hello, World!";
         let split_idx = 7;
@@ -484,20 +1380,20 @@ mod test {
         }
 
         assert_eq!(doc.len(), 1);
-        assert_eq!(&doc.rows[0].to_string(), input);
+        assert_eq!(&doc.line(0).unwrap().to_string(), input);
         assert_eq!(pos.x, input.len());
         assert_eq!(pos.y, 0);
 
         let (a, b) = input.split_at(split_idx);
         assert_eq!(doc.insert(&mut Position { x: split_idx, y: 0 }, '\n'), 0);
         assert_eq!(doc.len(), 2);
-        assert_eq!(&doc.rows[0].to_string(), a);
-        assert_eq!(&doc.rows[1].to_string(), b);
+        assert_eq!(&doc.line(0).unwrap().to_string(), a);
+        assert_eq!(&doc.line(1).unwrap().to_string(), b);
 
         assert_eq!(doc.insert(&mut Position { x: b.len(), y: 1 }, '\n'), 0);
         assert_eq!(doc.len(), 3);
-        assert_eq!(&doc.rows[1].to_string(), b);
-        assert_eq!(&doc.rows[2].to_string(), "");
+        assert_eq!(&doc.line(1).unwrap().to_string(), b);
+        assert_eq!(&doc.line(2).unwrap().to_string(), "");
     }
 
     #[test]
@@ -515,27 +1411,108 @@ mod test {
 
         let query = "John Doe";
         let text_matches = text.matches(query).count();
+        let pattern = Pattern::new(query, SearchOptions::default()).unwrap();
 
         let mut doc_matches = 0;
         let mut position = Position { x: 0, y: 0 };
-        while let Some(next_position) = doc.find(query, &position, SearchDirection::Forward) {
+        while let Some((next_position, len)) =
+            doc.find(&pattern, &position, SearchDirection::Forward)
+        {
             if position.x == next_position.x && position.y == next_position.y {
                 break;
             }
 
-            doc.add_selection(next_position, query.len());
+            assert_eq!(len, query.len());
+            doc.add_selection(next_position, len);
             position.x = next_position.x + 1;
             position.y = next_position.y;
             doc_matches += 1;
         }
 
         assert_eq!(text_matches, doc_matches);
+        let count = doc.count_matches(&pattern, &Position { x: 0, y: 0 });
+        assert_eq!((count.before, count.total), (Some(0), text_matches));
+        assert_eq!(doc.select_all(&pattern), text_matches);
+
+        // Searching past the last match wraps around to the first one
+        let first = doc.find(&pattern, &Position::default(), SearchDirection::Forward);
+        let (wrapped, _, did_wrap) = doc
+            .find_wrapping(&pattern, &position, SearchDirection::Forward)
+            .unwrap();
+        assert_eq!(Some(wrapped), first.map(|(position, _)| position));
+        assert!(did_wrap);
+    }
+
+    #[test]
+    fn expand_in_context() {
+        let doc = Document::from_text("x\néabc\n");
+        let options = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let pattern = Pattern::new(r"\Bb(c)", options).unwrap();
+        let (at, len) = doc
+            .find(&pattern, &Position::default(), SearchDirection::Forward)
+            .unwrap();
+        assert_eq!((at, len), (Position { x: 2, y: 1 }, 2));
+        let end = Position {
+            x: at.x + len,
+            y: at.y,
+        };
+        let matched = doc.get_doc_content_as_string(at, end);
+        assert_eq!(doc.expand(&pattern, &at, &matched, "<$1>"), "<c>");
+    }
+
+    #[test]
+    fn count_matches() {
+        let mut doc = Document::from_text(&"ab\n".repeat(1500));
+        let pattern = Pattern::new("a", SearchOptions::default()).unwrap();
+
+        // Counting stops after the first thousand matches
+        let count = doc.count_matches(&pattern, &Position { x: 0, y: 5 });
+        assert_eq!(count.before, Some(5));
+        assert_eq!(count.total, 1000);
+        assert!(count.capped);
+        assert_eq!(
+            doc.count_matches(&pattern, &Position { x: 0, y: 1200 })
+                .before,
+            None
+        );
+
+        // Matches that were counted are forgotten once the document changes
+        doc.delete(&Position { x: 0, y: 0 });
+        assert_eq!(
+            doc.count_matches(&pattern, &Position { x: 0, y: 5 }).before,
+            Some(4)
+        );
+
+        // Only so many rows are searched at a time, and counting again continues after them
+        let mut doc = Document::from_text(&format!("{}éa a\n", "\n".repeat(MAX_COUNTED_ROWS)));
+        let last = Position {
+            x: 0,
+            y: MAX_COUNTED_ROWS,
+        };
+        let count = doc.count_matches(&pattern, &last);
+        assert_eq!((count.before, count.total, count.capped), (None, 0, true));
+        let count = doc.count_matches(&pattern, &last);
+        assert_eq!(
+            (count.before, count.total, count.capped),
+            (Some(0), 2, false)
+        );
+        let second = Position { x: 2, ..last };
+        assert_eq!(doc.count_matches(&pattern, &second).before, Some(1));
+
+        let pattern = Pattern::new("b", SearchOptions::default()).unwrap();
+        let mut doc = Document::from_text("b b\n");
+        assert_eq!(doc.count_matches(&pattern, &Position::default()).total, 2);
+        doc.insert(&mut Position::default(), 'b');
+        assert_eq!(doc.count_matches(&pattern, &Position::default()).total, 3);
     }
 
     #[test]
     fn find_next_word() {
         let mut document = Document::default();
-        document.rows = vec![Row::from("Foo Bar"), Row::from("Hello, World!")];
+        document.text = Rope::from("Foo Bar\nHello, World!\n");
 
         let mut position = Position { x: 0, y: 0 };
         let mut next_position_opt = document.find_next_word(&position, SearchDirection::Backward);
@@ -559,16 +1536,12 @@ mod test {
     #[test]
     fn indent() {
         let mut document = Document::default();
-        document.rows = vec![
-            Row::from("fn main() {"),
-            Row::from("    println!(\"Hello, World!\")"),
-            Row::from("}"),
-        ];
+        document.text = Rope::from("fn main() {\n    println!(\"Hello, World!\")\n}\n");
 
         let mut position = Position { x: 0, y: 0 };
-        assert_eq!(document.rows[0].get_leading_spaces(), None);
+        assert_eq!(document.line(0).unwrap().get_leading_spaces(), None);
         assert_eq!(document.insert(&mut position, '\n'), 0);
-        assert_eq!(document.rows[1].get_leading_spaces(), None);
+        assert_eq!(document.line(1).unwrap().get_leading_spaces(), None);
 
         position = Position { x: 0, y: 1 };
         assert_eq!(
@@ -576,16 +1549,273 @@ mod test {
             DEFAULT_SPACES_PER_TAB - 1
         );
         assert_eq!(
-            document.rows[1].get_leading_spaces(),
+            document.line(1).unwrap().get_leading_spaces(),
             Some(DEFAULT_SPACES_PER_TAB)
         );
 
         position = Position { x: 7, y: 2 };
         assert_eq!(document.insert(&mut position, '\n'), 4);
-        assert_eq!(document.rows[3].get_leading_spaces(), Some(4));
+        assert_eq!(document.line(3).unwrap().get_leading_spaces(), Some(4));
 
         position = Position { x: 1, y: 4 };
         assert_eq!(document.insert(&mut position, '\n'), 0);
-        assert_eq!(document.rows[5].get_leading_spaces(), None);
+        assert_eq!(document.line(5).unwrap().get_leading_spaces(), None);
+
+        // Splitting within the indent only keeps the whitespace before the split
+        position = Position { x: 2, y: 3 };
+        assert_eq!(document.insert(&mut position, '\n'), 2);
+        assert_eq!(document.line(4).unwrap().get_leading_spaces(), Some(4));
+
+        position = Position { x: 0, y: 4 };
+        document.insert_verbatim(&mut position, '\n');
+        assert_eq!(document.line(4).unwrap().get_leading_spaces(), None);
+        assert_eq!(document.line(5).unwrap().get_leading_spaces(), Some(4));
+    }
+
+    #[test]
+    fn verbatim_tab() {
+        let mut document = Document::from_text("    a\n\tb\n");
+        assert!(!document.indents_with_tabs());
+
+        // Deleting a literal tab and undoing it restores the tab, not spaces
+        let mut position = Position { x: 0, y: 1 };
+        document.delete(&position);
+        document.insert_verbatim(&mut position, '\t');
+        assert_eq!(document.contents(1).unwrap(), "\tb");
+        assert_eq!(document.row_len(1), Some(2));
+
+        assert_eq!(position, Position { x: 1, y: 1 });
+
+        document.insert(&mut Position { x: 0, y: 1 }, '\t');
+        assert_eq!(
+            document.contents(1).unwrap(),
+            format!("{}\tb", " ".repeat(DEFAULT_SPACES_PER_TAB))
+        );
+    }
+
+    #[test]
+    fn verbatim_combining() {
+        let mut document = Document::from_text("ab\n");
+        let mut position = Position { x: 1, y: 0 };
+        for c in "e\u{301}".chars() {
+            document.insert_verbatim(&mut position, c);
+        }
+        assert_eq!(document.contents(0).unwrap(), "ae\u{301}b");
+        assert_eq!(document.row_len(0), Some(3));
+        assert_eq!(position, Position { x: 2, y: 0 });
+
+        document.insert_verbatim(&mut position, '\n');
+        assert_eq!(position, Position { x: 0, y: 1 });
+        assert_eq!(document.contents(1).unwrap(), "b");
+    }
+
+    #[test]
+    fn rows_stay_in_sync() {
+        let mut doc = Document::default();
+        doc.text = Rope::from("foo bar\n    baz\nqux\n");
+        assert_eq!(doc.len(), 3);
+
+        // Materialize only the first two rows, so that edits have to handle both cases
+        doc.highlight(&None, 0, Some(1));
+        assert!(doc.row(1).is_some() && doc.row(2).is_none());
+
+        doc.delete(&Position { x: 7, y: 1 });
+        doc.insert(&mut Position { x: 3, y: 0 }, '\n');
+        doc.insert(&mut Position { x: 5, y: 2 }, '!');
+        doc.delete(&Position { x: 0, y: 0 });
+
+        let expected = ["oo", " bar", "    b!azqux"];
+        assert_eq!(doc.len(), expected.len());
+        assert_eq!(doc.text.to_string(), expected.join("\n") + "\n");
+        for (y, contents) in expected.iter().enumerate() {
+            assert_eq!(line_contents(&doc.text, y), *contents);
+            if let Some(row) = doc.row(y) {
+                assert_eq!(&row.to_string(), contents);
+            }
+        }
+        assert_eq!(
+            doc.get_doc_content_as_string(Position { x: 1, y: 1 }, Position { x: 2, y: 2 }),
+            "bar\n  "
+        );
+    }
+
+    #[test]
+    fn highlight_incrementally() {
+        let mut doc = Document::default();
+        doc.file_type = FileType::from("a.rs");
+        doc.text = Rope::from("a\nb\nc\nd\n");
+        doc.highlight(&None, 0, None);
+        let in_comment = |doc: &Document, y: usize| {
+            doc.row(y)
+                .and_then(Row::end_state)
+                .is_some_and(|state| *state != State::Normal)
+        };
+
+        // Rows after an edit that leaves the state at its end unchanged stay highlighted
+        doc.insert(&mut Position { x: 1, y: 1 }, '!');
+        assert!(doc.row(2).is_some_and(|row| row.is_highlighted));
+
+        doc.insert(&mut Position { x: 0, y: 1 }, '/');
+        doc.insert(&mut Position { x: 1, y: 1 }, '*');
+        doc.highlight(&None, 0, None);
+        assert!(!in_comment(&doc, 0) && in_comment(&doc, 1) && in_comment(&doc, 3));
+
+        doc.insert(&mut Position { x: 1, y: 2 }, '*');
+        doc.insert(&mut Position { x: 2, y: 2 }, '/');
+        doc.highlight(&None, 0, None);
+        assert!(in_comment(&doc, 1) && !in_comment(&doc, 2) && !in_comment(&doc, 3));
+    }
+
+    #[test]
+    fn open_lazy() {
+        let dir = TempDir::new("lazy");
+        let path = dir.join("lazy.txt");
+        let contents = "fn main() {\r\n\tprintln!();\n}";
+        fs::write(&path, contents).unwrap();
+        let filename = path.to_str().unwrap();
+
+        let mut doc = Document::open_lazy(filename).unwrap();
+        assert!(doc.is_loading());
+        doc.insert(&mut Position { x: 0, y: 0 }, 'x');
+        assert!(!doc.is_dirty());
+        assert!(doc.save().is_err());
+
+        let pattern = Pattern::new("(", SearchOptions::default()).unwrap();
+        while doc.is_loading() {
+            // Matches are counted in the rows that have been loaded, and kept as more are loaded
+            assert!(doc.count_matches(&pattern, &Position::default()).capped);
+            doc.poll_loader().unwrap();
+            // Rows can be read from the file before it has been loaded entirely
+            if let Some(row) = doc.line(1) {
+                assert_eq!(row.to_string(), "\tprintln!();");
+            }
+        }
+
+        let eager = Document::open(filename).unwrap();
+        assert_eq!(doc.text, eager.text);
+        assert_eq!(doc.len(), 3);
+        assert_eq!(doc.hash(), eager.hash());
+        assert_eq!(doc.line(0).unwrap().to_string(), "fn main() {");
+        let count = doc.count_matches(&pattern, &Position::default());
+        assert_eq!((count.total, count.capped), (2, false));
+
+        doc.insert(&mut Position { x: 1, y: 2 }, '!');
+        assert_eq!(line_contents(&doc.text, 2), "}!");
+    }
+
+    #[test]
+    fn open_lazy_invalid_utf8() {
+        let dir = TempDir::new("lazy-invalid");
+        let path = dir.join("lazy.txt");
+        // The invalid byte comes after the sample that the encoding is detected from
+        let mut contents = "abc\n"
+            .repeat(usize::try_from(SAMPLE_SIZE).unwrap())
+            .into_bytes();
+        contents.extend_from_slice(b"caf\xe9\n");
+        fs::write(&path, &contents).unwrap();
+
+        let mut doc = Document::open_lazy(path.to_str().unwrap()).unwrap();
+        assert_eq!(doc.encoding(), Encoding::Utf8);
+        while doc.is_loading() {
+            doc.poll_loader().unwrap();
+        }
+
+        assert_eq!(doc.encoding(), Encoding::Windows1252);
+        assert_eq!(doc.line(doc.len() - 1).unwrap().to_string(), "café");
+        doc.insert(&mut Position { x: 0, y: 0 }, 'x');
+        assert!(doc.is_dirty());
+    }
+
+    #[test]
+    fn preserve_formatting() {
+        let dir = TempDir::new("format");
+        let path = dir.join("format.txt");
+        let contents = "all:\r\n\tcc main.c\r\n\r\nclean:\r\n\trm a.out";
+        fs::write(&path, contents).unwrap();
+
+        let mut doc = Document::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert!(doc.indents_with_tabs());
+        assert_eq!(doc.line(1).unwrap().to_string(), "\tcc main.c");
+        doc.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        // New rows are indented the same way as the rest of the document
+        doc.insert(&mut Position { x: 10, y: 1 }, '\n');
+        doc.insert(&mut Position { x: 0, y: 3 }, '\t');
+        assert_eq!(doc.line(2).unwrap().to_string(), "\t");
+        assert_eq!(doc.line(3).unwrap().to_string(), "\t");
+
+        let changes = doc.reindent(false);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(doc.line(1).unwrap().to_string(), "    cc main.c");
+        doc.set_line_ending(LineEnding::Lf);
+        doc.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "all:\n    cc main.c\n\t\n\t\nclean:\n    rm a.out"
+        );
+
+        doc.restore_indentation(&changes, true);
+        assert!(doc.indents_with_tabs());
+        assert_eq!(doc.line(5).unwrap().to_string(), "\trm a.out");
+    }
+
+    #[test]
+    fn encodings() {
+        let dir = TempDir::new("encoding");
+        let path = dir.join("encoding.txt");
+        let filename = path.to_str().unwrap();
+
+        // "café" in Latin-1, which is not valid UTF-8
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut doc = Document::open(filename).unwrap();
+        assert_eq!(doc.encoding(), Encoding::Windows1252);
+        assert_eq!(doc.line(0).unwrap().to_string(), "caf\u{e9}");
+        doc.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"caf\xe9\n");
+
+        doc.set_encoding(Encoding::Utf16Be);
+        doc.save().unwrap();
+        assert_eq!(
+            fs::read(&path).unwrap(),
+            b"\xfe\xff\0c\0a\0f\0\xe9\0\n".to_vec()
+        );
+        assert!(Document::open_as(filename, Some(Encoding::Utf8)).is_err());
+
+        doc = Document::open(filename).unwrap();
+        assert_eq!(doc.encoding(), Encoding::Utf16Be);
+        assert_eq!(doc.line(0).unwrap().to_string(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn binary() {
+        let dir = TempDir::new("binary");
+        let path = dir.join("binary.bin");
+        let filename = path.to_str().unwrap();
+        let bytes: Vec<u8> = (0..=255).collect();
+        fs::write(&path, &bytes).unwrap();
+
+        let mut doc = Document::open(filename).unwrap();
+        assert!(doc.is_binary());
+        assert_eq!(doc.len(), 16);
+        assert_eq!(doc.row_len(15), Some(16));
+        assert!(doc
+            .line(4)
+            .unwrap()
+            .to_string()
+            .starts_with("00000040  40 41 42"));
+
+        // Text edits are ignored, while byte edits shift the following rows
+        doc.insert(&mut Position { x: 0, y: 0 }, 'a');
+        assert!(!doc.is_dirty());
+        assert_eq!(doc.replace_bytes(0x40, 1, &[0xff, 0xfe]), vec![0x40]);
+        assert_eq!(doc.byte(0x41), Some(0xfe));
+        assert_eq!(doc.len(), 17);
+        assert_eq!(doc.row_len(16), Some(1));
+        doc.replace_bytes(0x40, 2, &[0x40]);
+
+        doc.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
     }
 }
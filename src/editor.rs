@@ -1,65 +1,74 @@
 use std::cell::RefCell;
 use std::env;
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
 
-use bounded_vec_deque::BoundedVecDeque;
+use serde::{Deserialize, Serialize};
 use shunting::{MathContext, ShuntingParser};
 use signal_hook::consts::SIGWINCH;
 use termion::event::{Event, Key, MouseEvent};
-use unicode_segmentation::UnicodeSegmentation;
 
+use crate::buffer::{Buffer, Output};
+use crate::commands::bytes::BytesCommand;
 use crate::commands::copy::CopyCommand;
 use crate::commands::delete::DeleteCommand;
 use crate::commands::group::{CommandGroup, CommandType};
+use crate::commands::history::UndoTree;
+use crate::commands::indent::IndentCommand;
 use crate::commands::insert::InsertCommand;
 use crate::commands::paste::PasteCommand;
 use crate::commands::{BoxedCommand, Command};
+use crate::config::{Config, EditingMode};
+use crate::document::IndentChange;
+use crate::filetype;
+use crate::grammar;
+use crate::hex::{self, BYTES_PER_ROW};
+use crate::highlighting;
+use crate::keymap::{format_keys, Action, Context, Keymap, Lookup};
+use crate::kill_ring::KillRing;
+use crate::minibuffer::{FileCompleter, ListCompleter, Minibuffer, Response};
+use crate::pane::{Layout, Pane, Rect, SplitDirection, View};
+use crate::project_search::{self, Match};
+use crate::prompt::{HistoryKind, PromptHistory};
+use crate::search::{CaseSensitivity, Pattern, SearchOptions};
+use crate::theme::{self, Theme};
+use crate::vim::{self, Insertion, Mode, Motion, Operation, Operator, Parse, Range, Vim};
 use crate::Document;
+use crate::Encoding;
+use crate::LineEnding;
 use crate::Row;
 use crate::Terminal;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-const QUIT_TIMES: u8 = 2;
-const HISTORY_LIMIT: usize = 10;
-
-// Key mappings for navigation
-const KEY_POS_UP: Key = Key::Up;
-const KEY_POS_DOWN: Key = Key::Down;
-const KEY_POS_LEFT: Key = Key::Left;
-const KEY_POS_RIGHT: Key = Key::Right;
-const KEY_WORD_LEFT: Key = Key::Alt('q');
-const KEY_WORD_RIGHT: Key = Key::Alt('w');
-const KEY_LINE_LEFT: Key = Key::Alt('b');
-const KEY_LINE_RIGHT: Key = Key::Alt('f');
-const KEY_PAGE_UP: Key = Key::Alt('t');
-const KEY_PAGE_DOWN: Key = Key::Alt('g');
-const KEY_DOC_UP: Key = Key::Home;
-const KEY_DOC_DOWN: Key = Key::End;
-
-// Key mappings for control
-const KEY_QUIT: Key = Key::Ctrl('q');
-const KEY_SAVE: Key = Key::Ctrl('s');
-const KEY_SEARCH: Key = Key::Ctrl('l');
-const KEY_SELECT_FORWARD: Key = Key::Ctrl('f');
-const KEY_SELECT_BACKWARD: Key = Key::Ctrl('b');
-const KEY_DELETE_SELECTIONS: Key = Key::Ctrl('d');
-const KEY_REPLACE_SELECTIONS: Key = Key::Ctrl('r');
-const KEY_START_SELECT: Key = Key::Ctrl('t');
-const KEY_END_SELECT: Key = Key::Ctrl('y');
-const KEY_COPY: Key = Key::Ctrl('c');
-const KEY_CUT: Key = Key::Ctrl('x');
-const KEY_PASTE: Key = Key::Ctrl('v');
-const KEY_UNDO: Key = Key::Ctrl('u');
+
+/// How much (in percent of its split) a pane grows or shrinks by at a time
+const RESIZE_STEP: isize = 5;
 
 fn die(e: &std::io::Error) {
     Terminal::clear_screen();
     panic!("{}", e);
 }
 
+/// Toggles a search option if the action is bound to one.
+///
+/// # Arguments
+///
+/// * `options` - the [`SearchOptions`] to change
+/// * `action` - the action performed in the search prompt, if any
+fn toggle_search_option(options: &mut SearchOptions, action: Option<Action>) {
+    match action {
+        Some(Action::ToggleRegex) => options.regex = !options.regex,
+        Some(Action::ToggleCase) => options.toggle_case(CaseSensitivity::Insensitive),
+        Some(Action::ToggleSmartCase) => options.toggle_case(CaseSensitivity::Smart),
+        Some(Action::ToggleWholeWord) => options.whole_word = !options.whole_word,
+        _ => (),
+    }
+}
+
 /// The direction in which a search query should be handled.
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
@@ -68,7 +77,7 @@ pub enum SearchDirection {
 }
 
 /// A position represented by (x, y) coordinates.
-#[derive(Default, Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -107,6 +116,7 @@ struct StatusMessage {
     time: Instant,
 }
 
+#[derive(Clone)]
 pub struct Selection {
     start: Position,
     end: Position,
@@ -144,54 +154,184 @@ pub struct Editor {
     status_message: StatusMessage,
     /// How many more times the quit command needs to be inputted before the application exits
     quit_times: u8,
-    /// The word to be highlighted, if any
-    highlighted_word: Option<String>,
+    /// The search pattern whose matches are highlighted, if any
+    highlighted_pattern: Option<Pattern>,
+    /// Information shown after the input of a prompt, e.g. the options of a search
+    prompt_info: String,
+    /// The column of the cursor in the message bar while a prompt is shown
+    prompt_cursor: Option<usize>,
+    /// Text that was entered in prompts before, so that it can be recalled
+    prompt_history: PromptHistory,
     /// Current selection, if any
     pub selection: Option<Selection>,
     /// Clipboard contents, if any
     pub clipboard: Option<String>,
+    /// Text that was cut or copied before, most recently first
+    kill_ring: KillRing,
+    /// Where the text that was yanked last was inserted, and the text itself
+    last_yank: Option<(Position, String)>,
     /// History of commands
-    command_history: BoundedVecDeque<CommandGroup>,
+    command_history: UndoTree,
+    /// Whether the next hex digit typed in a binary document sets the low (rather than high)
+    /// nibble of the byte under the cursor
+    low_nibble: bool,
+    /// The open buffers, where the active buffer only holds a placeholder while its state is
+    /// moved into the editor
+    buffers: Vec<Buffer>,
+    /// The index of the active buffer
+    current_buffer: usize,
+    /// The panes, where the pane that has focus only holds a placeholder view while its view is
+    /// moved into the editor
+    panes: Vec<Pane>,
+    /// The arrangement of the panes on the screen
+    layout: Layout,
+    /// The index of the pane that has focus
+    current_pane: usize,
+    /// Lines that are drawn over the top of the document, e.g. the buffer list
+    overlay: Option<Vec<String>>,
+    /// The user's configuration
+    config: Config,
+    /// The colors that the editor is drawn in
+    theme: Theme,
+    /// The actions that keys are bound to
+    keymap: Keymap,
+    /// The keys of a chord that have been pressed so far
+    pending_keys: Vec<Key>,
+    /// The action that was performed last, e.g. to append consecutive kills to each other
+    last_action: Option<Action>,
+    /// The state of vim-style modal editing, if it is enabled
+    vim: Option<Vim>,
     /// Flag for the SIGWINCH signal that is set when the terminal window is resized
     _sigwinch_flag: Arc<AtomicBool>,
 }
 
 impl Editor {
     /// Constructs the default editor.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the terminal cannot be initialized
     pub fn default() -> Self {
         let args: Vec<String> = env::args().collect();
-        let mut initial_status =
-            String::from("HELP: Ctrl-L = look for | Ctrl-S = save | Ctrl-Q = quit");
+        // Every problem found while starting up is reported, as none of them stops the editor
+        let mut errors = Vec::new();
 
-        let document = if let Some(filename) = args.get(1) {
-            if let Ok(doc) = Document::open(filename) {
-                doc
-            } else {
-                initial_status = format!("ERR: Could not open file {}", filename);
-                Document::default()
+        let config = Config::load().unwrap_or_else(|error| {
+            errors.push(format!("Could not load config: {error}"));
+            Config::default()
+        });
+        let keymap = Keymap::new(config.mode, &config.keys).unwrap_or_else(|error| {
+            errors.push(format!("Could not load config: {error}"));
+            Keymap::preset(config.mode)
+        });
+        for (kind, load_errors) in [
+            ("syntax definition", filetype::load_errors()),
+            ("grammar", grammar::load_errors()),
+            ("theme", theme::load_errors()),
+        ] {
+            errors.extend(
+                load_errors
+                    .iter()
+                    .map(|error| format!("Could not load {kind}: {error}")),
+            );
+        }
+        let theme = match &config.theme {
+            Some(name) => theme::find(name).unwrap_or_else(|| {
+                errors.push(format!("Unknown theme: {name}"));
+                theme::default_theme()
+            }),
+            None => theme::default_theme(),
+        };
+
+        let mut buffers = Vec::new();
+        for filename in args.iter().skip(1) {
+            match Document::open(filename) {
+                Ok(document) => buffers.push(Self::new_buffer(&config, document)),
+                Err(error) => errors.push(format!("Could not open file {filename}: {error}")),
             }
+        }
+        if buffers.is_empty() {
+            buffers.push(Self::new_buffer(&config, Document::default()));
+        }
+        let initial_status = if errors.is_empty() {
+            format!(
+                "HELP: {} = look for | {} = save | {} = quit | {} = key bindings",
+                keymap.key_hint(Action::Search),
+                keymap.key_hint(Action::Save),
+                keymap.key_hint(Action::Quit),
+                keymap.key_hint(Action::ShowBindings)
+            )
+        } else if errors.len() == 1 {
+            format!("ERR: {}", errors[0])
         } else {
-            Document::default()
+            format!("ERR: {} problems: {}", errors.len(), errors.join("; "))
         };
 
         let flag = Arc::new(AtomicBool::new(false));
         let _ = signal_hook::flag::register(SIGWINCH, Arc::clone(&flag)).unwrap();
 
-        Editor {
+        let mut editor = Editor {
             should_quit: false,
             terminal: Terminal::default().expect("Failed to initialize terminal"),
-            document,
+            document: Document::default(),
             offset: Position::default(),
             cursor_position: Position::default(),
             max_position: None,
             status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIMES,
-            highlighted_word: None,
+            quit_times: config.quit_times,
+            highlighted_pattern: None,
+            prompt_info: String::new(),
+            prompt_cursor: None,
+            prompt_history: PromptHistory::load(),
             selection: None,
             clipboard: None,
-            command_history: BoundedVecDeque::new(HISTORY_LIMIT),
+            kill_ring: KillRing::default(),
+            last_yank: None,
+            command_history: UndoTree::default(),
+            low_nibble: false,
+            buffers,
+            current_buffer: 0,
+            panes: vec![Pane::default()],
+            layout: Layout::Pane(0),
+            current_pane: 0,
+            overlay: None,
+            vim: (config.mode == EditingMode::Vim).then(Vim::default),
+            config,
+            theme,
+            keymap,
+            pending_keys: Vec::new(),
+            last_action: None,
             _sigwinch_flag: flag,
+        };
+        editor.swap_document(0);
+        editor
+    }
+
+    /// Constructs a buffer for a document, loading its command history.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - the user's configuration
+    /// * `document` - the [Document] to hold
+    fn new_buffer(config: &Config, mut document: Document) -> Buffer {
+        document.set_default_indent(config.spaces_per_tab);
+        let command_history = Self::load_history(&document, config.history_limit);
+        Buffer::new(document, command_history)
+    }
+
+    /// Loads the persisted command history of a document, if there is any.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - the [Document] whose history to load
+    /// * `limit` - how many recent changes to keep in the history
+    fn load_history(document: &Document, limit: usize) -> UndoTree {
+        if let (Some(filename), Some(hash)) = (&document.filename, document.hash()) {
+            UndoTree::load(filename, hash, limit)
+        } else {
+            None
         }
+        .unwrap_or_else(|| UndoTree::new(limit))
     }
 
     /// Runs the editor.
@@ -225,22 +365,40 @@ impl Editor {
             Terminal::clear_screen();
             println!("Goodbye.\r");
         } else {
-            self.document.highlight(
-                &self.highlighted_word,
-                Some(
+            for (index, area) in self.layout.areas(self.screen_area()).0 {
+                let offset = if index == self.current_pane {
                     self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
-            );
+                } else {
+                    self.panes[index].view.offset
+                };
+                let until = Some(offset.y.saturating_add(area.height));
+                let buffer = self.panes[index].buffer;
+                if buffer == self.current_buffer {
+                    self.document
+                        .highlight(&self.highlighted_pattern, offset.y, until);
+                } else {
+                    self.buffers[buffer]
+                        .document
+                        .highlight(&None, offset.y, until);
+                }
+            }
 
             self.draw_rows();
             self.draw_status_bar();
             self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
-            });
+            if let Some(x) = self.prompt_cursor {
+                let size = self.terminal.size();
+                Terminal::cursor_position(&Position {
+                    x: x.min((size.width as usize).saturating_sub(1)),
+                    y: size.height as usize + 1,
+                });
+            } else {
+                let area = self.viewport();
+                Terminal::cursor_position(&Position {
+                    x: area.x + self.cursor_column().saturating_sub(self.offset.x),
+                    y: area.y + self.cursor_position.y.saturating_sub(self.offset.y),
+                });
+            }
         }
         Terminal::cursor_show();
         Terminal::flush()
@@ -261,18 +419,42 @@ impl Editor {
             filename.truncate(20);
         }
 
-        let mut status = format!(
-            "{} - {} lines{}",
-            filename,
-            self.document.len(),
-            modified_indicator
-        );
+        // The line count is provisional until the document has been loaded
+        let (line_count, loading_indicator) = match self.document.loading_progress() {
+            Some(progress) => (
+                format!("{}+", self.document.len()),
+                format!(" (loading {progress}%)"),
+            ),
+            None => (self.document.len().to_string(), String::new()),
+        };
+
+        let mut status =
+            format!("{filename} - {line_count} lines{modified_indicator}{loading_indicator}");
+        if let Some(vim) = &self.vim {
+            status = format!("{} | {status}", vim.mode.name());
+        }
 
+        let format = if self.document.is_binary() {
+            "hex".to_string()
+        } else {
+            let indent = if self.document.indents_with_tabs() {
+                "tabs"
+            } else {
+                "spaces"
+            };
+            format!(
+                "{} | {} | {}",
+                self.document.encoding().name(),
+                self.document.line_ending().name(),
+                indent
+            )
+        };
         let line_indicator = format!(
-            "{} | {}/{}",
+            "{} | {} | {}/{}",
             self.document.file_type(),
+            format,
             self.cursor_position.y.saturating_add(1),
-            self.document.len()
+            line_count
         );
 
         let len = status.len() + line_indicator.len();
@@ -280,71 +462,133 @@ impl Editor {
         status = format!("{}{}", status, line_indicator);
         status.truncate(width);
 
-        Terminal::set_bg_color();
-        Terminal::set_fg_color();
+        Terminal::set_style(&self.theme.status_bar_escape());
         println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
+        Terminal::reset_style();
     }
 
     /// Draws the message bar at the bottom of the editor.
     fn draw_message_bar(&self) {
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if Instant::now() - message.time < Duration::from_secs(self.config.message_timeout) {
             let mut text = message.text.clone();
             text.truncate(self.terminal.size().width as usize);
             print!("{}", text);
         }
     }
 
-    /// Draws a given row on the terminal screen.
+    /// Draws a given row on the terminal screen, at the current position of the cursor.
     ///
     /// # Arguments
     ///
     /// * `row` - The row to be drawn
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    /// * `start` - the first column of the row to draw
+    /// * `width` - the number of columns to draw
+    /// * `tab_width` - the number of columns that a tab takes up
+    pub fn draw_row(&self, row: &Row, start: usize, width: usize, tab_width: usize) {
+        let end = start.saturating_add(width);
+        let row = row.render(start, end, tab_width, &self.theme);
+        print!("{}", row);
     }
 
-    /// Draws the rows onto the terminal screen.
+    /// Draws the panes and the lines that separate them onto the terminal screen.
     fn draw_rows(&self) {
-        let height = self.terminal.size().height;
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+        let (panes, separators) = self.layout.areas(self.screen_area());
+        for (index, area) in panes {
+            if index == self.current_pane {
+                self.draw_pane(&self.document, self.offset, area);
             } else {
-                println!("~\r");
+                let pane = &self.panes[index];
+                let document = if pane.buffer == self.current_buffer {
+                    &self.document
+                } else {
+                    &self.buffers[pane.buffer].document
+                };
+                self.draw_pane(document, pane.view.offset, area);
             }
         }
+
+        for (direction, area) in separators {
+            let line = match direction {
+                SplitDirection::Horizontal => "\u{2500}".repeat(area.width),
+                SplitDirection::Vertical => "\u{2502}".to_string(),
+            };
+            for y in area.y..area.y + area.height {
+                Terminal::cursor_position(&Position { x: area.x, y });
+                print!("{line}");
+            }
+        }
+
+        let width = self.terminal.size().width as usize;
+        let height = self.terminal.size().height as usize;
+        let overlay = self.overlay.as_deref().unwrap_or_default();
+        for (y, line) in overlay.iter().take(height).enumerate() {
+            let mut line = format!("{line:width$}");
+            line.truncate(width);
+            Terminal::cursor_position(&Position { x: 0, y });
+            Terminal::set_style(&self.theme.status_bar_escape());
+            print!("{line}");
+            Terminal::reset_style();
+        }
+        Terminal::cursor_position(&Position { x: 0, y: height });
     }
 
-    /// Draws a welcome message in the middle of the editor.
-    fn draw_welcome_message(&self) {
+    /// Draws the rows of a document that are visible in a pane.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - the [Document] shown in the pane
+    /// * `offset` - the offset of the visible page
+    /// * `area` - the area of the screen that the pane takes up
+    fn draw_pane(&self, document: &Document, offset: Position, area: Rect) {
+        let blank = " ".repeat(area.width);
+        for pane_row in 0..area.height {
+            let position = Position {
+                x: area.x,
+                y: area.y + pane_row,
+            };
+            Terminal::cursor_position(&position);
+            Terminal::set_style(&self.theme.escape(highlighting::Type::None));
+            print!("{blank}");
+            Terminal::cursor_position(&position);
+
+            if let Some(row) = document.row(offset.y.saturating_add(pane_row)) {
+                self.draw_row(row, offset.x, area.width, document.tab_width());
+            } else if document.is_empty() && pane_row == area.height / 3 {
+                Self::draw_welcome_message(area.width);
+            } else if area.width > 0 {
+                Terminal::set_style(&self.theme.escape(highlighting::Type::Gutter));
+                print!("~");
+            }
+            Terminal::reset_style();
+        }
+    }
+
+    /// Draws a welcome message in the middle of a pane.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - the width of the pane
+    fn draw_welcome_message(width: usize) {
         let mut welcome_message = format!("Ferro editor -- version {}", VERSION);
-        let width = self.terminal.size().width as usize;
         let len = welcome_message.len();
         let padding = width.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
         welcome_message = format!("~{}{}", spaces, welcome_message);
         welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        print!("{}", welcome_message);
     }
 
     /// Saves the document being edited.
     fn save(&mut self) {
         if self.document.filename.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
+            let minibuffer = self
+                .history_minibuffer("Save as: ", HistoryKind::SaveAs)
+                .with_completer(FileCompleter);
+            let new_name = self
+                .read_minibuffer(minibuffer, true, |_, _, _| {})
+                .unwrap_or(None);
             if new_name.is_none() {
                 self.set_status_message("Save aborted.".to_string());
                 return;
@@ -352,85 +596,1165 @@ impl Editor {
 
             self.document.filename = new_name;
         }
-        if self.document.save().is_ok() {
-            self.set_status_message("File saved successfully.".to_string());
+        if let Err(error) = self.document.save() {
+            self.set_status_message(format!("Error writing file: {error}"));
         } else {
-            self.set_status_message("Error writing file!".to_string());
+            let history_saved = match (&self.document.filename, self.document.hash()) {
+                (Some(filename), Some(hash)) => self.command_history.save(filename, hash).is_ok(),
+                _ => false,
+            };
+            if history_saved {
+                self.set_status_message("File saved successfully.".to_string());
+            } else {
+                self.set_status_message("File saved, but could not save undo history.".to_string());
+            }
         }
     }
 
-    /// Searches for a string in the document.
+    /// Searches for a string or regular expression in the document.
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
         let mut direction = SearchDirection::Forward;
-
+        let mut options = SearchOptions::default();
+        let mut match_len = 0;
+
+        let prompt = format!(
+            "Search ({}/{} = next/previous, {}/{} = select): ",
+            self.keymap.key_hint(Action::SearchForward),
+            self.keymap.key_hint(Action::SearchBackward),
+            self.keymap.key_hint(Action::SelectForward),
+            self.keymap.key_hint(Action::SelectBackward)
+        );
         let query = self
-            .prompt(
-                "Search (ESC to cancel, arrows to navigate, Ctrl+F/Ctrl+B to select): ",
-                |editor, key, query| {
-                    let mut moved = false;
-                    match key {
-                        KEY_SELECT_FORWARD => {
-                            editor
-                                .document
-                                .add_selection(editor.cursor_position, query.len());
-                            direction = SearchDirection::Forward;
-                            editor.move_cursor(Key::Right);
-                            moved = true;
-                        }
-                        KEY_POS_RIGHT | KEY_POS_DOWN => {
-                            direction = SearchDirection::Forward;
-                            editor.move_cursor(Key::Right);
-                            moved = true;
-                        }
-                        KEY_SELECT_BACKWARD => {
-                            editor
-                                .document
-                                .add_selection(editor.cursor_position, query.len());
-                            direction = SearchDirection::Backward;
-                        }
-                        KEY_POS_LEFT | KEY_POS_UP => direction = SearchDirection::Backward,
-                        _ => (),
+            .prompt_with_history(&prompt, HistoryKind::Search, |editor, action, query| {
+                let previous_position = editor.cursor_position;
+                let mut moved = false;
+                // Moving forward skips the current match, so that matches do not overlap
+                let skip_match = |editor: &mut Self| {
+                    (0..match_len.max(1)).for_each(|_| editor.move_cursor(Action::Right));
+                };
+                match action {
+                    Some(Action::SelectForward) => {
+                        editor
+                            .document
+                            .add_selection(editor.cursor_position, match_len);
+                        direction = SearchDirection::Forward;
+                        skip_match(editor);
+                        moved = true;
                     }
-
-                    if let Some(position) =
+                    Some(Action::SearchForward) => {
+                        direction = SearchDirection::Forward;
+                        skip_match(editor);
+                        moved = true;
+                    }
+                    Some(Action::SelectBackward) => {
                         editor
                             .document
-                            .find(&query, &editor.cursor_position, direction)
-                    {
-                        editor.cursor_position = position;
-                        editor.scroll();
-                    } else if moved {
-                        editor.move_cursor(Key::Left);
+                            .add_selection(editor.cursor_position, match_len);
+                        direction = SearchDirection::Backward;
                     }
-                    editor.highlighted_word = Some(query.to_string());
-                },
-            )
+                    Some(Action::SearchBackward) => direction = SearchDirection::Backward,
+                    _ => toggle_search_option(&mut options, action),
+                }
+
+                let mut info: Vec<String> =
+                    options.describe().into_iter().map(String::from).collect();
+                let pattern = Pattern::new(query, options);
+                if pattern.is_err() {
+                    info.push("invalid pattern".to_string());
+                }
+                editor.highlighted_pattern = pattern.ok();
+
+                let found = editor.highlighted_pattern.as_ref().and_then(|pattern| {
+                    editor
+                        .document
+                        .find_wrapping(pattern, &editor.cursor_position, direction)
+                });
+                if let Some((position, len, wrapped)) = found {
+                    editor.cursor_position = position;
+                    match_len = len;
+                    editor.scroll();
+                    if let Some(pattern) = &editor.highlighted_pattern {
+                        let count = editor.document.count_matches(pattern, &position);
+                        let more = if count.capped { "+" } else { "" };
+                        info.push(match count.before {
+                            Some(before) => format!("match {}/{}{more}", before + 1, count.total),
+                            None => format!("{}{more} matches", count.total),
+                        });
+                    }
+                    if wrapped {
+                        info.push("search wrapped".to_string());
+                    }
+                } else {
+                    if moved {
+                        editor.cursor_position = previous_position;
+                    }
+                    match_len = 0;
+                    if editor.highlighted_pattern.is_some() && !query.is_empty() {
+                        info.push("no matches".to_string());
+                    }
+                }
+
+                if action == Some(Action::SelectAll) {
+                    if let Some(pattern) = &editor.highlighted_pattern {
+                        let count = editor.document.select_all(pattern);
+                        info.push(format!("{count} selected"));
+                    }
+                }
+                editor.prompt_info = info.join(", ");
+            })
             .unwrap_or(None);
 
         if query.is_none() {
             self.cursor_position = old_position;
             self.scroll();
         }
-        self.highlighted_word = None;
+        self.highlighted_pattern = None;
+        self.document.refresh_highlighting();
+    }
+
+    /// Replaces matches of a pattern after the cursor one by one, asking the user whether to
+    /// replace each of them. All replacements are undone together.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn query_replace(&mut self) -> Result<(), std::io::Error> {
+        let prompt = format!(
+            "Query replace ({} = regex): ",
+            self.keymap.key_hint(Action::ToggleRegex)
+        );
+        let Some((query, pattern)) = self.prompt_pattern(&prompt)? else {
+            return Ok(());
+        };
+        let Some(replacement) = self.prompt_with_history(
+            &format!("Replace {query} with: "),
+            HistoryKind::Replace,
+            |_, _, _| {},
+        )?
+        else {
+            return Ok(());
+        };
+
+        let mut command_group = CommandGroup::new(CommandType::REPLACE);
+        let mut replaced = 0;
+        let mut replace_all = false;
+        let mut position = self.cursor_position;
+        self.highlighted_pattern = Some(pattern.clone());
+        while let Some((at, len)) =
+            self.document
+                .find(&pattern, &position, SearchDirection::Forward)
+        {
+            let end = Position {
+                x: at.x + len,
+                y: at.y,
+            };
+            let matched = self.document.get_doc_content_as_string(at, end);
+            self.cursor_position = at;
+            self.scroll();
+
+            let answer = if replace_all {
+                Key::Char('y')
+            } else {
+                self.document.reset_selections();
+                self.document.add_selection(at, len);
+                self.set_status_message(format!(
+                    "Replace with {replacement}? (y)es, (n)o, (a)ll, (q)uit or (e)dit"
+                ));
+                self.refresh_screen()?;
+                match Terminal::read_event()? {
+                    Event::Key(key) => key,
+                    _ => continue,
+                }
+            };
+            let replace_with = match answer {
+                Key::Char('y' | ' ') => {
+                    Some(self.document.expand(&pattern, &at, &matched, &replacement))
+                }
+                Key::Char('a' | '!') => {
+                    replace_all = true;
+                    Some(self.document.expand(&pattern, &at, &matched, &replacement))
+                }
+                Key::Char('e') => {
+                    self.document.reset_selections();
+                    self.prompt_with_history(
+                        "Replace this match with: ",
+                        HistoryKind::Replace,
+                        |_, _, _| {},
+                    )?
+                }
+                Key::Char('n') | Key::Delete | Key::Backspace => None,
+                Key::Char('q' | '\n') | Key::Esc => break,
+                _ => continue,
+            };
+
+            position = end;
+            if let Some(replace_with) = replace_with {
+                let mut delete_command = DeleteCommand::new(at, matched);
+                let mut insert_command = InsertCommand::new(at, replace_with);
+                delete_command.execute(self);
+                insert_command.execute(self);
+                command_group.add(Box::new(RefCell::new(delete_command)));
+                command_group.add(Box::new(RefCell::new(insert_command)));
+                position = self.cursor_position;
+                replaced += 1;
+            }
+        }
+
+        if replaced > 0 {
+            self.command_history.push(command_group);
+        }
+        self.highlighted_pattern = None;
+        self.document.reset_selections();
         self.document.refresh_highlighting();
+        self.set_status_message(format!("Replaced {replaced} occurrence(s)."));
+        Ok(())
+    }
+
+    /// Searches the files in the working directory for a pattern, and lists the matches in a
+    /// buffer of their own, from which the file of a match can be opened with `Enter`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn project_search(&mut self) -> Result<(), std::io::Error> {
+        let prompt = format!(
+            "Search files ({} = regex): ",
+            self.keymap.key_hint(Action::ToggleRegex)
+        );
+        let Some((query, pattern)) = self.prompt_pattern(&prompt)? else {
+            return Ok(());
+        };
+
+        let (matches, truncated) = project_search::search_directory(Path::new("."), &pattern);
+        if matches.is_empty() {
+            self.set_status_message(format!("No matches for {query}."));
+            return Ok(());
+        }
+        let list: Vec<String> = matches.iter().map(Match::describe).collect();
+        let count = matches.len();
+        self.show_output(&list.join("\n"), Output::Matches(matches));
+        let more = if truncated { " (search stopped)" } else { "" };
+        self.set_status_message(format!(
+            "{count} match(es) for {query}{more}. Press Enter to open one."
+        ));
+        Ok(())
+    }
+
+    /// Opens the file of the match that the cursor is on in a list of search results, with the
+    /// cursor at the match.
+    fn open_match(&mut self) {
+        let Some(Output::Matches(matches)) = &self.buffers[self.current_buffer].output else {
+            return;
+        };
+        let Some(found) = matches.get(self.cursor_position.y).cloned() else {
+            return;
+        };
+        if self.open_buffer(&found.path) {
+            self.cursor_position.y = found.position.y.min(self.document.len());
+            self.cursor_position.x = found.position.x;
+            self.clamp_cursor();
+            self.scroll();
+        }
+    }
+
+    /// Checks whether the active buffer lists the results of a project-wide search.
+    fn is_match_list(&self) -> bool {
+        matches!(
+            self.buffers[self.current_buffer].output,
+            Some(Output::Matches(_))
+        )
+    }
+
+    /// Prompts the user for a style to convert the document to, i.e. its line endings, whether
+    /// it is indented with tabs or spaces, or the encoding that it is saved in.
+    fn convert(&mut self) {
+        let style = self
+            .prompt(
+                "Convert to (lf, crlf, tabs, spaces or an encoding): ",
+                |_, _, _| {},
+            )
+            .unwrap_or(None)
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match style.as_str() {
+            "lf" => self.document.set_line_ending(LineEnding::Lf),
+            "crlf" => self.document.set_line_ending(LineEnding::CrLf),
+            "tabs" | "spaces" => {
+                let mut command = IndentCommand::new(style == "tabs");
+                command.execute(self);
+                self.command_history.push(CommandGroup::from_command(
+                    Box::new(RefCell::new(command)),
+                    CommandType::REPLACE,
+                ));
+            }
+            "" => return,
+            _ => {
+                if let Some(encoding) = Encoding::from_name(&style) {
+                    self.document.set_encoding(encoding);
+                } else {
+                    self.set_status_message(format!("Unknown style: {style}"));
+                    return;
+                }
+            }
+        }
+        self.set_status_message(format!("Converted to {style}."));
+    }
+
+    /// Prompts the user for an encoding to re-open the document's file with, or `hex` to
+    /// re-open it as a hex dump.
+    fn reopen(&mut self) {
+        let Some(filename) = self.document.filename.clone() else {
+            return;
+        };
+        if self.document.is_dirty() {
+            self.set_status_message("Save or undo changes before re-opening the file.".to_string());
+            return;
+        }
+
+        let name = self
+            .prompt("Re-open with encoding (or hex): ", |_, _, _| {})
+            .unwrap_or(None)
+            .unwrap_or_default();
+        let (reopened, name) = if name.eq_ignore_ascii_case("hex") {
+            (Document::open_hex(&filename), "hex")
+        } else if let Some(encoding) = Encoding::from_name(&name) {
+            (
+                Document::open_as(&filename, Some(encoding)),
+                encoding.name(),
+            )
+        } else {
+            if !name.is_empty() {
+                self.set_status_message(format!("Unknown encoding: {name}"));
+            }
+            return;
+        };
+
+        match reopened {
+            Ok(mut document) => {
+                // The history refers to positions in the text as it was decoded before
+                document.set_default_indent(self.config.spaces_per_tab);
+                self.document = document;
+                self.command_history = UndoTree::new(self.config.history_limit);
+                self.cursor_position = Position::default();
+                self.selection = None;
+                self.scroll();
+                self.set_status_message(format!("Re-opened file as {name}."));
+            }
+            Err(error) => {
+                self.set_status_message(format!("ERR: Could not re-open file as {name}: {error}"));
+            }
+        }
+    }
+
+    /// Prompts the user for a file to open in a new buffer.
+    fn open(&mut self) {
+        let minibuffer = Minibuffer::new("Open: ").with_completer(FileCompleter);
+        if let Some(filename) = self
+            .read_minibuffer(minibuffer, true, |_, _, _| {})
+            .unwrap_or(None)
+        {
+            self.open_buffer(&filename);
+        }
+    }
+
+    /// Prompts the user for a color theme to switch to.
+    fn switch_theme(&mut self) {
+        let names = theme::names();
+        let minibuffer = Minibuffer::new("Theme: ").with_completer(ListCompleter::new(&names));
+        let Some(name) = self
+            .read_minibuffer(minibuffer, true, |_, _, _| {})
+            .unwrap_or(None)
+        else {
+            return;
+        };
+
+        if let Some(theme) = theme::find(name.trim()) {
+            self.theme = theme;
+            self.set_status_message(format!("Switched to theme {}.", self.theme.name));
+        } else {
+            self.set_status_message(format!("Unknown theme: {name}"));
+        }
+    }
+
+    /// Shows the current key bindings.
+    fn show_bindings(&mut self) {
+        self.show_output(&self.keymap.describe(), Output::Bindings);
+    }
+
+    /// Shows generated text in the buffer that already shows the same kind of text, whose
+    /// contents and history are replaced, or in a new buffer if there is none.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to show
+    /// * `output` - the kind of text
+    fn show_output(&mut self, text: &str, output: Output) {
+        let document = Document::from_text(text);
+        let command_history = UndoTree::new(self.config.history_limit);
+        let existing = self.buffers.iter().position(|buffer| {
+            buffer.output.as_ref().is_some_and(|shown| {
+                std::mem::discriminant(shown) == std::mem::discriminant(&output)
+            })
+        });
+        if let Some(index) = existing {
+            self.switch_buffer(index);
+            self.document = document;
+            self.command_history = command_history;
+            self.set_view(View::default());
+        } else {
+            self.buffers.push(Buffer::new(document, command_history));
+            self.switch_buffer(self.buffers.len() - 1);
+        }
+        self.buffers[self.current_buffer].output = Some(output);
+    }
+
+    /// Opens a file in a new buffer and switches to the buffer that already
+    /// holds the file. Returns whether the file is now being edited.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file to open
+    fn open_buffer(&mut self, filename: &str) -> bool {
+        let existing = self
+            .documents()
+            .position(|document| document.filename.as_deref() == Some(filename));
+        if let Some(index) = existing {
+            self.switch_buffer(index);
+            return true;
+        }
+
+        match Document::open(filename) {
+            Ok(document) => {
+                let buffer = Self::new_buffer(&self.config, document);
+                self.buffers.push(buffer);
+                self.switch_buffer(self.buffers.len() - 1);
+                true
+            }
+            Err(error) => {
+                self.set_status_message(format!("ERR: Could not open file {filename}: {error}"));
+                false
+            }
+        }
+    }
+
+    /// Makes another buffer the active one.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index of the buffer to switch to
+    fn switch_buffer(&mut self, index: usize) {
+        if index != self.current_buffer {
+            self.buffers[self.current_buffer].view = self.take_view();
+            self.swap_document(self.current_buffer);
+            self.swap_document(index);
+            self.current_buffer = index;
+            self.panes[self.current_pane].buffer = index;
+            let view = std::mem::take(&mut self.buffers[index].view);
+            self.set_view(view);
+        }
+        let name = Self::document_name(&self.document);
+        self.set_status_message(format!(
+            "Buffer {}/{}: {name}",
+            index + 1,
+            self.buffers.len()
+        ));
+    }
+
+    /// Swaps the document and history of the editor with those of a buffer, which either moves
+    /// the active buffer's state back into the buffer or moves a buffer's state into the editor.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index of the buffer to swap with
+    fn swap_document(&mut self, index: usize) {
+        let buffer = &mut self.buffers[index];
+        std::mem::swap(&mut self.document, &mut buffer.document);
+        std::mem::swap(&mut self.command_history, &mut buffer.command_history);
+    }
+
+    /// Moves the view of the pane that has focus out of the editor.
+    fn take_view(&mut self) -> View {
+        View {
+            cursor_position: std::mem::take(&mut self.cursor_position),
+            offset: std::mem::take(&mut self.offset),
+            max_position: self.max_position.take(),
+            selection: self.selection.take(),
+            low_nibble: std::mem::take(&mut self.low_nibble),
+        }
+    }
+
+    /// Moves a view into the editor, keeping its cursor within the document in case the
+    /// document has been edited through another pane in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `view` - the [View] to move into the editor
+    fn set_view(&mut self, view: View) {
+        self.cursor_position = view.cursor_position;
+        self.offset = view.offset;
+        self.max_position = view.max_position;
+        self.selection = view.selection;
+        self.low_nibble = view.low_nibble;
+
+        self.cursor_position.y = self.cursor_position.y.min(self.document.len());
+        self.clamp_cursor();
+        self.scroll();
+    }
+
+    /// Splits the pane that has focus in two, with both showing the same buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - the direction to split in
+    fn split_pane(&mut self, direction: SplitDirection) {
+        let view = self.take_view();
+        self.panes.push(Pane {
+            buffer: self.current_buffer,
+            view: view.clone(),
+        });
+        self.layout
+            .split(self.current_pane, self.panes.len() - 1, direction);
+        self.set_view(view);
+    }
+
+    /// Closes the pane that has focus, unless it is the only one.
+    fn close_pane(&mut self) {
+        if self.panes.len() == 1 {
+            self.set_status_message("Cannot close the only pane.".to_string());
+            return;
+        }
+
+        let position = self.pane_position();
+        self.buffers[self.current_buffer].view = self.take_view();
+        self.panes.remove(self.current_pane);
+        self.layout.remove(self.current_pane);
+
+        let order = self.layout.panes();
+        self.activate_pane(order[position.min(order.len() - 1)]);
+    }
+
+    /// Moves focus to the next or previous pane, from top left to bottom right.
+    ///
+    /// # Arguments
+    ///
+    /// * `forward` - whether to move to the next (rather than previous) pane
+    fn cycle_pane(&mut self, forward: bool) {
+        let order = self.layout.panes();
+        let len = order.len();
+        let position = self.pane_position();
+        let next = if forward {
+            (position + 1) % len
+        } else {
+            (position + len - 1) % len
+        };
+        self.focus_pane(order[next]);
+    }
+
+    /// Gets the position of the pane that has focus, from top left to bottom right.
+    fn pane_position(&self) -> usize {
+        self.layout
+            .panes()
+            .iter()
+            .position(|pane| *pane == self.current_pane)
+            .unwrap_or(0)
+    }
+
+    /// Moves focus to another pane.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index of the pane to focus
+    fn focus_pane(&mut self, index: usize) {
+        if index != self.current_pane {
+            let view = self.take_view();
+            self.buffers[self.current_buffer].view = view.clone();
+            self.panes[self.current_pane].view = view;
+            self.activate_pane(index);
+        }
+    }
+
+    /// Moves the buffer and view of a pane into the editor, after the view of the pane that had
+    /// focus has been moved out.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index of the pane to activate
+    fn activate_pane(&mut self, index: usize) {
+        let buffer = self.panes[index].buffer;
+        if buffer != self.current_buffer {
+            self.swap_document(self.current_buffer);
+            self.swap_document(buffer);
+            self.current_buffer = buffer;
+        }
+        self.current_pane = index;
+        let view = std::mem::take(&mut self.panes[index].view);
+        self.set_view(view);
+    }
+
+    /// Grows or shrinks the pane that has focus.
+    ///
+    /// # Arguments
+    ///
+    /// * `delta` - the change in percent of its split, which is negative to shrink the pane
+    fn resize_pane(&mut self, delta: isize) {
+        if self.layout.resize(self.current_pane, delta) {
+            self.scroll();
+        } else {
+            self.set_status_message("Cannot resize the only pane.".to_string());
+        }
+    }
+
+    /// Gets the area of the screen that the panes are laid out in.
+    fn screen_area(&self) -> Rect {
+        let size = self.terminal.size();
+        Rect {
+            x: 0,
+            y: 0,
+            width: size.width as usize,
+            height: size.height as usize,
+        }
+    }
+
+    /// Gets the area of the screen taken up by the pane that has focus.
+    fn viewport(&self) -> Rect {
+        self.layout.area(self.screen_area(), self.current_pane)
+    }
+
+    /// Shows the list of buffers and prompts the user for the one to switch to, by its number or
+    /// (part of) its name.
+    fn pick_buffer(&mut self) {
+        self.overlay = Some(self.buffer_list(""));
+        let query = self
+            .prompt("Switch to buffer (number or name): ", |editor, _, query| {
+                editor.overlay = Some(editor.buffer_list(query));
+            })
+            .unwrap_or(None);
+        self.overlay = None;
+
+        let Some(query) = query else {
+            return;
+        };
+        let index = query
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_sub(1))
+            .filter(|index| *index < self.buffers.len())
+            .or_else(|| {
+                self.documents()
+                    .position(|document| Self::document_name(document).contains(&query))
+            });
+        match index {
+            Some(index) => self.switch_buffer(index),
+            None => self.set_status_message(format!("No buffer matches {query}.")),
+        }
+    }
+
+    /// Lists the buffers whose number or name matches a query, marking the active buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - the query to match
+    fn buffer_list(&self, query: &str) -> Vec<String> {
+        self.documents()
+            .enumerate()
+            .filter_map(|(index, document)| {
+                let number = (index + 1).to_string();
+                let name = Self::document_name(document);
+                if !number.starts_with(query) && !name.contains(query) {
+                    return None;
+                }
+
+                let marker = if index == self.current_buffer {
+                    '>'
+                } else {
+                    ' '
+                };
+                let modified = if document.is_dirty() {
+                    " (modified)"
+                } else {
+                    ""
+                };
+                Some(format!("{marker}{number:>3}  {name}{modified}"))
+            })
+            .collect()
+    }
+
+    /// Iterates over the documents of all buffers, in order.
+    fn documents(&self) -> impl Iterator<Item = &Document> {
+        self.buffers.iter().enumerate().map(|(index, buffer)| {
+            if index == self.current_buffer {
+                &self.document
+            } else {
+                &buffer.document
+            }
+        })
+    }
+
+    /// Gets the names of the documents that have unsaved changes.
+    fn dirty_documents(&self) -> Vec<String> {
+        self.documents()
+            .filter(|document| document.is_dirty())
+            .map(Self::document_name)
+            .collect()
+    }
+
+    /// Gets the name under which a document is shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - the [Document] to name
+    fn document_name(document: &Document) -> String {
+        document
+            .filename
+            .clone()
+            .unwrap_or_else(|| "[No Name]".to_string())
     }
 
     /// Prompts the user for a mathematical expression and displays its evaluated result.
     fn evaluate_expression(&mut self) {
         let query = self
-            .prompt("Enter your expression: ", |_, _, _| {})
+            .prompt_with_history(
+                "Enter your expression: ",
+                HistoryKind::Calculator,
+                |_, _, _| {},
+            )
             .unwrap_or(None)
             .unwrap_or(String::new());
 
-        if let Ok(expr) = ShuntingParser::parse_str(&query) {
-            if let Ok(result) = MathContext::new().eval(&expr) {
-                self.status_message = StatusMessage::from(format!("Result = {}", result));
-                return;
+        if let Ok(expr) = ShuntingParser::parse_str(&query) {
+            if let Ok(result) = MathContext::new().eval(&expr) {
+                self.status_message = StatusMessage::from(format!("Result = {}", result));
+                return;
+            }
+        }
+
+        self.status_message = StatusMessage::from("Invalid expression.".into());
+    }
+
+    /// Processes a key that is handled by vim-style modal editing.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the [Key] that was pressed
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn process_vim_key(&mut self, key: Key) -> Result<(), std::io::Error> {
+        let Some(vim) = &mut self.vim else {
+            return Ok(());
+        };
+
+        if vim.mode == Mode::Insert {
+            // Only Esc is handled in insert mode, which completes the change being made
+            vim.record(key);
+            vim.finish_change();
+            vim.mode = Mode::Normal;
+            self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+            self.max_position = Some(self.cursor_position.x);
+            return Ok(());
+        }
+
+        let Some(c) = Vim::command_char(key) else {
+            vim.cancel();
+            if key == Key::Esc {
+                self.set_vim_mode(Mode::Normal);
+            }
+            return Ok(());
+        };
+        if let Parse::Complete(operation, count) = vim.push(c) {
+            self.vim_execute(operation, count)?;
+        }
+        if let Some(vim) = &mut self.vim {
+            if vim.mode != Mode::Insert {
+                vim.finish_change();
+            }
+        }
+        self.scroll();
+        Ok(())
+    }
+
+    /// Executes a command typed in normal or visual mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - the [Operation] to execute
+    /// * `count` - the count typed before the command, if any
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn vim_execute(
+        &mut self,
+        operation: Operation,
+        count: Option<usize>,
+    ) -> Result<(), std::io::Error> {
+        match operation {
+            Operation::Move(motion) => self.vim_move(motion, count),
+            Operation::Operate(operator, motion) => {
+                let range = vim::range(
+                    &self.document,
+                    self.cursor_position,
+                    motion,
+                    count,
+                    operator,
+                );
+                self.vim_operate(operator, range);
+            }
+            Operation::OperateSelection(operator) => {
+                let range = self.visual_selection();
+                self.set_vim_mode(Mode::Normal);
+                self.vim_operate(operator, range);
+            }
+            Operation::Insert(insertion) => self.vim_insert(insertion),
+            Operation::Paste { before } => self.vim_paste(before, count),
+            Operation::Undo => {
+                for _ in 0..count.unwrap_or(1).min(vim::MAX_REPEAT_COUNT) {
+                    self.undo();
+                }
+            }
+            Operation::Visual { linewise } => {
+                let mode = if linewise {
+                    Mode::VisualLine
+                } else {
+                    Mode::Visual
+                };
+                let current = self.vim.as_ref().map_or(Mode::Normal, |vim| vim.mode);
+                self.set_vim_mode(if current == mode { Mode::Normal } else { mode });
+            }
+            Operation::CommandLine => self.vim_command_line()?,
+            Operation::Repeat => {
+                let keys = self
+                    .vim
+                    .as_ref()
+                    .map(|vim| vim.repeat_keys(count))
+                    .unwrap_or_default();
+                for key in keys {
+                    self.process_keypress(key)?;
+                }
+            }
+        }
+
+        if self.vim.as_ref().is_some_and(Vim::is_visual) {
+            self.highlight_visual();
+        }
+        Ok(())
+    }
+
+    /// Switches the mode of vim-style modal editing, starting a selection at the cursor when a
+    /// visual mode is entered.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - the [Mode] to switch to
+    fn set_vim_mode(&mut self, mode: Mode) {
+        let Some(vim) = &mut self.vim else {
+            return;
+        };
+        let was_visual = vim.is_visual();
+        if !was_visual {
+            vim.anchor = self.cursor_position;
+        }
+        vim.mode = mode;
+
+        if was_visual && !vim.is_visual() {
+            self.document.reset_selections();
+            self.document.refresh_highlighting();
+        }
+    }
+
+    /// Gets the text selected in visual mode.
+    fn visual_selection(&self) -> Range {
+        let (anchor, linewise) = self
+            .vim
+            .as_ref()
+            .map_or((self.cursor_position, false), |vim| {
+                (vim.anchor, vim.mode == Mode::VisualLine)
+            });
+        vim::selection(&self.document, anchor, self.cursor_position, linewise)
+    }
+
+    /// Highlights the text selected in visual mode.
+    fn highlight_visual(&mut self) {
+        let (start, end) = match self.visual_selection() {
+            Range::Chars(start, end) => (start, end),
+            Range::Lines(first, last) => (
+                Position { x: 0, y: first },
+                Position {
+                    x: self.document.row_len(last).unwrap_or(0),
+                    y: last,
+                },
+            ),
+        };
+
+        self.document.reset_selections();
+        for y in start.y..=end.y {
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y {
+                end.x
+            } else {
+                self.document.row_len(y).unwrap_or(0)
+            };
+            self.document
+                .add_selection(Position { x: from, y }, to.saturating_sub(from));
+        }
+        self.document.refresh_highlighting();
+    }
+
+    /// Moves the cursor with a motion typed in normal or visual mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `motion` - the [Motion]
+    /// * `count` - how many times to move, if a count was typed
+    fn vim_move(&mut self, motion: Motion, count: Option<usize>) {
+        if let Motion::Up | Motion::Down = motion {
+            let action = if motion == Motion::Up {
+                Action::Up
+            } else {
+                Action::Down
+            };
+            // Moving by more rows than the document has is the same as moving to its end
+            for _ in 0..count.unwrap_or(1).min(self.document.len()) {
+                self.move_cursor(action);
+            }
+            // Unlike in the default mode, the cursor cannot move past the last row
+            let last = self.document.len().saturating_sub(1);
+            if self.cursor_position.y > last {
+                self.cursor_position.y = last;
+                self.clamp_cursor();
+            }
+        } else {
+            self.cursor_position =
+                vim::target(&self.document, self.cursor_position, motion, count, None);
+            self.max_position = Some(self.cursor_position.x);
+        }
+    }
+
+    /// Checks whether the document can be edited as text by commands, telling the user why not
+    /// otherwise.
+    fn check_editable(&mut self) -> bool {
+        let message = if self.document.is_loading() {
+            "The file is read-only until it has finished loading."
+        } else if self.is_match_list() {
+            "Search results are read-only. Press Enter to open a match."
+        } else if self.document.is_binary() {
+            "Binary files can only be edited in insert mode."
+        } else {
+            return true;
+        };
+        self.set_status_message(message.to_string());
+        false
+    }
+
+    /// Applies an operator to a range of text. The text is copied to the clipboard, and is
+    /// pasted as whole lines later on if the range is made up of lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `operator` - the [Operator] to apply
+    /// * `range` - the [Range] of text to apply it to
+    fn vim_operate(&mut self, operator: Operator, range: Range) {
+        if operator != Operator::Yank && !self.check_editable() {
+            return;
+        }
+        let (start, end) = match range {
+            Range::Chars(start, end) => (start, end),
+            Range::Lines(first, last) => {
+                (Position { x: 0, y: first }, Position { x: 0, y: last + 1 })
+            }
+        };
+        let text = self.document.get_doc_content_as_string(start, end);
+        if text.is_empty() {
+            return;
+        }
+        self.clipboard = Some(text.clone());
+        if let Some(vim) = &mut self.vim {
+            vim.linewise_text = matches!(range, Range::Lines(..)).then(|| text.clone());
+        }
+
+        let lines = text.strip_suffix('\n').unwrap_or(&text).to_string();
+        let (position, deleted) = match (operator, range) {
+            (Operator::Yank, Range::Lines(first, last)) => {
+                self.set_status_message(format!("{} line(s) yanked.", last - first + 1));
+                return;
+            }
+            (Operator::Yank, Range::Chars(start, _)) => {
+                self.cursor_position = start;
+                return;
+            }
+            (_, Range::Chars(start, _)) => (start, text),
+            (Operator::Change, Range::Lines(_, last)) => {
+                // The lines are emptied rather than removed, so that text is inserted in their place
+                let end = Position {
+                    x: self.document.row_len(last).unwrap_or(0),
+                    y: last,
+                };
+                (start, self.document.get_doc_content_as_string(start, end))
+            }
+            (_, Range::Lines(first, last)) => {
+                if last + 1 < self.document.len() {
+                    (start, text)
+                } else if first > 0 {
+                    // The last row has no newline after it, so the newline before it is deleted
+                    let y = first - 1;
+                    let x = self.document.row_len(y).unwrap_or(0);
+                    (Position { x, y }, format!("\n{lines}"))
+                } else {
+                    (start, lines)
+                }
+            }
+        };
+
+        if !deleted.is_empty() {
+            let mut command = DeleteCommand::new(position, deleted);
+            command.execute(self);
+            self.command_history.push(CommandGroup::from_command(
+                Box::new(RefCell::new(command)),
+                CommandType::DELETE,
+            ));
+        }
+        if operator == Operator::Change {
+            self.set_vim_mode(Mode::Insert);
+        } else if let Range::Lines(first, _) = range {
+            let y = first.min(self.document.len().saturating_sub(1));
+            self.cursor_position = Position {
+                x: vim::first_non_blank(&self.document, y),
+                y,
+            };
+        }
+    }
+
+    /// Enters insert mode, opening a new line first for `o` and `O`.
+    ///
+    /// # Arguments
+    ///
+    /// * `insertion` - where to enter insert mode
+    fn vim_insert(&mut self, insertion: Insertion) {
+        let Position { x, y } = self.cursor_position;
+        let row_len = self.document.row_len(y).unwrap_or(0);
+        match insertion {
+            Insertion::Before => (),
+            Insertion::After => self.cursor_position.x = (x + 1).min(row_len),
+            Insertion::LineStart => {
+                self.cursor_position.x = vim::first_non_blank(&self.document, y);
+            }
+            Insertion::LineEnd => self.cursor_position.x = row_len,
+            Insertion::LineBelow | Insertion::LineAbove => {
+                if !self.check_editable() {
+                    return;
+                }
+                self.cursor_position = match (insertion, y.checked_sub(1)) {
+                    (Insertion::LineBelow, _) => Position { x: row_len, y },
+                    (_, Some(y)) => Position {
+                        x: self.document.row_len(y).unwrap_or(0),
+                        y,
+                    },
+                    (_, None) => Position::default(),
+                };
+
+                let mut command = InsertCommand::new(self.cursor_position, "\n".to_string());
+                command.execute(self);
+                self.merge_or_add_command(Box::new(RefCell::new(command)), CommandType::INSERT);
+                if insertion == Insertion::LineAbove && y == 0 {
+                    self.cursor_position = Position::default();
+                }
+            }
+        }
+        self.set_vim_mode(Mode::Insert);
+    }
+
+    /// Pastes the clipboard after or before the cursor, or below or above the cursor's line if
+    /// it holds whole lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - whether to paste before (rather than after) the cursor
+    /// * `count` - how many times to paste the clipboard, if a count was typed
+    fn vim_paste(&mut self, before: bool, count: Option<usize>) {
+        let Some(clipboard) = self.clipboard.clone() else {
+            return;
+        };
+        if !self.check_editable() {
+            return;
+        }
+        let linewise = self
+            .vim
+            .as_ref()
+            .is_some_and(|vim| vim.linewise_text.as_ref() == Some(&clipboard));
+        let text = clipboard.repeat(count.unwrap_or(1).min(vim::MAX_REPEAT_COUNT));
+        let Position { x, y } = self.cursor_position;
+        let row_len = self.document.row_len(y).unwrap_or(0);
+
+        let (position, text) = if !linewise {
+            let x = if before { x } else { (x + 1).min(row_len) };
+            (Position { x, y }, text)
+        } else if before {
+            (Position { x: 0, y }, text)
+        } else if y + 1 < self.document.len() {
+            (Position { x: 0, y: y + 1 }, text)
+        } else {
+            // There is no row below to paste before, so the lines are pasted after a newline
+            let lines = text.strip_suffix('\n').unwrap_or(&text);
+            (Position { x: row_len, y }, format!("\n{lines}"))
+        };
+
+        let mut command = PasteCommand::new(position, Some(text));
+        command.execute(self);
+        self.command_history.push(CommandGroup::from_command(
+            Box::new(RefCell::new(command)),
+            CommandType::PASTE,
+        ));
+        if linewise {
+            let y = if before { y } else { y + 1 };
+            self.cursor_position = Position {
+                x: vim::first_non_blank(&self.document, y),
+                y,
+            };
+        } else {
+            self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+        }
+    }
+
+    /// Prompts for a command such as `w` or `q`, as typed after `:` in vim, and runs it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn vim_command_line(&mut self) -> Result<(), std::io::Error> {
+        let minibuffer = Minibuffer::new(":").with_completer(ListCompleter::new(vim::COMMANDS));
+        let Some(command) = self.read_minibuffer(minibuffer, true, |_, _, _| {})? else {
+            return Ok(());
+        };
+        match command.trim() {
+            "w" => self.save(),
+            "q" => self.vim_quit(),
+            "q!" | "qa!" => self.should_quit = true,
+            "wq" | "x" => {
+                self.save();
+                self.vim_quit();
             }
+            command => match command.parse::<usize>() {
+                Ok(line) => self.vim_move(Motion::LastLine, Some(line)),
+                Err(_) => self.set_status_message(format!("Not an editor command: {command}")),
+            },
         }
+        Ok(())
+    }
 
-        self.status_message = StatusMessage::from("Invalid expression.".into());
+    /// Quits the editor unless there are unsaved changes, like `:q` in vim.
+    fn vim_quit(&mut self) {
+        let dirty = self.dirty_documents();
+        if dirty.is_empty() {
+            self.should_quit = true;
+        } else {
+            self.set_status_message(format!(
+                "No write since last change in {} (add ! to override).",
+                dirty.join(", ")
+            ));
+        }
     }
 
     /// Processes an event (i.e. a keypress or a mousepress).
@@ -447,6 +1771,9 @@ impl Editor {
                 self.terminal.resize()?;
                 self.refresh_screen()?;
             }
+            if self.poll_document() {
+                self.refresh_screen()?;
+            }
         }
         let event = handle.join().unwrap()?;
 
@@ -457,7 +1784,25 @@ impl Editor {
         }
     }
 
-    /// Processes a keypress event.
+    /// Takes the progress made loading the document, loading its command history once it has
+    /// been loaded. Returns whether the screen needs to be re-rendered as a result.
+    fn poll_document(&mut self) -> bool {
+        match self.document.poll_loader() {
+            Ok(true) if !self.document.is_loading() => {
+                self.command_history =
+                    Self::load_history(&self.document, self.config.history_limit);
+                true
+            }
+            Ok(changed) => changed,
+            Err(error) => {
+                self.set_status_message(format!("ERR: Could not finish loading file: {error}"));
+                true
+            }
+        }
+    }
+
+    /// Processes a keypress event, performing the action that the keys pressed so far are bound
+    /// to once they make up a whole key sequence.
     ///
     /// # Arguments
     ///
@@ -467,11 +1812,75 @@ impl Editor {
     ///
     /// Will return `Err` if I/O error encountered
     fn process_keypress(&mut self, keypress: Key) -> Result<(), std::io::Error> {
-        match keypress {
-            KEY_QUIT => {
-                if self.quit_times > 0 && self.document.is_dirty() {
+        if keypress == Key::Char('\n') && self.pending_keys.is_empty() && self.is_match_list() {
+            self.open_match();
+            return Ok(());
+        }
+        if let Some(vim) = &mut self.vim {
+            if self.pending_keys.is_empty() && vim.handles(keypress) {
+                return self.process_vim_key(keypress);
+            }
+            vim.record(keypress);
+        }
+
+        self.pending_keys.push(keypress);
+        let action = match self.keymap.lookup(Context::Editor, &self.pending_keys) {
+            Lookup::Action(action) => action,
+            Lookup::Prefix => {
+                self.set_status_message(format!("{} -", format_keys(&self.pending_keys)));
+                return Ok(());
+            }
+            Lookup::Unbound => match (self.pending_keys.len(), keypress) {
+                (1, Key::Char(c)) => Action::Insert(c),
+                (1, _) => {
+                    self.pending_keys.clear();
+                    return Ok(());
+                }
+                (_, key)
+                    if self.keymap.lookup(Context::Editor, &[key])
+                        == Lookup::Action(Action::Cancel) =>
+                {
+                    self.pending_keys.clear();
+                    Action::Cancel
+                }
+                _ => {
+                    let keys = format_keys(&self.pending_keys);
+                    self.pending_keys.clear();
+                    self.set_status_message(format!("{keys} is not bound to anything."));
+                    return Ok(());
+                }
+            },
+        };
+        if self.pending_keys.len() > 1 {
+            self.set_status_message(String::new());
+        }
+        self.pending_keys.clear();
+        self.perform(action)?;
+        self.last_action = Some(action);
+        if self.vim.as_ref().is_some_and(Vim::is_visual) {
+            self.highlight_visual();
+        }
+        Ok(())
+    }
+
+    /// Performs an action.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - the [Action] to perform
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn perform(&mut self, action: Action) -> Result<(), std::io::Error> {
+        match action {
+            Action::Quit => {
+                let dirty = self.dirty_documents();
+                if self.quit_times > 0 && !dirty.is_empty() {
                     self.set_status_message(format!(
-                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more time(s) to quit.",
+                        "WARNING! Unsaved changes in {}. Press {} {} more time(s) to quit.",
+                        dirty.join(", "),
+                        self.keymap.key_hint(Action::Quit),
                         self.quit_times
                     ));
                     self.quit_times -= 1;
@@ -480,10 +1889,67 @@ impl Editor {
 
                 self.should_quit = true;
             }
-            KEY_COPY => {
+            Action::Copy => {
                 CopyCommand::new().execute(self);
             }
-            KEY_CUT => {
+            Action::Cut
+            | Action::Paste
+            | Action::Undo
+            | Action::Redo
+            | Action::Earlier
+            | Action::Later
+            | Action::Save
+            | Action::Convert
+            | Action::Reopen
+            | Action::QueryReplace
+            | Action::Insert(_)
+            | Action::Delete
+            | Action::Backspace
+            | Action::KillLine
+            | Action::KillRegion
+            | Action::Yank
+            | Action::YankPop
+                if self.document.is_loading() =>
+            {
+                self.set_status_message(
+                    "The file is read-only until it has finished loading.".to_string(),
+                );
+            }
+            Action::Cut
+            | Action::Paste
+            | Action::Convert
+            | Action::QueryReplace
+            | Action::Insert(_)
+            | Action::Delete
+            | Action::Backspace
+            | Action::InsertByte
+            | Action::KillLine
+            | Action::KillRegion
+            | Action::Yank
+            | Action::YankPop
+                if self.is_match_list() =>
+            {
+                self.set_status_message(
+                    "Search results are read-only. Press Enter to open a match.".to_string(),
+                );
+            }
+            Action::Cut
+            | Action::Paste
+            | Action::Convert
+            | Action::QueryReplace
+            | Action::Insert(_)
+            | Action::Delete
+            | Action::Backspace
+            | Action::InsertByte
+            | Action::KillLine
+            | Action::KillRegion
+            | Action::Yank
+            | Action::YankPop
+                if self.document.is_binary() =>
+            {
+                self.edit_bytes(action);
+            }
+            Action::Cut => {
                 if let Some(Selection { start, end }) = self.selection {
                     CopyCommand::new().execute(self);
                     let mut command = DeleteCommand::new(
@@ -494,34 +1960,35 @@ impl Editor {
                     );
 
                     command.execute(self);
-                    self.command_history.push_back(CommandGroup::from_command(
+                    self.command_history.push(CommandGroup::from_command(
                         Box::new(RefCell::new(command)),
                         CommandType::PASTE,
                     ));
                 }
             }
-            KEY_PASTE => {
+            Action::Paste => {
                 let mut command = PasteCommand::new(self.cursor_position, self.clipboard.clone());
                 command.execute(self);
-                self.command_history.push_back(CommandGroup::from_command(
+                self.command_history.push(CommandGroup::from_command(
                     Box::new(RefCell::new(command)),
                     CommandType::PASTE,
                 ));
             }
-            KEY_UNDO => {
-                if let Some(mut command) = self.command_history.pop_back() {
-                    command.undo(self);
-                }
-            }
-            KEY_SAVE => self.save(),
-            KEY_SEARCH => self.search(),
-            KEY_START_SELECT => {
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::Earlier => self.travel_history(UndoTree::earlier),
+            Action::Later => self.travel_history(UndoTree::later),
+            Action::Save => self.save(),
+            Action::Search => self.search(),
+            Action::QueryReplace => self.query_replace()?,
+            Action::ProjectSearch => self.project_search()?,
+            Action::StartSelection => {
                 self.selection = Some(Selection {
                     start: self.cursor_position,
                     end: self.cursor_position,
                 });
             }
-            KEY_END_SELECT => {
+            Action::EndSelection => {
                 if let Some(Selection { start, end: _ }) = self.selection {
                     self.selection = Some(Selection {
                         start: start.min(self.cursor_position),
@@ -529,17 +1996,63 @@ impl Editor {
                     });
                 }
             }
-            Key::Alt('c') => self.evaluate_expression(),
-            Key::Char(c) => {
-                let mut command = InsertCommand::new(self.cursor_position, c.to_string());
+            Action::KillLine => self.kill_line(),
+            Action::KillRegion | Action::CopyRegion => {
+                if let Some(Selection { start: mark, .. }) = self.selection.take() {
+                    let start = mark.min(self.cursor_position);
+                    let end = mark.max(self.cursor_position);
+                    if action == Action::KillRegion {
+                        self.kill(start, end);
+                    } else {
+                        self.selection = Some(Selection { start, end });
+                        CopyCommand::new().execute(self);
+                        self.selection = None;
+                    }
+                } else {
+                    self.set_status_message("The mark is not set.".to_string());
+                }
+            }
+            Action::Yank => self.yank(),
+            Action::YankPop => self.yank_pop(),
+            Action::Cancel => {
+                self.selection = None;
+                self.set_status_message("Quit".to_string());
+            }
+            Action::Calculator => self.evaluate_expression(),
+            Action::Convert => self.convert(),
+            Action::Reopen => self.reopen(),
+            Action::Open => self.open(),
+            Action::NextBuffer => {
+                self.switch_buffer((self.current_buffer + 1) % self.buffers.len());
+            }
+            Action::PreviousBuffer => {
+                let len = self.buffers.len();
+                self.switch_buffer((self.current_buffer + len - 1) % len);
+            }
+            Action::BufferList => self.pick_buffer(),
+            Action::SplitHorizontal => self.split_pane(SplitDirection::Horizontal),
+            Action::SplitVertical => self.split_pane(SplitDirection::Vertical),
+            Action::ClosePane => self.close_pane(),
+            Action::NextPane => self.cycle_pane(true),
+            Action::PreviousPane => self.cycle_pane(false),
+            Action::GrowPane => self.resize_pane(RESIZE_STEP),
+            Action::ShrinkPane => self.resize_pane(-RESIZE_STEP),
+            Action::ShowBindings => self.show_bindings(),
+            Action::Theme => self.switch_theme(),
+            Action::Insert(c) => {
+                // A tab is recorded as the spaces it expands to, so that undoing it removes them
+                let content = if c == '\t' && !self.document.indents_with_tabs() {
+                    " ".repeat(self.document.tab_width())
+                } else {
+                    c.to_string()
+                };
+                let mut command = InsertCommand::new(self.cursor_position, content);
                 command.execute(self);
                 self.merge_or_add_command(Box::new(RefCell::new(command)), CommandType::INSERT);
             }
-            Key::Delete => {
+            Action::Delete => {
                 let Position { x, y } = self.cursor_position;
-                if y < self.document.len() - 1
-                    || x < self.document.row(y).unwrap_or(&Row::default()).len()
-                {
+                if y < self.document.len() - 1 || x < self.document.row_len(y).unwrap_or(0) {
                     let mut command = DeleteCommand::new(
                         self.cursor_position,
                         self.document
@@ -552,9 +2065,9 @@ impl Editor {
                     self.merge_or_add_command(Box::new(RefCell::new(command)), CommandType::DELETE);
                 }
             }
-            Key::Backspace => {
+            Action::Backspace => {
                 if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
+                    self.move_cursor(Action::Left);
                     let mut command = DeleteCommand::new(
                         self.cursor_position,
                         self.document
@@ -570,15 +2083,24 @@ impl Editor {
                     );
                 }
             }
-            KEY_POS_UP | KEY_POS_DOWN | KEY_POS_LEFT | KEY_POS_RIGHT | KEY_WORD_LEFT
-            | KEY_WORD_RIGHT | KEY_LINE_LEFT | KEY_LINE_RIGHT | KEY_PAGE_UP | KEY_PAGE_DOWN
-            | KEY_DOC_UP | KEY_DOC_DOWN => self.move_cursor(keypress),
+            Action::Up
+            | Action::Down
+            | Action::Left
+            | Action::Right
+            | Action::WordLeft
+            | Action::WordRight
+            | Action::LineLeft
+            | Action::LineRight
+            | Action::PageUp
+            | Action::PageDown
+            | Action::DocumentUp
+            | Action::DocumentDown => self.move_cursor(action),
             _ => (),
         }
 
         self.scroll();
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
+        if self.quit_times < self.config.quit_times {
+            self.quit_times = self.config.quit_times;
             self.set_status_message(String::new());
         }
         Ok(())
@@ -593,23 +2115,61 @@ impl Editor {
     /// * `command` - the [BoxedCommand] to add
     /// * `command_type` - the [CommandType] of `command`
     fn merge_or_add_command(&mut self, command: BoxedCommand, command_type: CommandType) {
-        let mut can_merge_with_last_command = false;
-        if let Some(last_command) = self.command_history.back_mut() {
-            if last_command.command_type == command_type {
-                can_merge_with_last_command = true;
-            }
+        self.command_history.merge_or_push(command, command_type);
+    }
+
+    /// Undoes the most recent change.
+    fn undo(&mut self) {
+        if !self.with_history(UndoTree::undo) {
+            self.set_status_message("Already at oldest change.".to_string());
         }
+    }
 
-        if can_merge_with_last_command {
-            if let Some(last_command) = self.command_history.back_mut() {
-                last_command.add(command);
-            }
-        } else {
-            self.command_history
-                .push_back(CommandGroup::from_command(command, command_type));
+    /// Redoes the most recently undone change.
+    fn redo(&mut self) {
+        if !self.with_history(UndoTree::redo) {
+            self.set_status_message("Already at newest change.".to_string());
         }
     }
 
+    /// Moves to an earlier or later state of the document, in the order that the states were
+    /// created (regardless of which branch of the undo tree they are on).
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - either [`UndoTree::earlier`] or [`UndoTree::later`]
+    fn travel_history<F>(&mut self, operation: F)
+    where
+        F: FnOnce(&mut UndoTree, &mut Self) -> bool,
+    {
+        let message = if !self.with_history(operation) {
+            "No more changes in that direction.".to_string()
+        } else if let Some(age) = self.command_history.current_age() {
+            format!("Moved to change from {}s ago.", age.as_secs())
+        } else {
+            "Moved to original state.".to_string()
+        };
+        self.set_status_message(message);
+    }
+
+    /// Runs an operation on the command history that needs to execute commands on the editor.
+    ///
+    /// The history is temporarily taken out of the editor so that the commands it holds can
+    /// mutate the editor.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - the operation to run, e.g. [`UndoTree::undo`]
+    fn with_history<F>(&mut self, operation: F) -> bool
+    where
+        F: FnOnce(&mut UndoTree, &mut Self) -> bool,
+    {
+        let mut history = std::mem::take(&mut self.command_history);
+        let result = operation(&mut history, self);
+        self.command_history = history;
+        result
+    }
+
     /// Processes a mousepress event.
     ///
     /// # Arguments
@@ -620,12 +2180,29 @@ impl Editor {
     ///
     /// Will return `Err` if I/O error encountered
     fn process_mousepress(&mut self, mousepress: MouseEvent) -> Result<(), std::io::Error> {
-        let offset = &self.offset;
         match mousepress {
             MouseEvent::Press(_, a, b) | MouseEvent::Release(a, b) | MouseEvent::Hold(a, b) => {
-                let y = offset.y + b.saturating_sub(1) as usize;
-                if let Some(row) = self.document.row(y) {
-                    let x = (offset.x + a.saturating_sub(1) as usize).min(row.len());
+                let (a, b) = (a.saturating_sub(1) as usize, b.saturating_sub(1) as usize);
+                let Some((pane, area)) = self
+                    .layout
+                    .areas(self.screen_area())
+                    .0
+                    .into_iter()
+                    .find(|(_, area)| area.contains(a, b))
+                else {
+                    return Ok(());
+                };
+                self.focus_pane(pane);
+
+                let y = self.offset.y + b - area.y;
+                if let Some(row) = self.document.line(y) {
+                    let column = self.offset.x + a - area.x;
+                    let x = if self.document.is_binary() {
+                        hex::index_at(column).min(self.document.row_len(y).unwrap_or(0))
+                    } else {
+                        row.index_at(column, self.document.tab_width())
+                    };
+                    self.low_nibble = false;
                     self.cursor_position = Position { x, y };
                     self.max_position = Some(x);
                 }
@@ -644,134 +2221,245 @@ impl Editor {
     /// # Errors
     ///
     /// Will return `Err` if I/O error encountered
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Option<Action>, &str),
+    {
+        self.read_minibuffer(Minibuffer::new(prompt), true, callback)
+    }
+
+    /// Prompts the user for input like [`Editor::prompt`], adding what is entered to the history
+    /// of a kind of prompt, whose entries can be recalled with `Up` and `Down`.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - the prompt to print
+    /// * `history` - the [`HistoryKind`] of the prompt
+    /// * `callback` - the callback to use
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn prompt_with_history<C>(
+        &mut self,
+        prompt: &str,
+        history: HistoryKind,
+        callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Option<Action>, &str),
+    {
+        self.read_minibuffer(self.history_minibuffer(prompt, history), true, callback)
+    }
+
+    /// Constructs a [`Minibuffer`] that keeps a history of a kind of prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - the prompt to print
+    /// * `history` - the [`HistoryKind`] of the prompt
+    fn history_minibuffer(&self, prompt: &str, history: HistoryKind) -> Minibuffer {
+        Minibuffer::new(prompt).with_history(history, self.prompt_history.entries(history))
+    }
+
+    /// Reads input from the user in a minibuffer, until it is entered or cancelled. The input is
+    /// added to the minibuffer's history, if it keeps one. Returns the input, unless it is empty
+    /// or was cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `minibuffer` - the [`Minibuffer`] to read input in
+    /// * `prompt_actions` - whether the keys bound to actions of prompts (e.g. replacing
+    ///   selections) perform them, which prompts that are opened by those actions do not
+    /// * `callback` - the callback to use after every key
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn read_minibuffer<C>(
+        &mut self,
+        mut minibuffer: Minibuffer,
+        prompt_actions: bool,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
     where
-        C: FnMut(&mut Self, Key, &String),
+        C: FnMut(&mut Self, Option<Action>, &str),
     {
-        let mut result = String::new();
         loop {
-            self.set_status_message(format!("{}{}", prompt, result));
+            let info: Vec<&str> = [
+                self.prompt_info.as_str(),
+                minibuffer.message().unwrap_or(""),
+            ]
+            .into_iter()
+            .filter(|info| !info.is_empty())
+            .collect();
+            if info.is_empty() {
+                self.set_status_message(minibuffer.render());
+            } else {
+                self.set_status_message(format!("{}  [{}]", minibuffer.render(), info.join("; ")));
+            }
+            self.prompt_cursor = Some(minibuffer.cursor_column());
             self.refresh_screen()?;
+
             let event = Terminal::read_event()?;
             if let Event::Key(key) = event {
-                match key {
-                    Key::Backspace => {
-                        let graphemes_cnt = result.graphemes(true).count();
-                        result = result
-                            .graphemes(true)
-                            .take(graphemes_cnt.saturating_sub(1))
-                            .collect();
+                let action = match self.keymap.lookup(Context::Prompt, &[key]) {
+                    Lookup::Action(action) if prompt_actions => Some(action),
+                    _ => None,
+                };
+                match action {
+                    Some(Action::DeleteSelections) => {
+                        self.delete_selections();
+                        break;
                     }
-                    Key::Char('\n') => {
-                        self.document.reset_selections();
+                    Some(Action::ReplaceSelections) => {
+                        self.replace_selections()?;
                         break;
                     }
-                    Key::Char(c) => {
-                        if !c.is_control() {
-                            result.push(c);
+                    Some(_) => (),
+                    None => match minibuffer.handle(key, &self.keymap) {
+                        Response::Submit => {
+                            if prompt_actions {
+                                self.document.reset_selections();
+                            }
+                            break;
                         }
-                    }
-                    KEY_DELETE_SELECTIONS => {
-                        let selections = self.document.update_and_get_selections();
-
-                        let mut command_group = CommandGroup::new(CommandType::REPLACE);
-                        for (pos, selection) in selections {
-                            let delete_command = DeleteCommand::new(pos, selection);
-                            command_group.add(Box::new(RefCell::new(delete_command)));
+                        Response::Cancel => {
+                            if prompt_actions {
+                                self.document.reset_selections();
+                            }
+                            minibuffer.clear();
+                            break;
                         }
-
-                        command_group.execute(self);
-                        self.command_history.push_back(command_group);
-                        self.document.reset_selections();
-                        break;
-                    }
-                    KEY_REPLACE_SELECTIONS => {
-                        let replacement = self.prompt_replacement()?;
-                        if let Some(replacement_string) = replacement {
-                            let selections = self.document.update_and_get_selections();
-
-                            let mut command_group = CommandGroup::new(CommandType::REPLACE);
-                            for (pos, selection) in selections {
-                                let delete_command = DeleteCommand::new(pos, selection);
-                                let insert_command =
-                                    InsertCommand::new(pos, replacement_string.clone());
-                                command_group.add(Box::new(RefCell::new(delete_command)));
-                                command_group.add(Box::new(RefCell::new(insert_command)));
+                        Response::Action(Action::Paste) => {
+                            if let Some(text) = &self.clipboard {
+                                minibuffer.insert(text);
                             }
-
-                            command_group.execute(self);
-                            self.command_history.push_back(command_group);
                         }
-                        self.document.reset_selections();
-                        break;
-                    }
-                    Key::Esc => {
-                        self.document.reset_selections();
-                        result.truncate(0);
-                        break;
-                    }
-                    _ => (),
+                        Response::Action(Action::Yank) => {
+                            if let Some(text) = self.kill_ring.yank() {
+                                minibuffer.insert(text);
+                            }
+                        }
+                        Response::Action(_) | Response::Continue => (),
+                    },
                 }
-                callback(self, key, &result);
+                callback(self, action, minibuffer.text());
             }
         }
 
+        self.prompt_info.clear();
+        self.prompt_cursor = None;
         self.set_status_message(String::new());
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result))
+        let text = minibuffer.text();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        if let Some(kind) = minibuffer.history_kind() {
+            self.prompt_history.add(kind, text);
+            // Failing to persist the history should not get in the way of what was entered
+            let _ = self.prompt_history.save();
         }
+        Ok(Some(text.to_string()))
     }
 
-    /// Prompts the user for a string to replace all selections with.
+    /// Deletes every selection, which is undone in one step.
+    fn delete_selections(&mut self) {
+        let selections = self.document.update_and_get_selections();
+
+        let mut command_group = CommandGroup::new(CommandType::REPLACE);
+        for (pos, selection) in selections {
+            let delete_command = DeleteCommand::new(pos, selection);
+            command_group.add(Box::new(RefCell::new(delete_command)));
+        }
+
+        command_group.execute(self);
+        self.command_history.push(command_group);
+        self.document.reset_selections();
+    }
+
+    /// Prompts the user for a replacement and replaces every selection with it, which is undone
+    /// in one step. Selected matches of the highlighted pattern expand the replacement.
     ///
     /// # Errors
     ///
     /// Will return `Err` if I/O error encountered
-    fn prompt_replacement(&mut self) -> Result<Option<String>, std::io::Error> {
-        let mut result = String::new();
-        loop {
-            self.set_status_message(format!("Replace with: {}", result));
-            self.refresh_screen()?;
-            let event = Terminal::read_event()?;
-            if let Event::Key(key) = event {
-                match key {
-                    Key::Backspace => {
-                        let graphemes_cnt = result.graphemes(true).count();
-                        result = result
-                            .graphemes(true)
-                            .take(graphemes_cnt.saturating_sub(1))
-                            .collect();
-                    }
-                    Key::Char('\n') => break,
-                    Key::Char(c) => {
-                        if !c.is_control() {
-                            result.push(c);
-                        }
-                    }
-                    Key::Esc => {
-                        result.truncate(0);
-                        break;
+    fn replace_selections(&mut self) -> Result<(), std::io::Error> {
+        let replacement = self.prompt_replacement()?;
+        if let Some(replacement_string) = replacement {
+            let mut selections = self.document.update_and_get_selections();
+            // Later selections are replaced first, so that replacing the earlier ones in the same
+            // row does not move them
+            selections.sort_by_key(|(pos, _)| std::cmp::Reverse(*pos));
+
+            let mut command_group = CommandGroup::new(CommandType::REPLACE);
+            for (pos, selection) in selections {
+                let replacement = match &self.highlighted_pattern {
+                    Some(pattern) => {
+                        self.document
+                            .expand(pattern, &pos, &selection, &replacement_string)
                     }
-                    _ => (),
-                }
+                    None => replacement_string.clone(),
+                };
+                let delete_command = DeleteCommand::new(pos, selection);
+                let insert_command = InsertCommand::new(pos, replacement);
+                command_group.add(Box::new(RefCell::new(delete_command)));
+                command_group.add(Box::new(RefCell::new(insert_command)));
             }
-        }
 
-        self.set_status_message(String::new());
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result))
+            command_group.execute(self);
+            self.command_history.push(command_group);
         }
+        self.document.reset_selections();
+        Ok(())
+    }
+
+    /// Prompts the user for a search pattern, whose options can be toggled while it is typed.
+    /// Returns the query and the compiled [`Pattern`], unless the prompt was cancelled or the
+    /// query is an invalid regular expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - the prompt to show
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn prompt_pattern(
+        &mut self,
+        prompt: &str,
+    ) -> Result<Option<(String, Pattern)>, std::io::Error> {
+        let mut options = SearchOptions::default();
+        let mut pattern = None;
+        let query =
+            self.prompt_with_history(prompt, HistoryKind::Search, |editor, action, query| {
+                toggle_search_option(&mut options, action);
+                let mut info: Vec<String> =
+                    options.describe().into_iter().map(String::from).collect();
+                pattern = Pattern::new(query, options).ok();
+                if pattern.is_none() {
+                    info.push("invalid pattern".to_string());
+                }
+                editor.prompt_info = info.join(", ");
+            })?;
+        Ok(query.zip(pattern))
+    }
+
+    /// Prompts the user for a string to replace all selections with.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered
+    fn prompt_replacement(&mut self) -> Result<Option<String>, std::io::Error> {
+        let minibuffer = self.history_minibuffer("Replace with: ", HistoryKind::Replace);
+        self.read_minibuffer(minibuffer, false, |_, _, _| {})
     }
 
     /// Scrolls the screen by the height of the terminal.
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let height = self.terminal.size().height as usize;
-        let width = self.terminal.size().width as usize;
+        let x = self.cursor_column();
+        let y = self.cursor_position.y;
+        let Rect { width, height, .. } = self.viewport();
         let mut offset = &mut self.offset;
 
         if y < offset.y {
@@ -787,6 +2475,18 @@ impl Editor {
         }
     }
 
+    /// Gets the column at which the cursor is rendered, which differs from its position within
+    /// its row when the row contains tabs.
+    fn cursor_column(&self) -> usize {
+        let Position { x, y } = self.cursor_position;
+        if self.document.is_binary() {
+            return hex::column(x, self.low_nibble);
+        }
+        self.document
+            .line(y)
+            .map_or(x, |row| row.column(x, self.document.tab_width()))
+    }
+
     /// Sets the editor's status message
     ///
     /// # Arguments
@@ -796,14 +2496,101 @@ impl Editor {
         self.status_message = StatusMessage::from(msg);
     }
 
-    /// Copies the selection into the clipboard
+    /// Copies the selection into the clipboard and the kill ring
     pub fn copy_to_clipboard(&mut self) {
         if let Some(Selection { start, end }) = self.selection {
-            self.clipboard = Some(self.document.get_doc_content_as_string(start, end));
+            let text = self.document.get_doc_content_as_string(start, end);
+            self.clipboard = Some(self.kill_ring.kill(text, false).to_string());
+        }
+    }
+
+    /// Kills the rest of the line, or the line break at its end if only whitespace follows the
+    /// cursor.
+    fn kill_line(&mut self) {
+        let Position { x: _, y } = self.cursor_position;
+        let line_end = Position {
+            x: self.document.row_len(y).unwrap_or(0),
+            y,
+        };
+        let rest = self
+            .document
+            .get_doc_content_as_string(self.cursor_position, line_end);
+        let end = if rest.trim().is_empty() && y + 1 < self.document.len() {
+            Position { x: 0, y: y + 1 }
+        } else {
+            line_end
+        };
+        self.kill(self.cursor_position, end);
+    }
+
+    /// Deletes text into the clipboard and the kill ring. The text is appended to the newest
+    /// kill if the previous action was a kill too, so that consecutive kills are yanked as one.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the start of the text to kill
+    /// * `end` - the end of the text to kill (exclusive)
+    fn kill(&mut self, start: Position, end: Position) {
+        let text = self.document.get_doc_content_as_string(start, end);
+        if text.is_empty() {
+            self.set_status_message("Nothing to kill.".to_string());
+            return;
         }
+        let append = matches!(
+            self.last_action,
+            Some(Action::KillLine | Action::KillRegion)
+        );
+        self.clipboard = Some(self.kill_ring.kill(text.clone(), append).to_string());
+
+        let mut command = DeleteCommand::new(start, text);
+        command.execute(self);
+        self.command_history.push(CommandGroup::from_command(
+            Box::new(RefCell::new(command)),
+            CommandType::DELETE,
+        ));
+    }
+
+    /// Inserts the newest text in the kill ring at the cursor.
+    fn yank(&mut self) {
+        let Some(text) = self.kill_ring.yank().map(str::to_string) else {
+            self.set_status_message("The kill ring is empty.".to_string());
+            return;
+        };
+        let start = self.cursor_position;
+        let mut command = PasteCommand::new(start, Some(text.clone()));
+        command.execute(self);
+        self.command_history.push(CommandGroup::from_command(
+            Box::new(RefCell::new(command)),
+            CommandType::PASTE,
+        ));
+        self.last_yank = Some((start, text));
+    }
+
+    /// Replaces the text that was just yanked with the previous text in the kill ring.
+    fn yank_pop(&mut self) {
+        let last_yank = self.last_yank.take();
+        let (Some((start, yanked)), Some(Action::Yank | Action::YankPop)) =
+            (last_yank, self.last_action)
+        else {
+            self.set_status_message("The previous command was not a yank.".to_string());
+            return;
+        };
+        let text = self.kill_ring.pop().unwrap_or_default().to_string();
+        self.clipboard = Some(text.clone());
+
+        let mut command_group = CommandGroup::new(CommandType::REPLACE);
+        command_group.add(Box::new(RefCell::new(DeleteCommand::new(start, yanked))));
+        command_group.add(Box::new(RefCell::new(PasteCommand::new(
+            start,
+            Some(text.clone()),
+        ))));
+        command_group.execute(self);
+        self.command_history.push(command_group);
+        self.last_yank = Some((start, text));
     }
 
-    /// Inserts a string at the specified position
+    /// Inserts a string at the specified position. Only a newline that is typed on its own is
+    /// auto-indented, so that longer strings (e.g. pasted or restored text) keep their indent.
     ///
     /// # Arguments
     ///
@@ -812,10 +2599,19 @@ impl Editor {
     /// * `move_right` - whether to move the cursor right after each insertion
     pub fn insert_string_at(&mut self, at: &Position, content: &String, move_right: bool) {
         self.cursor_position = *at;
+        let verbatim = !move_right || content.chars().nth(1).is_some();
         for c in content.chars() {
-            let indent = self.document.insert(&mut self.cursor_position, c);
-            if move_right {
-                (0..indent + 1).for_each(|_| self.move_cursor(Key::Right));
+            if verbatim {
+                let mut at = self.cursor_position;
+                self.document.insert_verbatim(&mut at, c);
+                if move_right {
+                    self.cursor_position = at;
+                }
+            } else {
+                let indent = self.document.insert(&mut self.cursor_position, c);
+                if move_right {
+                    (0..indent + 1).for_each(|_| self.move_cursor(Action::Right));
+                }
             }
         }
     }
@@ -833,42 +2629,144 @@ impl Editor {
         self.cursor_position = *at;
     }
 
-    /// Moves the cursor based on the key that was pressed.
+    /// Converts the indentation of the document between tabs and spaces. Returns whether the
+    /// document was indented with tabs before, and the changes made to rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `tabs` - whether to indent with tabs (or spaces)
+    pub fn reindent(&mut self, tabs: bool) -> (bool, Vec<IndentChange>) {
+        let previous_tabs = self.document.indents_with_tabs();
+        let changes = self.document.reindent(tabs);
+        self.clamp_cursor();
+        (previous_tabs, changes)
+    }
+
+    /// Reverts changes made to the indentation of rows by [`Editor::reindent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `changes` - the changes to revert
+    /// * `tabs` - whether the document was indented with tabs before the changes
+    pub fn restore_indentation(&mut self, changes: &[IndentChange], tabs: bool) {
+        self.document.restore_indentation(changes, tabs);
+        self.clamp_cursor();
+    }
+
+    /// Replaces a range of bytes in a binary document, and moves the cursor to the first of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - the offset of the first byte to replace
+    /// * `len` - the number of bytes to replace
+    /// * `bytes` - the bytes to replace them with
+    pub fn replace_bytes(&mut self, offset: usize, len: usize, bytes: &[u8]) {
+        self.document.replace_bytes(offset, len, bytes);
+        self.low_nibble = false;
+        self.cursor_position = Position {
+            x: offset % BYTES_PER_ROW,
+            y: offset / BYTES_PER_ROW,
+        };
+    }
+
+    /// Edits the bytes of a binary document based on the key that was pressed: hex digits
+    /// overwrite the byte under the cursor one nibble at a time, `Delete` and `Backspace` remove
+    /// a byte and `Insert` inserts a null byte.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - the [Action] that was performed
+    fn edit_bytes(&mut self, action: Action) {
+        let Position { x, y } = self.cursor_position;
+        let offset = y * BYTES_PER_ROW + x;
+        let byte = self.document.byte(offset);
+
+        let (mut command, command_type) = match action {
+            Action::Insert(c) if c.is_ascii_hexdigit() => {
+                let Some(digit) = c.to_digit(16).and_then(|digit| u8::try_from(digit).ok()) else {
+                    return;
+                };
+                let new_byte = match byte {
+                    Some(byte) if self.low_nibble => byte & 0xf0 | digit,
+                    Some(byte) => digit << 4 | byte & 0x0f,
+                    None => digit << 4,
+                };
+                let removed = byte.map(|byte| vec![byte]).unwrap_or_default();
+                let command = BytesCommand::new(offset, removed, vec![new_byte]);
+                (command, CommandType::INSERT)
+            }
+            Action::Delete if byte.is_some() => (
+                BytesCommand::new(offset, byte.into_iter().collect(), Vec::new()),
+                CommandType::DELETE,
+            ),
+            Action::Backspace if offset > 0 => {
+                let previous = self.document.byte(offset - 1).into_iter().collect();
+                (
+                    BytesCommand::new(offset - 1, previous, Vec::new()),
+                    CommandType::BACKSPACE,
+                )
+            }
+            Action::InsertByte => (
+                BytesCommand::new(offset, Vec::new(), vec![0]),
+                CommandType::PASTE,
+            ),
+            Action::Delete | Action::Backspace => return,
+            _ => {
+                self.set_status_message(
+                    "Type hex digits to edit bytes, or Insert/Delete to add/remove them."
+                        .to_string(),
+                );
+                return;
+            }
+        };
+
+        let low_nibble = self.low_nibble;
+        command.execute(self);
+        self.merge_or_add_command(Box::new(RefCell::new(command)), command_type);
+        if let Action::Insert(_) = action {
+            if low_nibble {
+                self.move_cursor(Action::Right);
+            } else {
+                self.low_nibble = true;
+            }
+        }
+    }
+
+    /// Moves the cursor to the end of its row if it is past it.
+    fn clamp_cursor(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        self.cursor_position.x = x.min(self.document.row_len(y).unwrap_or(0));
+    }
+
+    /// Moves the cursor based on the movement action that was performed.
     ///
     /// # Arguments
     ///
-    /// * `key` - The key that was pressed
-    fn move_cursor(&mut self, key: Key) {
-        let terminal_height = self.terminal.size().height as usize;
+    /// * `action` - The [Action] that was performed
+    fn move_cursor(&mut self, action: Action) {
+        let terminal_height = self.viewport().height;
 
+        self.low_nibble = false;
         let Position { mut x, mut y } = self.cursor_position;
         let height = self.document.len();
-        let mut width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
+        let mut width = self.document.row_len(y).unwrap_or(0);
 
-        match key {
-            KEY_POS_UP => y = y.saturating_sub(1),
-            KEY_POS_DOWN => {
+        match action {
+            Action::Up => y = y.saturating_sub(1),
+            Action::Down => {
                 if y < height {
                     y = y.saturating_add(1);
                 }
             }
-            KEY_POS_LEFT => {
+            Action::Left => {
                 if x > 0 {
                     x -= 1;
                 } else if y > 0 {
                     y -= 1;
-                    if let Some(row) = self.document.row(y) {
-                        x = row.len();
-                    } else {
-                        x = 0;
-                    }
+                    x = self.document.row_len(y).unwrap_or(0);
                 }
             }
-            KEY_POS_RIGHT => {
+            Action::Right => {
                 if x < width {
                     x += 1;
                 } else if y < height {
@@ -876,7 +2774,7 @@ impl Editor {
                     x = 0;
                 }
             }
-            KEY_WORD_LEFT => {
+            Action::WordLeft => {
                 if let Some(pos) = self
                     .document
                     .find_next_word(&self.cursor_position, SearchDirection::Backward)
@@ -885,7 +2783,7 @@ impl Editor {
                     y = pos.y;
                 }
             }
-            KEY_WORD_RIGHT => {
+            Action::WordRight => {
                 if let Some(pos) = self
                     .document
                     .find_next_word(&self.cursor_position, SearchDirection::Forward)
@@ -894,34 +2792,41 @@ impl Editor {
                     y = pos.y;
                 }
             }
-            KEY_LINE_LEFT => x = 0,
-            KEY_LINE_RIGHT => x = width,
-            KEY_PAGE_UP => y = y.saturating_sub(terminal_height),
-            KEY_PAGE_DOWN => y = y.saturating_add(terminal_height).min(height),
-            KEY_DOC_UP => y = 0,
-            KEY_DOC_DOWN => y = height,
+            Action::LineLeft => x = 0,
+            Action::LineRight => x = width,
+            Action::PageUp => y = y.saturating_sub(terminal_height),
+            Action::PageDown => y = y.saturating_add(terminal_height).min(height),
+            Action::DocumentUp => y = 0,
+            Action::DocumentDown => y = height,
             _ => (),
         }
 
-        width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
-        };
-
-        let is_vertical_control = |k: Key| match k {
-            KEY_POS_UP | KEY_POS_DOWN | KEY_PAGE_UP | KEY_PAGE_DOWN | KEY_DOC_UP | KEY_DOC_DOWN => {
-                true
-            }
-            _ => false,
+        width = self.document.row_len(y).unwrap_or(0);
+
+        let is_vertical_control = |a: Action| {
+            matches!(
+                a,
+                Action::Up
+                    | Action::Down
+                    | Action::PageUp
+                    | Action::PageDown
+                    | Action::DocumentUp
+                    | Action::DocumentDown
+            )
         };
-        let is_horizontal_control = |k: Key| match k {
-            KEY_POS_LEFT | KEY_POS_RIGHT | KEY_WORD_LEFT | KEY_WORD_RIGHT | KEY_LINE_LEFT
-            | KEY_LINE_RIGHT => true,
-            _ => false,
+        let is_horizontal_control = |a: Action| {
+            matches!(
+                a,
+                Action::Left
+                    | Action::Right
+                    | Action::WordLeft
+                    | Action::WordRight
+                    | Action::LineLeft
+                    | Action::LineRight
+            )
         };
 
-        if !is_vertical_control(key) || self.max_position.is_none() {
+        if !is_vertical_control(action) || self.max_position.is_none() {
             x = x.min(width);
         } else if let Some(pos) = self.max_position {
             x = x.max(pos).min(width);
@@ -929,7 +2834,7 @@ impl Editor {
 
         self.cursor_position = Position { x, y };
 
-        if is_horizontal_control(key) {
+        if is_horizontal_control(action) {
             // We need to update the cursor's max_position iff the keypress controls the cursor's x position
             self.max_position = Some(x);
         }
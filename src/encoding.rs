@@ -0,0 +1,173 @@
+use std::io::{Error, ErrorKind};
+
+use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// The character encoding of a file.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 that starts with a byte order mark
+    Utf8Bom,
+    /// UTF-16 (little endian) that starts with a byte order mark
+    Utf16Le,
+    /// UTF-16 (big endian) that starts with a byte order mark
+    Utf16Be,
+    /// Windows-1252, which is a superset of the printable characters of Latin-1
+    Windows1252,
+}
+
+impl Encoding {
+    /// Detects the encoding of a file from its byte order mark, falling back to Windows-1252
+    /// if the file is not valid UTF-8.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - the contents of the file
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+
+    /// Gets the encoding with the given name, ignoring case (e.g. "utf-16le" or "latin1").
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the encoding
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf8bom" => Some(Encoding::Utf8Bom),
+            "utf16le" | "utf16" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "windows1252" | "cp1252" | "latin1" | "iso88591" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of this encoding.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "Windows-1252",
+        }
+    }
+
+    /// Decodes the contents of a file, without its byte order mark.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - the contents of the file
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the contents are not valid in this encoding
+    pub fn decode(self, bytes: &[u8]) -> Result<String, Error> {
+        let decoded = match self {
+            Encoding::Utf8 => {
+                return String::from_utf8(bytes.to_vec())
+                    .map_err(|error| Error::new(ErrorKind::InvalidData, error));
+            }
+            Encoding::Utf8Bom => {
+                let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+                return String::from_utf8(bytes.to_vec())
+                    .map_err(|error| Error::new(ErrorKind::InvalidData, error));
+            }
+            Encoding::Utf16Le => UTF_16LE.decode_without_bom_handling_and_without_replacement(
+                bytes.strip_prefix(UTF16LE_BOM).unwrap_or(bytes),
+            ),
+            Encoding::Utf16Be => UTF_16BE.decode_without_bom_handling_and_without_replacement(
+                bytes.strip_prefix(UTF16BE_BOM).unwrap_or(bytes),
+            ),
+            Encoding::Windows1252 => {
+                WINDOWS_1252.decode_without_bom_handling_and_without_replacement(bytes)
+            }
+        };
+
+        decoded.map(String::from).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("file is not valid {}", self.name()),
+            )
+        })
+    }
+
+    /// Encodes text to be written to a file, including its byte order mark.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to encode
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the text contains characters that this encoding cannot represent
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf8Bom => Ok([UTF8_BOM, text.as_bytes()].concat()),
+            Encoding::Utf16Le => Ok(UTF16LE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()),
+            Encoding::Utf16Be => Ok(UTF16BE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect()),
+            Encoding::Windows1252 => {
+                let (bytes, _, had_errors) = WINDOWS_1252.encode(text);
+                if had_errors {
+                    Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("text cannot be represented in {}", self.name()),
+                    ))
+                } else {
+                    Ok(bytes.into_owned())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Encoding;
+
+    #[test]
+    fn round_trip() {
+        let text = "caf\u{e9} \u{201c}quoted\u{201d}\r\n";
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf8Bom,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Windows1252,
+        ] {
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(Encoding::detect(&bytes), encoding);
+            assert_eq!(encoding.decode(&bytes).unwrap(), text);
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+
+        assert!(Encoding::Windows1252.encode("\u{1f980}").is_err());
+        assert!(Encoding::Utf8.decode(&[0xe9]).is_err());
+        assert_eq!(Encoding::from_name("Latin-1"), Some(Encoding::Windows1252));
+    }
+}
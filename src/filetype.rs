@@ -1,13 +1,65 @@
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::config;
+use crate::grammar::Grammar;
+
+/// The syntax definitions that are built into ferro, as pairs of file name and contents
+const BUILT_IN_SYNTAXES: [(&str, &str); 13] = [
+    ("rust.toml", include_str!("../resources/syntax/rust.toml")),
+    ("java.toml", include_str!("../resources/syntax/java.toml")),
+    (
+        "python.toml",
+        include_str!("../resources/syntax/python.toml"),
+    ),
+    ("c.toml", include_str!("../resources/syntax/c.toml")),
+    ("cpp.toml", include_str!("../resources/syntax/cpp.toml")),
+    ("go.toml", include_str!("../resources/syntax/go.toml")),
+    (
+        "javascript.toml",
+        include_str!("../resources/syntax/javascript.toml"),
+    ),
+    (
+        "typescript.toml",
+        include_str!("../resources/syntax/typescript.toml"),
+    ),
+    ("shell.toml", include_str!("../resources/syntax/shell.toml")),
+    ("toml.toml", include_str!("../resources/syntax/toml.toml")),
+    ("yaml.toml", include_str!("../resources/syntax/yaml.toml")),
+    ("json.toml", include_str!("../resources/syntax/json.toml")),
+    (
+        "markdown.toml",
+        include_str!("../resources/syntax/markdown.toml"),
+    ),
+];
+
+static SYNTAXES: LazyLock<Syntaxes> = LazyLock::new(|| {
+    Syntaxes::load(
+        config::config_dir()
+            .map(|dir| dir.join("syntax"))
+            .as_deref(),
+    )
+});
+
 /// The file type of a document.
 pub struct FileType {
     /// The type associated with this [FileType] (e.g. "Rust" for ".rs" files)
     name: String,
     /// The associated [HighlightingOptions] for this [FileType]
     highlighting_options: HighlightingOptions,
+    /// The user's [`Grammar`] for this [`FileType`], which takes precedence over its
+    /// highlighting options
+    grammar: Option<Grammar>,
 }
 
 /// The highlighting options that determine what gets highlighted in a file.
-#[derive(Default)]
+#[derive(Default, Clone, Deserialize)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct HighlightingOptions {
     numbers: bool,
     characters: bool,
@@ -16,6 +68,21 @@ pub struct HighlightingOptions {
     multiline_comments: Option<Vec<(String, String)>>,
     primary_keywords: Vec<String>,
     secondary_keywords: Vec<String>,
+    /// Whether shell-style variables (`$VAR`, `${VAR}`) are highlighted, including within
+    /// double-quoted strings
+    variables: bool,
+    /// The characters that separate a key from its value (e.g. `=` in TOML), which make the key
+    /// before them highlighted
+    key_separators: Option<Vec<char>>,
+    /// Whether Markdown headings and emphasis are highlighted
+    markup: bool,
+    /// Whether multiline comments can be nested, as in Rust
+    nested_comments: bool,
+    /// Whether Rust-style raw strings (`r"..."`, `r#"..."#`) are highlighted
+    raw_strings: bool,
+    /// The delimiters of strings that can span several lines, either characters that are also
+    /// listed in `strings` or longer delimiters such as `"""`
+    multiline_strings: Option<Vec<String>>,
 }
 
 impl HighlightingOptions {
@@ -40,6 +107,186 @@ impl HighlightingOptions {
     pub fn secondary_keywords(&self) -> &Vec<String> {
         &self.secondary_keywords
     }
+    pub fn variables(&self) -> bool {
+        self.variables
+    }
+    pub fn key_separators(&self) -> &Option<Vec<char>> {
+        &self.key_separators
+    }
+    pub fn markup(&self) -> bool {
+        self.markup
+    }
+    pub fn nested_comments(&self) -> bool {
+        self.nested_comments
+    }
+    pub fn raw_strings(&self) -> bool {
+        self.raw_strings
+    }
+    pub fn multiline_strings(&self) -> &Option<Vec<String>> {
+        &self.multiline_strings
+    }
+}
+
+/// A definition of how to recognize and highlight a type of file, which is read from a TOML
+/// file like `resources/syntax/rust.toml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyntaxDefinition {
+    /// The name of the file type, e.g. "Rust"
+    name: String,
+    /// The extensions of the files of this type, without the leading dot
+    #[serde(default)]
+    extensions: Vec<String>,
+    /// The exact names of the files of this type, e.g. "Makefile"
+    #[serde(default)]
+    filenames: Vec<String>,
+    /// The interpreters that the shebangs of the files of this type name, e.g. "python3"
+    #[serde(default)]
+    shebangs: Vec<String>,
+    /// A regex that the first lines of the files of this type match
+    #[serde(default)]
+    first_line: Option<String>,
+    /// The compiled `first_line` regex
+    #[serde(skip)]
+    first_line_regex: Option<Regex>,
+    /// What gets highlighted in the files of this type
+    #[serde(default)]
+    highlighting: HighlightingOptions,
+}
+
+impl SyntaxDefinition {
+    /// Parses a syntax definition from TOML.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the definition to parse
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a description of the problem if the definition is invalid
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut definition: Self =
+            toml::from_str(contents).map_err(|error| error.message().to_string())?;
+        if let Some(first_line) = &definition.first_line {
+            definition.first_line_regex =
+                Some(Regex::new(first_line).map_err(|error| error.to_string())?);
+        }
+        Ok(definition)
+    }
+
+    /// Checks whether a shebang line names one of the interpreters of this definition, either
+    /// directly (`#!/bin/bash`) or through `env` (`#!/usr/bin/env -S python3 -u`). Versioned
+    /// interpreters such as `python3.12` match `python3`, too.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the first line of a file
+    fn matches_shebang(&self, line: &str) -> bool {
+        let Some(command) = line.strip_prefix("#!") else {
+            return false;
+        };
+        let mut words = command.split_whitespace();
+        let mut interpreter = words.next().unwrap_or_default();
+        if interpreter.rsplit('/').next() == Some("env") {
+            interpreter = words
+                .find(|word| !word.starts_with('-'))
+                .unwrap_or_default();
+        }
+        let interpreter = interpreter.rsplit('/').next().unwrap_or_default();
+        self.shebangs.iter().any(|shebang| {
+            interpreter
+                .strip_prefix(shebang.as_str())
+                .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        })
+    }
+}
+
+/// The syntax definitions that files are matched against.
+struct Syntaxes {
+    /// The definitions, with the user's before the built-in ones so that they take precedence
+    definitions: Vec<SyntaxDefinition>,
+    /// Descriptions of the user's definitions that could not be loaded
+    errors: Vec<String>,
+}
+
+impl Syntaxes {
+    /// Loads the built-in syntax definitions and the user's definitions, which are the `.toml`
+    /// files in the given directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the directory that holds the user's definitions, if any
+    fn load(dir: Option<&Path>) -> Self {
+        let mut syntaxes = Self {
+            definitions: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for path in config::files_with_extensions(dir, &["toml"]) {
+            match fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|contents| SyntaxDefinition::parse(&contents))
+            {
+                Ok(definition) => syntaxes.definitions.push(definition),
+                Err(error) => syntaxes.errors.push(format!("{}: {error}", path.display())),
+            }
+        }
+
+        for (name, contents) in BUILT_IN_SYNTAXES {
+            match SyntaxDefinition::parse(contents) {
+                Ok(definition) => syntaxes.definitions.push(definition),
+                Err(error) => syntaxes.errors.push(format!("{name}: {error}")),
+            }
+        }
+        syntaxes
+    }
+
+    /// Finds the definition that matches a file. The exact file name is tried first, then the
+    /// extension, the shebang and finally the first-line regex.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - the name of the file
+    /// * `first_line` - the first line of the file
+    fn detect(&self, file_name: &str, first_line: &str) -> Option<&SyntaxDefinition> {
+        let base_name = Path::new(file_name)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let extension = Path::new(file_name)
+            .extension()
+            .map(|extension| extension.to_string_lossy())
+            .unwrap_or_default();
+
+        let definitions = &self.definitions;
+        definitions
+            .iter()
+            .find(|definition| definition.filenames.iter().any(|name| *name == base_name))
+            .or_else(|| {
+                definitions.iter().find(|definition| {
+                    !extension.is_empty() && definition.extensions.iter().any(|e| *e == extension)
+                })
+            })
+            .or_else(|| {
+                definitions
+                    .iter()
+                    .find(|definition| definition.matches_shebang(first_line))
+            })
+            .or_else(|| {
+                definitions.iter().find(|definition| {
+                    definition
+                        .first_line_regex
+                        .as_ref()
+                        .is_some_and(|regex| regex.is_match(first_line))
+                })
+            })
+    }
+}
+
+/// Gets descriptions of the user's syntax definitions that could not be loaded, e.g.
+/// "~/.config/ferro/syntax/go.toml: missing field `name`".
+pub fn load_errors() -> &'static [String] {
+    &SYNTAXES.errors
 }
 
 impl Default for FileType {
@@ -47,6 +294,7 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             highlighting_options: HighlightingOptions::default(),
+            grammar: None,
         }
     }
 }
@@ -58,207 +306,39 @@ impl FileType {
     ///
     /// * `file_name` - the name of the file
     pub fn from(file_name: &str) -> Self {
-        if file_name.ends_with(".rs") {
-            return Self {
-                name: String::from("Rust"),
-                highlighting_options: HighlightingOptions {
-                    numbers: true,
-                    characters: true,
-                    strings: Some(vec!['"']),
-                    comments: Some("//".to_string()),
-                    multiline_comments: Some(vec![("/*".to_string(), "*/".to_string())]),
-                    primary_keywords: vec![
-                        "as".to_string(),
-                        "break".to_string(),
-                        "const".to_string(),
-                        "continue".to_string(),
-                        "crate".to_string(),
-                        "else".to_string(),
-                        "enum".to_string(),
-                        "extern".to_string(),
-                        "false".to_string(),
-                        "fn".to_string(),
-                        "for".to_string(),
-                        "if".to_string(),
-                        "impl".to_string(),
-                        "in".to_string(),
-                        "let".to_string(),
-                        "loop".to_string(),
-                        "match".to_string(),
-                        "mod".to_string(),
-                        "move".to_string(),
-                        "mut".to_string(),
-                        "pub".to_string(),
-                        "ref".to_string(),
-                        "return".to_string(),
-                        "self".to_string(),
-                        "Self".to_string(),
-                        "static".to_string(),
-                        "struct".to_string(),
-                        "super".to_string(),
-                        "trait".to_string(),
-                        "true".to_string(),
-                        "type".to_string(),
-                        "unsafe".to_string(),
-                        "use".to_string(),
-                        "where".to_string(),
-                        "while".to_string(),
-                        "dyn".to_string(),
-                        "abstract".to_string(),
-                        "become".to_string(),
-                        "box".to_string(),
-                        "do".to_string(),
-                        "final".to_string(),
-                        "macro".to_string(),
-                        "override".to_string(),
-                        "priv".to_string(),
-                        "typeof".to_string(),
-                        "unsized".to_string(),
-                        "virtual".to_string(),
-                        "yield".to_string(),
-                        "async".to_string(),
-                        "await".to_string(),
-                        "try".to_string(),
-                    ],
-                    secondary_keywords: vec![
-                        "bool".to_string(),
-                        "char".to_string(),
-                        "i8".to_string(),
-                        "i16".to_string(),
-                        "i32".to_string(),
-                        "i64".to_string(),
-                        "isize".to_string(),
-                        "u8".to_string(),
-                        "u16".to_string(),
-                        "u32".to_string(),
-                        "u64".to_string(),
-                        "usize".to_string(),
-                        "f32".to_string(),
-                        "f64".to_string(),
-                    ],
-                },
-            };
-        } else if file_name.ends_with(".java") {
-            return Self {
-                name: String::from("Java"),
-                highlighting_options: HighlightingOptions {
-                    numbers: true,
-                    characters: true,
-                    strings: Some(vec!['"']),
-                    comments: Some("//".to_string()),
-                    multiline_comments: Some(vec![("/*".to_string(), "*/".to_string())]),
-                    primary_keywords: vec![
-                        "abstract".to_string(),
-                        "assert".to_string(),
-                        "break".to_string(),
-                        "case".to_string(),
-                        "catch".to_string(),
-                        "class".to_string(),
-                        "const".to_string(),
-                        "continue".to_string(),
-                        "default".to_string(),
-                        "do".to_string(),
-                        "else".to_string(),
-                        "enum".to_string(),
-                        "extends".to_string(),
-                        "false".to_string(),
-                        "final".to_string(),
-                        "finally".to_string(),
-                        "for".to_string(),
-                        "if".to_string(),
-                        "implements".to_string(),
-                        "import".to_string(),
-                        "instanceof".to_string(),
-                        "interface".to_string(),
-                        "native".to_string(),
-                        "new".to_string(),
-                        "null".to_string(),
-                        "package".to_string(),
-                        "private".to_string(),
-                        "protected".to_string(),
-                        "public".to_string(),
-                        "return".to_string(),
-                        "static".to_string(),
-                        "super".to_string(),
-                        "switch".to_string(),
-                        "synchronized".to_string(),
-                        "this".to_string(),
-                        "throw".to_string(),
-                        "throws".to_string(),
-                        "transient".to_string(),
-                        "true".to_string(),
-                        "try".to_string(),
-                        "void".to_string(),
-                        "volatile".to_string(),
-                        "while".to_string(),
-                    ],
-                    secondary_keywords: vec![
-                        "boolean".to_string(),
-                        "byte".to_string(),
-                        "char".to_string(),
-                        "double".to_string(),
-                        "float".to_string(),
-                        "int".to_string(),
-                        "long".to_string(),
-                        "short".to_string(),
-                    ],
-                },
-            };
-        } else if file_name.ends_with(".py") {
-            return Self {
-                name: String::from("Python"),
-                highlighting_options: HighlightingOptions {
-                    numbers: true,
-                    characters: false,
-                    strings: Some(vec!['"', '\'']),
-                    comments: Some("#".to_string()),
-                    multiline_comments: Some(vec![
-                        ("'''".to_string(), "'''".to_string()),
-                        ("\"\"\"".to_string(), "\"\"\"".to_string()),
-                    ]),
-                    primary_keywords: vec![
-                        "False".to_string(),
-                        "None".to_string(),
-                        "True".to_string(),
-                        "and".to_string(),
-                        "as".to_string(),
-                        "assert".to_string(),
-                        "async".to_string(),
-                        "await".to_string(),
-                        "break".to_string(),
-                        "class".to_string(),
-                        "continue".to_string(),
-                        "def".to_string(),
-                        "del".to_string(),
-                        "elif".to_string(),
-                        "else".to_string(),
-                        "except".to_string(),
-                        "finally".to_string(),
-                        "for".to_string(),
-                        "from".to_string(),
-                        "global".to_string(),
-                        "if".to_string(),
-                        "import".to_string(),
-                        "in".to_string(),
-                        "is".to_string(),
-                        "lambda".to_string(),
-                        "nonlocal".to_string(),
-                        "not".to_string(),
-                        "or".to_string(),
-                        "pass".to_string(),
-                        "raise".to_string(),
-                        "return".to_string(),
-                        "try".to_string(),
-                        "while".to_string(),
-                        "with".to_string(),
-                        "yield".to_string(),
-                    ],
-                    secondary_keywords: vec![],
-                },
-            };
-        }
+        Self::detect(file_name, "")
+    }
 
-        Self::default()
+    /// Constructs the [`FileType`] based on a given filename and the first line of the file, which
+    /// identifies files without a known name or extension by their shebang (e.g.
+    /// `#!/usr/bin/env python3`) or by a regex of a [`SyntaxDefinition`].
+    ///
+    /// The user's grammars are matched by the filename first. A grammar that only matches the
+    /// first line is used only if there is no [`SyntaxDefinition`] for the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - the name of the file
+    /// * `first_line` - the first line of the file, or an empty string if it is not known
+    pub fn detect(file_name: &str, first_line: &str) -> Self {
+        let definition = SYNTAXES.detect(file_name, first_line);
+        let grammar = Grammar::find_by_name(file_name).or_else(|| {
+            definition
+                .is_none()
+                .then(|| Grammar::find_by_first_line(first_line))
+                .flatten()
+        });
+
+        let mut file_type = definition.map_or_else(Self::default, |definition| Self {
+            name: definition.name.clone(),
+            highlighting_options: definition.highlighting.clone(),
+            grammar: None,
+        });
+        if let Some(grammar) = grammar {
+            file_type.name = grammar.name().to_string();
+            file_type.grammar = Some(grammar);
+        }
+        file_type
     }
 
     /// Gets the name of the FileType.
@@ -270,10 +350,19 @@ impl FileType {
     pub fn highlighting_options(&self) -> &HighlightingOptions {
         &self.highlighting_options
     }
+
+    /// Gets the [`Grammar`] of the [`FileType`], if the user has one for it.
+    pub fn grammar(&self) -> Option<Grammar> {
+        self.grammar
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::fs;
+
+    use super::{SyntaxDefinition, Syntaxes, BUILT_IN_SYNTAXES};
+    use crate::test_util::TempDir;
     use crate::FileType;
 
     #[test]
@@ -282,5 +371,89 @@ mod test {
         assert_eq!(FileType::from("a.java").name, "Java");
         assert_eq!(FileType::from("a.txt").name, "No filetype");
         assert_eq!(FileType::from("foo").name, "No filetype");
+        assert_eq!(
+            FileType::detect("foo", "#!/usr/bin/env python3").name,
+            "Python"
+        );
+        assert_eq!(
+            FileType::detect("foo", "#!/usr/bin/python3.12 -u").name,
+            "Python"
+        );
+        assert_eq!(
+            FileType::detect("foo", "#!/usr/bin/pythonic").name,
+            "No filetype"
+        );
+
+        for (file_name, name) in [
+            ("main.c", "C"),
+            ("vec.hpp", "C++"),
+            ("main.go", "Go"),
+            ("index.mjs", "JavaScript"),
+            ("App.tsx", "TypeScript"),
+            ("build.sh", "Shell"),
+            ("/home/me/.bashrc", "Shell"),
+            ("Cargo.toml", "TOML"),
+            ("Cargo.lock", "TOML"),
+            ("ci.yml", "YAML"),
+            ("package.json", "JSON"),
+            ("README.md", "Markdown"),
+        ] {
+            assert_eq!(FileType::from(file_name).name, name, "{file_name}");
+        }
+        assert_eq!(FileType::detect("deploy", "#!/bin/bash -e").name, "Shell");
+        assert_eq!(
+            FileType::detect("serve", "#!/usr/bin/env node").name,
+            "JavaScript"
+        );
+
+        for (name, contents) in BUILT_IN_SYNTAXES {
+            assert!(SyntaxDefinition::parse(contents).is_ok(), "{name}");
+        }
+        let rust = FileType::from("src/main.rs");
+        let options = rust.highlighting_options();
+        assert!(options.characters());
+        assert_eq!(options.comments().as_deref(), Some("//"));
+        assert!(options.primary_keywords().contains(&"fn".to_string()));
+    }
+
+    #[test]
+    fn user_definitions() {
+        let dir = TempDir::new("syntax");
+        fs::write(
+            dir.join("make.toml"),
+            "name = \"Makefile\"\nfilenames = [\"Makefile\"]\nextensions = [\"mk\"]\n\n\
+             [highlighting]\ncomments = \"#\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("xml.toml"),
+            "name = \"XML\"\nfirst_line = '^<\\?xml '\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("rust.toml"),
+            "name = \"My Rust\"\nextensions = [\"rs\"]\n",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.toml"), "name = 1\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a definition").unwrap();
+
+        let syntaxes = Syntaxes::load(Some(dir.path()));
+
+        assert_eq!(syntaxes.errors.len(), 1);
+        assert!(syntaxes.errors[0].contains("broken.toml"));
+        let name = |file_name, first_line| {
+            syntaxes
+                .detect(file_name, first_line)
+                .map(|definition| definition.name.as_str())
+        };
+        assert_eq!(name("project/Makefile", ""), Some("Makefile"));
+        assert_eq!(name("rules.mk", ""), Some("Makefile"));
+        assert_eq!(name("Makefile.rs", ""), Some("My Rust"));
+        assert_eq!(name("a.java", ""), Some("Java"));
+        assert_eq!(name("data", "<?xml version=\"1.0\"?>"), Some("XML"));
+        assert_eq!(name("data", "<xml"), None);
+        assert!(SyntaxDefinition::parse("name = \"Bad\"\nfirst_line = \"(\"\n").is_err());
+        assert!(SyntaxDefinition::parse("name = \"Bad\"\ncolour = true\n").is_err());
     }
 }
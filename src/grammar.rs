@@ -0,0 +1,577 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use serde_json::{json, Map, Value};
+use syntect::parsing::{
+    ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet, SyntaxSetBuilder,
+};
+
+use crate::config;
+use crate::highlighting::Type;
+
+static GRAMMARS: LazyLock<Grammars> = LazyLock::new(|| {
+    Grammars::load(
+        config::config_dir()
+            .map(|dir| dir.join("grammars"))
+            .as_deref(),
+    )
+});
+
+/// The highlighting types of scopes, by the prefix of their names. More specific prefixes come
+/// first, as the first one that matches is used.
+static SCOPE_TYPES: LazyLock<Vec<(Scope, Type)>> = LazyLock::new(|| {
+    [
+        ("constant.character.escape", Type::Escape),
+        ("constant.other.placeholder", Type::Placeholder),
+        ("storage.modifier.lifetime", Type::Lifetime),
+        ("entity.name.lifetime", Type::Lifetime),
+        ("punctuation.definition.lifetime", Type::Lifetime),
+        ("meta.annotation", Type::Attribute),
+        ("meta.attribute", Type::Attribute),
+        ("entity.other.attribute-name", Type::Attribute),
+        ("comment", Type::Comment),
+        ("string", Type::String),
+        ("constant.character", Type::Character),
+        ("constant.numeric", Type::Number),
+        ("constant.language", Type::PrimaryKeywords),
+        ("keyword", Type::PrimaryKeywords),
+        ("storage.type", Type::SecondaryKeywords),
+        ("storage", Type::PrimaryKeywords),
+        ("variable.language", Type::PrimaryKeywords),
+        ("entity.name.function", Type::Function),
+        ("support.function", Type::Function),
+        ("variable.function", Type::Function),
+        ("variable", Type::Variable),
+        ("meta.mapping.key", Type::Key),
+        ("support.type.property-name", Type::Key),
+        ("entity.name.tag", Type::Key),
+        ("entity.name", Type::Entity),
+        ("support.type", Type::Entity),
+        ("support.class", Type::Entity),
+        ("markup.heading", Type::Heading),
+        ("markup.bold", Type::Emphasis),
+        ("markup.italic", Type::Emphasis),
+        ("markup.raw", Type::String),
+    ]
+    .into_iter()
+    .filter_map(|(prefix, hl_type)| Some((Scope::new(prefix).ok()?, hl_type)))
+    .collect()
+});
+
+/// A `TextMate` or Sublime Text grammar, which highlights files more precisely than the
+/// [`HighlightingOptions`](crate::HighlightingOptions) of a file type, e.g. escapes within
+/// strings.
+#[derive(Clone, Copy)]
+pub struct Grammar {
+    /// The grammars that this grammar belongs to, which it can include
+    set: &'static SyntaxSet,
+    /// The grammar itself
+    syntax: &'static SyntaxReference,
+}
+
+/// The state of a [`Grammar`] at the end of a row.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct GrammarState {
+    /// The state of the parser, i.e. the contexts of the grammar that are open
+    parse: ParseState,
+    /// The scopes that are open
+    scopes: ScopeStack,
+}
+
+impl Grammar {
+    /// Finds the user's grammar for a file by its exact name or its extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - the name of the file
+    pub fn find_by_name(file_name: &str) -> Option<Self> {
+        let path = Path::new(file_name);
+        [path.file_name(), path.extension()]
+            .into_iter()
+            .flatten()
+            .find_map(|name| {
+                GRAMMARS
+                    .set
+                    .find_syntax_by_extension(&name.to_string_lossy())
+            })
+            .map(|syntax| Self {
+                set: &GRAMMARS.set,
+                syntax,
+            })
+    }
+
+    /// Finds the user's grammar for a file by its first line, e.g. a shebang.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_line` - the first line of the file
+    pub fn find_by_first_line(first_line: &str) -> Option<Self> {
+        GRAMMARS
+            .set
+            .find_syntax_by_first_line(first_line)
+            .map(|syntax| Self {
+                set: &GRAMMARS.set,
+                syntax,
+            })
+    }
+
+    /// Gets the name of the grammar, e.g. "Rust".
+    pub fn name(&self) -> &str {
+        &self.syntax.name
+    }
+
+    /// Gets the state of the grammar at the start of a file.
+    pub fn start_state(&self) -> GrammarState {
+        GrammarState {
+            parse: ParseState::new(self.syntax),
+            scopes: ScopeStack::new(),
+        }
+    }
+
+    /// Computes the highlighting of every character in a row. If the grammar fails to parse the
+    /// row, the rest of it is left unhighlighted.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the contents of the row
+    /// * `state` - the state of the grammar at the start of the row; this gets updated to the
+    ///   state at the end of the row
+    pub fn highlight_line(&self, line: &str, state: &mut GrammarState) -> Vec<Type> {
+        let line = format!("{line}\n");
+        let ops = state.parse.parse_line(&line, self.set).unwrap_or_default();
+
+        let mut ops = ops.into_iter().peekable();
+        let mut hl_type = scope_type(&state.scopes);
+        let mut types = Vec::new();
+        for (at, _) in line[..line.len() - 1].char_indices() {
+            while let Some((_, op)) = ops.next_if(|(op_at, _)| *op_at <= at) {
+                if state.scopes.apply(&op).is_ok() {
+                    hl_type = scope_type(&state.scopes);
+                }
+            }
+            types.push(hl_type);
+        }
+        for (_, op) in ops {
+            let _ = state.scopes.apply(&op);
+        }
+        types
+    }
+}
+
+/// Gets the highlighting type of the innermost scope that has one.
+///
+/// # Arguments
+///
+/// * `scopes` - the scopes that are open
+fn scope_type(scopes: &ScopeStack) -> Type {
+    scopes
+        .as_slice()
+        .iter()
+        .rev()
+        .find_map(|scope| {
+            SCOPE_TYPES
+                .iter()
+                .find(|(prefix, _)| prefix.is_prefix_of(*scope))
+                .map(|(_, hl_type)| *hl_type)
+        })
+        .unwrap_or(Type::None)
+}
+
+/// The grammars that files are matched against.
+struct Grammars {
+    /// The grammars, linked to each other so that they can include one another
+    set: SyntaxSet,
+    /// Descriptions of the grammars that could not be loaded
+    errors: Vec<String>,
+}
+
+impl Grammars {
+    /// Loads the user's grammars, converting `TextMate` grammars to Sublime Text's format.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the directory of `.sublime-syntax` and `.tmLanguage` grammars, if any
+    fn load(dir: Option<&Path>) -> Self {
+        let mut builder = SyntaxSetBuilder::new();
+        let mut errors = Vec::new();
+
+        for path in config::files_with_extensions(dir, &["sublime-syntax", "tmLanguage"]) {
+            let definition = if path.extension().unwrap_or_default() == "tmLanguage" {
+                plist::Value::from_file(&path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|grammar| convert_tm_language(&grammar))
+            } else {
+                fs::read_to_string(&path).map_err(|error| error.to_string())
+            };
+            let name = path.file_stem().map(|name| name.to_string_lossy());
+            match definition.and_then(|definition| {
+                SyntaxDefinition::load_from_str(&definition, true, name.as_deref())
+                    .map_err(|error| error.to_string())
+            }) {
+                Ok(definition) => builder.add(definition),
+                Err(error) => errors.push(format!("{}: {error}", path.display())),
+            }
+        }
+
+        Self {
+            set: builder.build(),
+            errors,
+        }
+    }
+}
+
+/// Gets descriptions of the user's grammars that could not be loaded.
+pub fn load_errors() -> &'static [String] {
+    &GRAMMARS.errors
+}
+
+/// Converts a `TextMate` grammar into the equivalent Sublime Text grammar, which is written as
+/// YAML. Rules that begin and end become contexts that are pushed when they begin and popped
+/// when they end; rules that begin and continue `while` a pattern matches are popped at the
+/// start of the first row that does not match it.
+///
+/// # Arguments
+///
+/// * `grammar` - the `TextMate` grammar, as read from a `.tmLanguage` property list
+///
+/// # Errors
+///
+/// Will return `Err` with a description of the problem if the grammar is invalid
+fn convert_tm_language(grammar: &plist::Value) -> Result<String, String> {
+    let grammar = grammar
+        .as_dictionary()
+        .ok_or("the grammar is not a dictionary")?;
+    let scope = grammar
+        .get("scopeName")
+        .and_then(plist::Value::as_string)
+        .ok_or("the grammar has no scopeName")?;
+    let repository = grammar
+        .get("repository")
+        .and_then(plist::Value::as_dictionary);
+    let converter = Converter { repository };
+
+    let mut contexts = Map::new();
+    contexts.insert("main".to_string(), converter.rules(grammar.get("patterns")));
+    for (name, rule) in repository.into_iter().flatten() {
+        let rule = rule.as_dictionary();
+        let context = if rule.is_some_and(|rule| rule.contains_key("patterns"))
+            && !rule.is_some_and(|rule| rule.contains_key("begin"))
+        {
+            converter.rules(rule.and_then(|rule| rule.get("patterns")))
+        } else {
+            Value::Array(rule.map(|rule| converter.rule(rule)).unwrap_or_default())
+        };
+        contexts.insert(name.clone(), context);
+    }
+
+    let mut syntax = Map::new();
+    if let Some(name) = grammar.get("name").and_then(plist::Value::as_string) {
+        syntax.insert("name".to_string(), name.into());
+    }
+    syntax.insert("scope".to_string(), scope.into());
+    let extensions = grammar.get("fileTypes").and_then(plist::Value::as_array);
+    syntax.insert(
+        "file_extensions".to_string(),
+        extensions
+            .into_iter()
+            .flatten()
+            .filter_map(plist::Value::as_string)
+            .map(Value::from)
+            .collect(),
+    );
+    if let Some(first_line) = grammar
+        .get("firstLineMatch")
+        .and_then(plist::Value::as_string)
+    {
+        syntax.insert("first_line_match".to_string(), first_line.into());
+    }
+    syntax.insert("contexts".to_string(), Value::Object(contexts));
+
+    let mut yaml = String::new();
+    write_yaml(&Value::Object(syntax), &mut yaml);
+    Ok(yaml)
+}
+
+/// Converts the rules of a `TextMate` grammar into the patterns of Sublime Text contexts.
+struct Converter<'a> {
+    /// The rules of the grammar that can be included by name
+    repository: Option<&'a plist::Dictionary>,
+}
+
+impl Converter<'_> {
+    /// Converts a list of rules into the patterns of a context.
+    ///
+    /// # Arguments
+    ///
+    /// * `rules` - the rules, if any
+    fn rules(&self, rules: Option<&plist::Value>) -> Value {
+        rules
+            .and_then(plist::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(plist::Value::as_dictionary)
+            .flat_map(|rule| self.rule(rule))
+            .collect()
+    }
+
+    /// Converts a rule into the patterns that it is made up of.
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - the rule
+    fn rule(&self, rule: &plist::Dictionary) -> Vec<Value> {
+        let string = |key: &str| rule.get(key).and_then(plist::Value::as_string);
+        if rule
+            .get("disabled")
+            .and_then(plist::Value::as_unsigned_integer)
+            == Some(1)
+        {
+            return Vec::new();
+        }
+
+        if let Some(include) = string("include") {
+            let context = match include {
+                "$self" | "$base" => "main".to_string(),
+                _ => {
+                    if let Some(name) = include.strip_prefix('#') {
+                        // References to rules that do not exist would fail to link
+                        if !self.repository.is_some_and(|repo| repo.contains_key(name)) {
+                            return Vec::new();
+                        }
+                        name.to_string()
+                    } else {
+                        format!("scope:{include}")
+                    }
+                }
+            };
+            return vec![json!({ "include": context })];
+        }
+
+        let mut pattern = Map::new();
+        if let Some(regex) = string("match") {
+            pattern.insert("match".to_string(), regex.into());
+            if let Some(name) = string("name") {
+                pattern.insert("scope".to_string(), name.into());
+            }
+            insert_captures(&mut pattern, rule.get("captures"));
+            return vec![Value::Object(pattern)];
+        }
+
+        let Some(begin) = string("begin") else {
+            return self
+                .rules(rule.get("patterns"))
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+        };
+        pattern.insert("match".to_string(), begin.into());
+        insert_captures(
+            &mut pattern,
+            rule.get("beginCaptures").or_else(|| rule.get("captures")),
+        );
+
+        let mut context = Vec::new();
+        if let Some(name) = string("name") {
+            context.push(json!({ "meta_scope": name }));
+        }
+        if let Some(name) = string("contentName") {
+            context.push(json!({ "meta_content_scope": name }));
+        }
+        let mut end = Map::new();
+        if let Some(regex) = string("end") {
+            end.insert("match".to_string(), regex.into());
+            insert_captures(
+                &mut end,
+                rule.get("endCaptures").or_else(|| rule.get("captures")),
+            );
+        } else if let Some(regex) = string("while") {
+            end.insert("match".to_string(), format!("^(?!{regex})").into());
+        }
+        end.insert("pop".to_string(), true.into());
+        context.push(Value::Object(end));
+        if let Value::Array(patterns) = self.rules(rule.get("patterns")) {
+            context.extend(patterns);
+        }
+        pattern.insert("push".to_string(), Value::Array(context));
+        vec![Value::Object(pattern)]
+    }
+}
+
+/// Adds the scopes of the capture groups of a `TextMate` rule to a Sublime Text pattern.
+///
+/// # Arguments
+///
+/// * `pattern` - the pattern
+/// * `captures` - the captures of the rule, e.g. `{"1": {"name": "keyword"}}`
+fn insert_captures(pattern: &mut Map<String, Value>, captures: Option<&plist::Value>) {
+    let captures: Map<String, Value> = captures
+        .and_then(plist::Value::as_dictionary)
+        .into_iter()
+        .flatten()
+        .filter_map(|(group, capture)| {
+            let name = capture.as_dictionary()?.get("name")?.as_string()?;
+            Some((group.clone(), name.into()))
+        })
+        .collect();
+    if !captures.is_empty() {
+        pattern.insert("captures".to_string(), Value::Object(captures));
+    }
+}
+
+/// Writes a value as YAML in flow style. Strings are written as JSON strings, which are valid
+/// YAML, while keys made up of digits (i.e. capture groups) are written as integers.
+///
+/// # Arguments
+///
+/// * `value` - the value to write
+/// * `yaml` - the YAML to write to
+fn write_yaml(value: &Value, yaml: &mut String) {
+    match value {
+        Value::Array(values) => {
+            yaml.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    yaml.push_str(", ");
+                }
+                write_yaml(value, yaml);
+            }
+            yaml.push(']');
+        }
+        Value::Object(entries) => {
+            yaml.push('{');
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    yaml.push_str(", ");
+                }
+                if !key.is_empty() && key.chars().all(|c| c.is_ascii_digit()) {
+                    yaml.push_str(key);
+                } else {
+                    let _ = write!(yaml, "{}", Value::from(key.as_str()));
+                }
+                yaml.push_str(": ");
+                write_yaml(value, yaml);
+            }
+            yaml.push('}');
+        }
+        _ => {
+            let _ = write!(yaml, "{value}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{Grammar, Grammars};
+    use crate::test_util::{runs, TempDir};
+
+    const SUBLIME_SYNTAX: &str = r#"
+name: Tiny
+file_extensions: [tiny]
+scope: source.tiny
+contexts:
+  main:
+    - match: \b(let)\b
+      scope: keyword.other.tiny
+    - match: "'[a-z]+"
+      scope: storage.modifier.lifetime.tiny
+    - match: '"'
+      push:
+        - meta_scope: string.quoted.double.tiny
+        - match: '"'
+          pop: true
+        - match: \\.
+          scope: constant.character.escape.tiny
+"#;
+
+    const TM_LANGUAGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>name</key><string>Notes</string>
+  <key>scopeName</key><string>text.notes</string>
+  <key>fileTypes</key><array><string>notes</string></array>
+  <key>patterns</key>
+  <array>
+    <dict><key>include</key><string>#comment</string></dict>
+    <dict><key>include</key><string>#missing</string></dict>
+    <dict>
+      <key>match</key><string>(@)(\w+)</string>
+      <key>captures</key>
+      <dict>
+        <key>1</key><dict><key>name</key><string>keyword.operator</string></dict>
+        <key>2</key><dict><key>name</key><string>entity.name.function</string></dict>
+      </dict>
+    </dict>
+  </array>
+  <key>repository</key>
+  <dict>
+    <key>comment</key>
+    <dict>
+      <key>begin</key><string>\{</string>
+      <key>end</key><string>\}</string>
+      <key>name</key><string>comment.block</string>
+    </dict>
+  </dict>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn load_grammars() {
+        let dir = TempDir::new("grammars");
+        fs::write(dir.join("Tiny.sublime-syntax"), SUBLIME_SYNTAX).unwrap();
+        fs::write(dir.join("Notes.tmLanguage"), TM_LANGUAGE).unwrap();
+        fs::write(dir.join("Broken.sublime-syntax"), "name: [").unwrap();
+        fs::write(dir.join("README.md"), "not a grammar").unwrap();
+
+        let grammars = Grammars::load(Some(dir.path()));
+
+        assert_eq!(grammars.errors.len(), 1);
+        assert!(grammars.errors[0].contains("Broken.sublime-syntax"));
+        let names: Vec<&str> = grammars
+            .set
+            .syntaxes()
+            .iter()
+            .map(|syntax| syntax.name.as_str())
+            .collect();
+        assert_eq!(names, ["Notes", "Tiny"]);
+    }
+
+    #[test]
+    fn highlight_lines() {
+        let dir = TempDir::new("grammar-lines");
+        fs::write(dir.join("Tiny.sublime-syntax"), SUBLIME_SYNTAX).unwrap();
+        fs::write(dir.join("Notes.tmLanguage"), TM_LANGUAGE).unwrap();
+        let grammars = Box::leak(Box::new(Grammars::load(Some(dir.path()))));
+
+        let highlight = |name: &str, lines: &[&str]| -> Vec<String> {
+            let grammar = Grammar {
+                set: &grammars.set,
+                syntax: grammars.set.find_syntax_by_name(name).unwrap(),
+            };
+            let mut state = grammar.start_state();
+            lines
+                .iter()
+                .map(|line| runs(&grammar.highlight_line(line, &mut state)))
+                .collect()
+        };
+
+        assert_eq!(
+            highlight("Tiny", &["let x = \"a\\n", "b\" 'a"]),
+            [
+                "PrimaryKeywords 3, None 5, String 2, Escape 2",
+                "String 2, None 1, Lifetime 2"
+            ]
+        );
+        assert_eq!(
+            highlight("Notes", &["{ a", "} @run"]),
+            [
+                "Comment 3",
+                "Comment 1, None 1, PrimaryKeywords 1, Function 3"
+            ]
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::Write;
+
+/// The number of bytes shown in each row of the hex view.
+pub const BYTES_PER_ROW: usize = 16;
+/// The number of bytes at the start of a file that are checked for binary content.
+const SAMPLE_SIZE: usize = 8000;
+/// The width of the offset gutter at the start of each row, including its padding.
+pub const GUTTER_WIDTH: usize = 10;
+
+/// Checks whether the contents of a file are binary, i.e. whether they contain a null byte
+/// near their start (unless they are UTF-16 with a byte order mark).
+///
+/// # Arguments
+///
+/// * `bytes` - the contents of the file
+pub fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+        return false;
+    }
+    bytes.iter().take(SAMPLE_SIZE).any(|byte| *byte == 0)
+}
+
+/// Gets the number of rows that are needed to show the given bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes to show
+pub fn len(bytes: &[u8]) -> usize {
+    bytes.len().div_ceil(BYTES_PER_ROW)
+}
+
+/// Renders a row of the hex view, made up of the offset of its first byte, the bytes in hex and
+/// the bytes as ASCII (with `.` for unprintable bytes).
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes being shown
+/// * `index` - the index of the row
+pub fn render_row(bytes: &[u8], index: usize) -> Option<String> {
+    let start = index.checked_mul(BYTES_PER_ROW)?;
+    let row = bytes.get(start..bytes.len().min(start + BYTES_PER_ROW))?;
+    if row.is_empty() {
+        return None;
+    }
+
+    let mut rendered = format!("{start:08x}  ");
+    for byte in row {
+        let _ = write!(rendered, "{byte:02x} ");
+    }
+    let padding = (BYTES_PER_ROW - row.len()) * 3;
+    rendered.push_str(&" ".repeat(padding));
+    rendered.push_str(" |");
+    rendered.extend(row.iter().map(|byte| {
+        if byte.is_ascii_graphic() || *byte == b' ' {
+            char::from(*byte)
+        } else {
+            '.'
+        }
+    }));
+    rendered.push('|');
+    Some(rendered)
+}
+
+/// Gets the column at which a byte is shown within its row.
+///
+/// # Arguments
+///
+/// * `x` - the index of the byte within its row
+/// * `low_nibble` - whether to get the column of the byte's low (rather than high) nibble
+pub fn column(x: usize, low_nibble: bool) -> usize {
+    GUTTER_WIDTH + x * 3 + usize::from(low_nibble)
+}
+
+/// Gets the index within its row of the byte shown at the given column.
+///
+/// # Arguments
+///
+/// * `column` - the column
+pub fn index_at(column: usize) -> usize {
+    (column.saturating_sub(GUTTER_WIDTH) / 3).min(BYTES_PER_ROW)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{column, index_at, is_binary, len, render_row};
+
+    #[test]
+    fn rows() {
+        let bytes: Vec<u8> = (0..20).chain(b"Hi!".iter().copied()).collect();
+        assert!(is_binary(&bytes));
+        assert!(!is_binary(b"Hello, World!"));
+        assert_eq!(len(&bytes), 2);
+
+        assert_eq!(
+            render_row(&bytes, 1).unwrap(),
+            format!("00000010  10 11 12 13 48 69 21 {}|....Hi!|", " ".repeat(28))
+        );
+        assert_eq!(render_row(&bytes, 2), None);
+        assert_eq!(index_at(column(5, true)), 5);
+    }
+}
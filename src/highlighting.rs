@@ -1,21 +1,16 @@
-use lazy_static::lazy_static;
-use termbg::{self, Theme};
-use termion::color;
+use serde::Deserialize;
 
-lazy_static! {
-    static ref SHOULD_USE_DARK_THEME: bool = termbg::theme(std::time::Duration::from_millis(100))
-        .map_or(true, |res| {
-            match res {
-                Theme::Dark => true,
-                _ => false,
-            }
-        });
-}
+use crate::grammar::GrammarState;
 
-/// The different types of highlighting.
-#[derive(PartialEq, Clone, Copy, Debug)]
+/// The different types of highlighting, which are named in snake case (e.g. `primary_keywords`)
+/// in [themes](crate::theme::Theme).
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Type {
+    /// Text that is not highlighted otherwise
+    #[serde(rename = "text")]
     None,
+    #[serde(skip)]
     Start,
     Number,
     Match,
@@ -26,30 +21,38 @@ pub enum Type {
     MultilineComment,
     PrimaryKeywords,
     SecondaryKeywords,
+    Variable,
+    Key,
+    Heading,
+    Emphasis,
+    Escape,
+    Placeholder,
+    Lifetime,
+    Attribute,
+    Function,
+    Entity,
+    /// The offsets at the start of the rows of the hex view, and the `~` after the end of a
+    /// document
+    Gutter,
 }
 
-impl Type {
-    /// Gets the ANSI value representation of a highlighting type to be used for highlight rendering.
-    ///
-    /// For more information, a 216-color chart that was used for reference can be found here:
-    /// <https://www.web-source.net/216_color_chart.htm>
-    pub fn to_color(&self) -> color::AnsiValue {
-        match self {
-            Type::Number => color::AnsiValue::rgb(5, 1, 5),
-            Type::Match => color::AnsiValue::rgb(0, 5, 0),
-            Type::Selection => color::AnsiValue::rgb(2, 2, 5),
-            Type::String => color::AnsiValue::rgb(5, 2, 2),
-            Type::Character => color::AnsiValue::rgb(5, 4, 0),
-            Type::Comment | Type::MultilineComment => color::AnsiValue::rgb(3, 3, 3),
-            Type::PrimaryKeywords => color::AnsiValue::rgb(0, 4, 5),
-            Type::SecondaryKeywords => color::AnsiValue::rgb(0, 5, 4),
-            _ => {
-                if *SHOULD_USE_DARK_THEME {
-                    color::AnsiValue::rgb(5, 5, 5)
-                } else {
-                    color::AnsiValue::rgb(0, 0, 0)
-                }
-            }
-        }
-    }
+/// The state of the highlighter at the end of a row, i.e. the construct that is still open
+/// there and continues on the next row.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub enum State {
+    /// Nothing is open
+    #[default]
+    Normal,
+    /// A multiline comment, which is closed by `close` once as many comments as were nested
+    /// within it (`depth`) have been closed
+    Comment {
+        open: String,
+        close: String,
+        depth: usize,
+    },
+    /// A string literal that is closed by `close`, in which backslashes escape characters
+    /// unless it is raw
+    String { close: String, raw: bool },
+    /// The state of a [`Grammar`](crate::grammar::Grammar)
+    Grammar(Box<GrammarState>),
 }
@@ -0,0 +1,754 @@
+use std::collections::{BTreeMap, HashMap};
+
+use termion::event::Key;
+
+use crate::config::{EditingMode, KeyBindings};
+
+/// The context in which keys are pressed, which decides the actions that they are bound to.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
+pub enum Context {
+    /// Editing a document
+    Editor,
+    /// Typing into a prompt, e.g. the search prompt
+    Prompt,
+}
+
+impl Context {
+    /// Gets the name of this context.
+    pub fn name(self) -> &'static str {
+        match self {
+            Context::Editor => "editor",
+            Context::Prompt => "prompt",
+        }
+    }
+}
+
+/// Defines the [Action] enum along with the names under which its variants can be bound.
+macro_rules! actions {
+    ($($(#[$doc:meta])* $action:ident => $name:literal in $context:ident,)*) => {
+        /// An operation of the editor that can be bound to keys.
+        #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+        pub enum Action {
+            $($(#[$doc])* $action,)*
+            /// Inserts a character; this is what keys that are not bound to anything else do
+            Insert(char),
+        }
+
+        impl Action {
+            /// All actions that can be bound to keys.
+            pub const ALL: &'static [Action] = &[$(Action::$action,)*];
+
+            /// Gets the name under which this action is bound.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Action::$action => $name,)*
+                    Action::Insert(_) => "insert",
+                }
+            }
+
+            /// Gets the context in which this action is performed.
+            pub fn context(self) -> Context {
+                match self {
+                    $(Action::$action => Context::$context,)*
+                    Action::Insert(_) => Context::Editor,
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    /// Moves the cursor up a line
+    Up => "up" in Editor,
+    /// Moves the cursor down a line
+    Down => "down" in Editor,
+    /// Moves the cursor left a character
+    Left => "left" in Editor,
+    /// Moves the cursor right a character
+    Right => "right" in Editor,
+    /// Moves the cursor to the start of the previous word
+    WordLeft => "word_left" in Editor,
+    /// Moves the cursor to the start of the next word
+    WordRight => "word_right" in Editor,
+    /// Moves the cursor to the start of the line
+    LineLeft => "line_left" in Editor,
+    /// Moves the cursor to the end of the line
+    LineRight => "line_right" in Editor,
+    /// Moves the cursor up a page
+    PageUp => "page_up" in Editor,
+    /// Moves the cursor down a page
+    PageDown => "page_down" in Editor,
+    /// Moves the cursor to the start of the document
+    DocumentUp => "document_up" in Editor,
+    /// Moves the cursor to the end of the document
+    DocumentDown => "document_down" in Editor,
+    /// Deletes the character under the cursor
+    Delete => "delete" in Editor,
+    /// Deletes the character before the cursor
+    Backspace => "backspace" in Editor,
+    /// Inserts a null byte in a binary document
+    InsertByte => "insert_byte" in Editor,
+    /// Quits the editor
+    Quit => "quit" in Editor,
+    /// Saves the document
+    Save => "save" in Editor,
+    /// Starts searching the document
+    Search => "search" in Editor,
+    /// Replaces matches of a pattern one by one, asking whether to replace each of them
+    QueryReplace => "query_replace" in Editor,
+    /// Searches the files in the working directory and lists the matches
+    ProjectSearch => "project_search" in Editor,
+    /// Starts a selection at the cursor
+    StartSelection => "start_selection" in Editor,
+    /// Ends the selection at the cursor
+    EndSelection => "end_selection" in Editor,
+    /// Copies the selection
+    Copy => "copy" in Editor,
+    /// Cuts the selection
+    Cut => "cut" in Editor,
+    /// Pastes the clipboard
+    Paste => "paste" in Editor,
+    /// Undoes the last change
+    Undo => "undo" in Editor,
+    /// Redoes the last undone change
+    Redo => "redo" in Editor,
+    /// Moves to the previous state of the document in time
+    Earlier => "earlier" in Editor,
+    /// Moves to the next state of the document in time
+    Later => "later" in Editor,
+    /// Converts the document's line endings, indentation or encoding
+    Convert => "convert" in Editor,
+    /// Re-opens the document's file with another encoding
+    Reopen => "reopen" in Editor,
+    /// Evaluates a math expression
+    Calculator => "calculator" in Editor,
+    /// Opens a file in a new buffer
+    Open => "open" in Editor,
+    /// Switches to the next buffer
+    NextBuffer => "next_buffer" in Editor,
+    /// Switches to the previous buffer
+    PreviousBuffer => "previous_buffer" in Editor,
+    /// Lists the buffers to switch to one of them
+    BufferList => "buffer_list" in Editor,
+    /// Splits the pane into stacked panes
+    SplitHorizontal => "split_horizontal" in Editor,
+    /// Splits the pane into side-by-side panes
+    SplitVertical => "split_vertical" in Editor,
+    /// Closes the pane
+    ClosePane => "close_pane" in Editor,
+    /// Moves focus to the next pane
+    NextPane => "next_pane" in Editor,
+    /// Moves focus to the previous pane
+    PreviousPane => "previous_pane" in Editor,
+    /// Grows the pane
+    GrowPane => "grow_pane" in Editor,
+    /// Shrinks the pane
+    ShrinkPane => "shrink_pane" in Editor,
+    /// Shows the current key bindings
+    ShowBindings => "show_bindings" in Editor,
+    /// Switches to another color theme
+    Theme => "theme" in Editor,
+    /// Deletes the rest of the line (or the line break at its end) into the kill ring
+    KillLine => "kill_line" in Editor,
+    /// Deletes the text between the mark and the cursor into the kill ring
+    KillRegion => "kill_region" in Editor,
+    /// Copies the text between the mark and the cursor into the kill ring
+    CopyRegion => "copy_region" in Editor,
+    /// Inserts the newest text in the kill ring
+    Yank => "yank" in Editor,
+    /// Replaces the text that was just yanked with the previous text in the kill ring
+    YankPop => "yank_pop" in Editor,
+    /// Cancels the current chord and clears the mark
+    Cancel => "cancel" in Editor,
+    /// Moves to the next match of a search
+    SearchForward => "search_forward" in Prompt,
+    /// Moves to the previous match of a search
+    SearchBackward => "search_backward" in Prompt,
+    /// Selects the current match of a search and moves to the next one
+    SelectForward => "select_forward" in Prompt,
+    /// Selects the current match of a search and moves to the previous one
+    SelectBackward => "select_backward" in Prompt,
+    /// Selects every match of a search
+    SelectAll => "select_all" in Prompt,
+    /// Deletes the selected matches of a search
+    DeleteSelections => "delete_selections" in Prompt,
+    /// Replaces the selected matches of a search
+    ReplaceSelections => "replace_selections" in Prompt,
+    /// Switches a search between literal text and regular expressions
+    ToggleRegex => "toggle_regex" in Prompt,
+    /// Switches a search between case-sensitive and case-insensitive matching
+    ToggleCase => "toggle_case" in Prompt,
+    /// Switches a search between case-sensitive and smart-case matching
+    ToggleSmartCase => "toggle_smart_case" in Prompt,
+    /// Switches a search between matching anywhere and only matching whole words
+    ToggleWholeWord => "toggle_whole_word" in Prompt,
+}
+
+impl Action {
+    /// Gets the action with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the action
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// The default key bindings.
+const DEFAULT_BINDINGS: &[(&[Key], Action)] = &[
+    (&[Key::Up], Action::Up),
+    (&[Key::Down], Action::Down),
+    (&[Key::Left], Action::Left),
+    (&[Key::Right], Action::Right),
+    (&[Key::Alt('q')], Action::WordLeft),
+    (&[Key::Alt('w')], Action::WordRight),
+    (&[Key::Alt('b')], Action::LineLeft),
+    (&[Key::Alt('f')], Action::LineRight),
+    (&[Key::Alt('t')], Action::PageUp),
+    (&[Key::Alt('g')], Action::PageDown),
+    (&[Key::Home], Action::DocumentUp),
+    (&[Key::End], Action::DocumentDown),
+    (&[Key::Delete], Action::Delete),
+    (&[Key::Backspace], Action::Backspace),
+    (&[Key::Insert], Action::InsertByte),
+    (&[Key::Ctrl('q')], Action::Quit),
+    (&[Key::Ctrl('s')], Action::Save),
+    (&[Key::Ctrl('l')], Action::Search),
+    (&[Key::Alt('%')], Action::QueryReplace),
+    (&[Key::Ctrl('g')], Action::ProjectSearch),
+    (&[Key::Ctrl('t')], Action::StartSelection),
+    (&[Key::Ctrl('y')], Action::EndSelection),
+    (&[Key::Ctrl('c')], Action::Copy),
+    (&[Key::Ctrl('x')], Action::Cut),
+    (&[Key::Ctrl('v')], Action::Paste),
+    (&[Key::Ctrl('u')], Action::Undo),
+    (&[Key::Ctrl('r')], Action::Redo),
+    (&[Key::Alt('u')], Action::Earlier),
+    (&[Key::Alt('r')], Action::Later),
+    (&[Key::Alt('e')], Action::Convert),
+    (&[Key::Alt('o')], Action::Reopen),
+    (&[Key::Alt('c')], Action::Calculator),
+    (&[Key::Ctrl('o')], Action::Open),
+    (&[Key::Alt('n')], Action::NextBuffer),
+    (&[Key::Alt('p')], Action::PreviousBuffer),
+    (&[Key::Alt('l')], Action::BufferList),
+    (&[Key::Alt('h')], Action::Theme),
+    (&[Key::Alt('s')], Action::SplitHorizontal),
+    (&[Key::Alt('v')], Action::SplitVertical),
+    (&[Key::Alt('x')], Action::ClosePane),
+    (&[Key::Alt('j')], Action::NextPane),
+    (&[Key::Alt('k')], Action::PreviousPane),
+    (&[Key::Alt('=')], Action::GrowPane),
+    (&[Key::Alt('-')], Action::ShrinkPane),
+    (&[Key::F(1)], Action::ShowBindings),
+    (&[Key::Ctrl('n')], Action::SearchForward),
+    (&[Key::Ctrl('p')], Action::SearchBackward),
+    (&[Key::Ctrl('f')], Action::SelectForward),
+    (&[Key::Ctrl('b')], Action::SelectBackward),
+    (&[Key::Alt('a')], Action::SelectAll),
+    (&[Key::Ctrl('d')], Action::DeleteSelections),
+    (&[Key::Ctrl('r')], Action::ReplaceSelections),
+    (&[Key::Alt('r')], Action::ToggleRegex),
+    (&[Key::Alt('c')], Action::ToggleCase),
+    (&[Key::Alt('s')], Action::ToggleSmartCase),
+    (&[Key::Alt('w')], Action::ToggleWholeWord),
+];
+
+/// The key bindings of the Emacs preset.
+const EMACS_BINDINGS: &[(&[Key], Action)] = &[
+    (&[Key::Up], Action::Up),
+    (&[Key::Ctrl('p')], Action::Up),
+    (&[Key::Down], Action::Down),
+    (&[Key::Ctrl('n')], Action::Down),
+    (&[Key::Left], Action::Left),
+    (&[Key::Ctrl('b')], Action::Left),
+    (&[Key::Right], Action::Right),
+    (&[Key::Ctrl('f')], Action::Right),
+    (&[Key::Alt('b')], Action::WordLeft),
+    (&[Key::Alt('f')], Action::WordRight),
+    (&[Key::Ctrl('a')], Action::LineLeft),
+    (&[Key::Home], Action::LineLeft),
+    (&[Key::Ctrl('e')], Action::LineRight),
+    (&[Key::End], Action::LineRight),
+    (&[Key::Alt('v')], Action::PageUp),
+    (&[Key::PageUp], Action::PageUp),
+    (&[Key::Ctrl('v')], Action::PageDown),
+    (&[Key::PageDown], Action::PageDown),
+    (&[Key::Alt('<')], Action::DocumentUp),
+    (&[Key::Alt('>')], Action::DocumentDown),
+    (&[Key::Delete], Action::Delete),
+    (&[Key::Ctrl('d')], Action::Delete),
+    (&[Key::Backspace], Action::Backspace),
+    (&[Key::Insert], Action::InsertByte),
+    (&[Key::Ctrl('x'), Key::Ctrl('c')], Action::Quit),
+    (&[Key::Ctrl('x'), Key::Ctrl('s')], Action::Save),
+    (&[Key::Ctrl('s')], Action::Search),
+    (&[Key::Alt('%')], Action::QueryReplace),
+    (
+        &[Key::Ctrl('x'), Key::Char('p'), Key::Char('g')],
+        Action::ProjectSearch,
+    ),
+    (&[Key::Null], Action::StartSelection),
+    (&[Key::Ctrl('k')], Action::KillLine),
+    (&[Key::Ctrl('w')], Action::KillRegion),
+    (&[Key::Alt('w')], Action::CopyRegion),
+    (&[Key::Ctrl('y')], Action::Yank),
+    (&[Key::Alt('y')], Action::YankPop),
+    (&[Key::Ctrl('g')], Action::Cancel),
+    // Terminals send Ctrl+_ as Ctrl+7
+    (&[Key::Ctrl('7')], Action::Undo),
+    (&[Key::Ctrl('x'), Key::Char('u')], Action::Undo),
+    (&[Key::Alt('_')], Action::Redo),
+    (&[Key::Alt('u')], Action::Earlier),
+    (&[Key::Alt('r')], Action::Later),
+    (&[Key::Alt('e')], Action::Convert),
+    (&[Key::Alt('o')], Action::Reopen),
+    (&[Key::Alt('c')], Action::Calculator),
+    (&[Key::Ctrl('x'), Key::Ctrl('f')], Action::Open),
+    (&[Key::Ctrl('x'), Key::Right], Action::NextBuffer),
+    (&[Key::Ctrl('x'), Key::Left], Action::PreviousBuffer),
+    (&[Key::Ctrl('x'), Key::Char('b')], Action::BufferList),
+    (&[Key::Ctrl('x'), Key::Char('t')], Action::Theme),
+    (&[Key::Ctrl('x'), Key::Char('2')], Action::SplitHorizontal),
+    (&[Key::Ctrl('x'), Key::Char('3')], Action::SplitVertical),
+    (&[Key::Ctrl('x'), Key::Char('0')], Action::ClosePane),
+    (&[Key::Ctrl('x'), Key::Char('o')], Action::NextPane),
+    (&[Key::Ctrl('x'), Key::Char('^')], Action::GrowPane),
+    (&[Key::Ctrl('x'), Key::Char('-')], Action::ShrinkPane),
+    (&[Key::F(1)], Action::ShowBindings),
+    (&[Key::Ctrl('h'), Key::Char('b')], Action::ShowBindings),
+    (&[Key::Ctrl('s')], Action::SearchForward),
+    (&[Key::Ctrl('r')], Action::SearchBackward),
+    // C-f, C-b and C-d edit the input of the prompt, as they do in the editor
+    (&[Key::Alt('n')], Action::SelectForward),
+    (&[Key::Alt('p')], Action::SelectBackward),
+    (&[Key::Alt('a')], Action::SelectAll),
+    (&[Key::Alt('k')], Action::DeleteSelections),
+    (&[Key::Alt('%')], Action::ReplaceSelections),
+    (&[Key::Alt('r')], Action::ToggleRegex),
+    (&[Key::Alt('c')], Action::ToggleCase),
+    (&[Key::Alt('s')], Action::ToggleSmartCase),
+    (&[Key::Alt('w')], Action::ToggleWholeWord),
+];
+
+/// The result of looking up the keys pressed so far in a [Keymap].
+#[derive(PartialEq, Eq, Debug)]
+pub enum Lookup {
+    /// The keys are bound to the contained action
+    Action(Action),
+    /// The keys are the start of a longer sequence of keys (i.e. a chord)
+    Prefix,
+    /// The keys are not bound to anything
+    Unbound,
+}
+
+/// A table of the key sequences that are bound to each [Action], per [Context].
+pub struct Keymap {
+    bindings: HashMap<Context, HashMap<Vec<Key>, Action>>,
+}
+
+impl Default for Keymap {
+    /// Constructs the keymap with the default bindings.
+    fn default() -> Self {
+        Self::preset(EditingMode::Default)
+    }
+}
+
+impl Keymap {
+    /// Constructs the keymap with the bindings of an editing mode's preset, i.e. the Emacs
+    /// bindings for the Emacs mode and the default bindings otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - the [`EditingMode`] whose bindings to use
+    pub fn preset(mode: EditingMode) -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        let bindings = match mode {
+            EditingMode::Emacs => EMACS_BINDINGS,
+            EditingMode::Default | EditingMode::Vim => DEFAULT_BINDINGS,
+        };
+        for (keys, action) in bindings {
+            keymap.bind(keys.to_vec(), *action);
+        }
+        keymap
+    }
+
+    /// Constructs the keymap with the bindings of an editing mode's preset, replacing the
+    /// bindings of the actions that the user has bound to other keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - the [`EditingMode`] whose bindings to start from
+    /// * `overrides` - the keys bound by the user, by the name of the action
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an action or key is unknown, or if a key sequence is bound to an
+    /// action while also being the start of another bound key sequence
+    pub fn new(
+        mode: EditingMode,
+        overrides: &BTreeMap<String, KeyBindings>,
+    ) -> Result<Self, String> {
+        let mut keymap = Self::preset(mode);
+        for (name, bindings) in overrides {
+            let action =
+                Action::from_name(name).ok_or_else(|| format!("unknown action: {name}"))?;
+            keymap
+                .bindings
+                .entry(action.context())
+                .or_default()
+                .retain(|_, bound| *bound != action);
+            for keys in bindings.iter() {
+                keymap.bind(parse_keys(keys)?, action);
+            }
+        }
+
+        for (keys, action) in keymap.bindings.values().flatten() {
+            if keys.len() > 1 {
+                let context = &keymap.bindings[&action.context()];
+                if let Some(prefix) =
+                    (1..keys.len()).find(|len| context.contains_key(&keys[..*len]))
+                {
+                    return Err(format!(
+                        "{} is bound to {} and also starts {}",
+                        format_keys(&keys[..prefix]),
+                        context[&keys[..prefix]].name(),
+                        format_keys(keys)
+                    ));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Binds a key sequence to an action, replacing what it was bound to before.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - the key sequence
+    /// * `action` - the action to bind it to
+    fn bind(&mut self, keys: Vec<Key>, action: Action) {
+        self.bindings
+            .entry(action.context())
+            .or_default()
+            .insert(keys, action);
+    }
+
+    /// Looks up the keys pressed so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - the context in which the keys were pressed
+    /// * `keys` - the keys pressed so far
+    pub fn lookup(&self, context: Context, keys: &[Key]) -> Lookup {
+        let Some(bindings) = self.bindings.get(&context) else {
+            return Lookup::Unbound;
+        };
+        if let Some(action) = bindings.get(keys) {
+            Lookup::Action(*action)
+        } else if bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// Gets the key sequences bound to an action, shortest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - the action
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        let mut keys: Vec<&Vec<Key>> = self
+            .bindings
+            .get(&action.context())
+            .into_iter()
+            .flatten()
+            .filter_map(|(keys, bound)| (*bound == action).then_some(keys))
+            .collect();
+        keys.sort_by_key(|keys| (keys.len(), format_keys(keys)));
+        keys.into_iter().map(|keys| format_keys(keys)).collect()
+    }
+
+    /// Gets the shortest key sequence bound to an action, for hints to the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - the action
+    pub fn key_hint(&self, action: Action) -> String {
+        self.keys(action)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| format!("<{}>", action.name()))
+    }
+
+    /// Describes all bindings, one line per action, grouped by context.
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        for context in [Context::Editor, Context::Prompt] {
+            lines.push(format!("# {} keys", context.name()));
+            for action in Action::ALL
+                .iter()
+                .filter(|action| action.context() == context)
+            {
+                let keys = self.keys(*action);
+                let keys = if keys.is_empty() {
+                    "(unbound)".to_string()
+                } else {
+                    keys.join(", ")
+                };
+                lines.push(format!("{:<20} {keys}", action.name()));
+            }
+            lines.push(String::new());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Parses the description of a key, e.g. `Ctrl+Q`, `Alt+w`, `PageUp`, `Ctrl+Space` or `F5`.
+///
+/// # Arguments
+///
+/// * `key` - the description of the key
+///
+/// # Errors
+///
+/// Will return `Err` if the description does not name a key
+pub fn parse_key(key: &str) -> Result<Key, String> {
+    let invalid = || format!("invalid key: {key}");
+    let single_char = |name: &str| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(invalid()),
+        }
+    };
+
+    if key == "Ctrl+Space" {
+        // Terminals send Ctrl+Space as a null byte
+        return Ok(Key::Null);
+    }
+    if let Some(name) = key.strip_prefix("Ctrl+") {
+        return single_char(name).map(|c| Key::Ctrl(c.to_ascii_lowercase()));
+    }
+    if let Some(name) = key.strip_prefix("Alt+") {
+        return single_char(name).map(|c| Key::Alt(c.to_ascii_lowercase()));
+    }
+
+    let key = match key {
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Insert" => Key::Insert,
+        "Delete" => Key::Delete,
+        "Backspace" => Key::Backspace,
+        "BackTab" => Key::BackTab,
+        "Esc" => Key::Esc,
+        "Enter" => Key::Char('\n'),
+        "Tab" => Key::Char('\t'),
+        "Space" => Key::Char(' '),
+        _ => {
+            if let Some(number) = key.strip_prefix('F').and_then(|n| n.parse().ok()) {
+                Key::F(number)
+            } else {
+                Key::Char(single_char(key)?)
+            }
+        }
+    };
+    Ok(key)
+}
+
+/// Parses the description of a key sequence, made up of keys separated by spaces (e.g.
+/// `Ctrl+X Ctrl+S`).
+///
+/// # Arguments
+///
+/// * `keys` - the description of the key sequence
+///
+/// # Errors
+///
+/// Will return `Err` if the description is empty or does not name keys
+pub fn parse_keys(keys: &str) -> Result<Vec<Key>, String> {
+    let keys = keys
+        .split_whitespace()
+        .map(parse_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+/// Describes a key in the format that is understood by [`parse_key`].
+///
+/// # Arguments
+///
+/// * `key` - the key to describe
+pub fn format_key(key: Key) -> String {
+    match key {
+        Key::Ctrl(c) => format!("Ctrl+{}", c.to_ascii_uppercase()),
+        Key::Alt(c) => format!("Alt+{}", c.to_ascii_uppercase()),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::F(number) => format!("F{number}"),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::BackTab => "BackTab".to_string(),
+        Key::Null => "Ctrl+Space".to_string(),
+        key => format!("{key:?}"),
+    }
+}
+
+/// Describes a key sequence in the format that is understood by [`parse_keys`].
+///
+/// # Arguments
+///
+/// * `keys` - the key sequence to describe
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| format_key(*key))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use termion::event::Key;
+
+    use super::{format_keys, parse_key, parse_keys, Action, Context, Keymap, Lookup};
+    use crate::config::{EditingMode, KeyBindings};
+    use crate::minibuffer::Minibuffer;
+
+    #[test]
+    fn keys() {
+        assert_eq!(parse_key("Ctrl+Q"), Ok(Key::Ctrl('q')));
+        assert_eq!(parse_key("Alt+="), Ok(Key::Alt('=')));
+        assert_eq!(parse_key("PageDown"), Ok(Key::PageDown));
+        assert_eq!(parse_key("F12"), Ok(Key::F(12)));
+        assert_eq!(parse_key("x"), Ok(Key::Char('x')));
+        assert!(parse_key("Hyper+X").is_err());
+        assert!(parse_keys(" ").is_err());
+
+        let keys = [
+            Key::Ctrl('x'),
+            Key::Alt('w'),
+            Key::Home,
+            Key::Char(' '),
+            Key::Null,
+        ];
+        assert_eq!(format_keys(&keys), "Ctrl+X Alt+W Home Space Ctrl+Space");
+        assert_eq!(parse_keys(&format_keys(&keys)), Ok(keys.to_vec()));
+    }
+
+    #[test]
+    fn bindings() {
+        let keymap = Keymap::new(EditingMode::Default, &BTreeMap::new()).unwrap();
+        assert_eq!(
+            keymap.lookup(Context::Editor, &[Key::Ctrl('r')]),
+            Lookup::Action(Action::Redo)
+        );
+        assert_eq!(
+            keymap.lookup(Context::Prompt, &[Key::Ctrl('r')]),
+            Lookup::Action(Action::ReplaceSelections)
+        );
+        assert_eq!(keymap.keys(Action::SearchForward), vec!["Ctrl+N"]);
+
+        let overrides = BTreeMap::from([
+            (
+                "save".to_string(),
+                KeyBindings::Many(vec!["Ctrl+X Ctrl+S".to_string(), "F2".to_string()]),
+            ),
+            ("cut".to_string(), KeyBindings::One("Ctrl+K".to_string())),
+        ]);
+        let keymap = Keymap::new(EditingMode::Default, &overrides).unwrap();
+        let lookup = |keys: &[Key]| keymap.lookup(Context::Editor, keys);
+        assert_eq!(lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            lookup(&[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Action(Action::Save)
+        );
+        assert_eq!(lookup(&[Key::Ctrl('s')]), Lookup::Unbound);
+        assert_eq!(lookup(&[Key::Ctrl('k')]), Lookup::Action(Action::Cut));
+        assert!(keymap
+            .describe()
+            .contains("save                 F2, Ctrl+X Ctrl+S\n"));
+
+        // Ctrl+X cannot start a chord while it is still bound to cut
+        let conflict = BTreeMap::from([(
+            "save".to_string(),
+            KeyBindings::One("Ctrl+X Ctrl+S".to_string()),
+        )]);
+        assert!(Keymap::new(EditingMode::Default, &conflict).is_err());
+        let unknown = BTreeMap::from([("fly".to_string(), KeyBindings::One("F3".to_string()))]);
+        assert!(Keymap::new(EditingMode::Default, &unknown).is_err());
+    }
+
+    #[test]
+    fn emacs_preset() {
+        let keymap = Keymap::new(EditingMode::Emacs, &BTreeMap::new()).unwrap();
+        let lookup = |keys: &[Key]| keymap.lookup(Context::Editor, keys);
+        assert_eq!(lookup(&[Key::Ctrl('f')]), Lookup::Action(Action::Right));
+        assert_eq!(lookup(&[Key::Alt('b')]), Lookup::Action(Action::WordLeft));
+        assert_eq!(lookup(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(
+            lookup(&[Key::Ctrl('x'), Key::Ctrl('c')]),
+            Lookup::Action(Action::Quit)
+        );
+        assert_eq!(lookup(&[Key::Null]), Lookup::Action(Action::StartSelection));
+        assert_eq!(lookup(&[Key::Alt('y')]), Lookup::Action(Action::YankPop));
+        assert_eq!(keymap.key_hint(Action::Save), "Ctrl+X Ctrl+S");
+        assert_eq!(
+            keymap.lookup(Context::Prompt, &[Key::Ctrl('s')]),
+            Lookup::Action(Action::SearchForward)
+        );
+        assert_eq!(
+            keymap.lookup(Context::Prompt, &[Key::Alt('k')]),
+            Lookup::Action(Action::DeleteSelections)
+        );
+
+        // Keys that edit the input of a prompt are not taken over by the prompt's actions
+        for key in [Key::Ctrl('f'), Key::Ctrl('b'), Key::Ctrl('d')] {
+            assert_eq!(keymap.lookup(Context::Prompt, &[key]), Lookup::Unbound);
+        }
+        let mut minibuffer = Minibuffer::new("Search: ");
+        for key in [
+            Key::Char('a'),
+            Key::Char('b'),
+            Key::Ctrl('a'),
+            Key::Ctrl('d'),
+        ] {
+            minibuffer.handle(key, &keymap);
+        }
+        assert_eq!(minibuffer.text(), "b");
+
+        // Overrides apply on top of the preset
+        let overrides = BTreeMap::from([("save".to_string(), KeyBindings::One("F2".to_string()))]);
+        let keymap = Keymap::new(EditingMode::Emacs, &overrides).unwrap();
+        assert_eq!(
+            keymap.lookup(Context::Editor, &[Key::Ctrl('x'), Key::Ctrl('s')]),
+            Lookup::Unbound
+        );
+        assert_eq!(
+            keymap.lookup(Context::Editor, &[Key::Ctrl('k')]),
+            Lookup::Action(Action::KillLine)
+        );
+    }
+}
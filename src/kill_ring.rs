@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+
+/// How many kills the kill ring keeps before it forgets the oldest ones
+const KILL_RING_SIZE: usize = 60;
+
+/// The text that was killed (i.e. cut or copied) most recently, as in Emacs. Yanking inserts the
+/// newest kill, and popping replaces the text that was just yanked with the next older kill.
+#[derive(Default)]
+pub struct KillRing {
+    /// The kills, newest first
+    kills: VecDeque<String>,
+    /// The index of the kill that was yanked last
+    yank_index: usize,
+}
+
+impl KillRing {
+    /// Adds killed text to the ring, and returns the newest kill.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the killed text
+    /// * `append` - whether to append the text to the newest kill (e.g. for consecutive kills)
+    ///   rather than adding it as a new kill
+    pub fn kill(&mut self, text: String, append: bool) -> &str {
+        match self.kills.front_mut() {
+            Some(newest) if append => newest.push_str(&text),
+            _ => {
+                self.kills.push_front(text);
+                self.kills.truncate(KILL_RING_SIZE);
+            }
+        }
+        self.yank_index = 0;
+        &self.kills[0]
+    }
+
+    /// Gets the newest kill to yank it, if anything has been killed.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_index = 0;
+        self.kills.front().map(String::as_str)
+    }
+
+    /// Gets the kill before the one that was yanked last, wrapping around to the newest kill
+    /// after the oldest one.
+    pub fn pop(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yank_index = (self.yank_index + 1) % self.kills.len();
+        Some(&self.kills[self.yank_index])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::KillRing;
+
+    #[test]
+    fn kill_and_yank() {
+        let mut ring = KillRing::default();
+        assert_eq!(ring.yank(), None);
+        assert_eq!(ring.pop(), None);
+
+        ring.kill("one".to_string(), false);
+        assert_eq!(ring.kill(" two".to_string(), true), "one two");
+        ring.kill("three".to_string(), false);
+        ring.kill("four".to_string(), false);
+
+        assert_eq!(ring.yank(), Some("four"));
+        assert_eq!(ring.pop(), Some("three"));
+        assert_eq!(ring.pop(), Some("one two"));
+        assert_eq!(ring.pop(), Some("four"));
+        ring.pop();
+        assert_eq!(ring.yank(), Some("four"));
+    }
+}
@@ -0,0 +1,37 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::must_use_candidate)]
+mod buffer;
+mod commands;
+mod config;
+mod document;
+mod editor;
+mod encoding;
+mod filetype;
+mod grammar;
+mod hex;
+mod highlighting;
+mod keymap;
+mod kill_ring;
+mod loader;
+mod minibuffer;
+mod pane;
+mod project_search;
+mod prompt;
+mod row;
+mod search;
+mod terminal;
+#[cfg(test)]
+mod test_util;
+mod theme;
+mod vim;
+
+pub use document::Document;
+pub use document::LineEnding;
+pub use editor::Editor;
+pub use editor::Position;
+pub use editor::SearchDirection;
+pub use encoding::Encoding;
+pub use filetype::FileType;
+pub use filetype::HighlightingOptions;
+pub use row::Row;
+pub use terminal::Terminal;
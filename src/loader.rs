@@ -0,0 +1,241 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::os::unix::fs::FileExt;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ropey::{Rope, RopeBuilder};
+
+use crate::document::{extend_hash, HASH_SEED};
+
+/// Files at least this large (in bytes) are opened lazily.
+pub const LAZY_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// The number of bytes at the start of a lazily opened file that are used to calculate its
+/// indent.
+pub const SAMPLE_SIZE: u64 = 64 * 1024;
+/// How often the background thread publishes the lines that it has indexed.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The state that is shared between a [Loader] and its background thread.
+#[derive(Default)]
+struct Shared {
+    /// Offsets of line ends that have been indexed but not yet taken by the [Loader]
+    line_ends: Vec<u64>,
+    /// The number of bytes that have been read so far
+    bytes_read: u64,
+    /// The text and hash of the file once it has been read entirely, or the error that
+    /// stopped it from being read
+    result: Option<Result<(Rope, u64), Error>>,
+}
+
+/// The outcome of polling a [Loader].
+pub enum Poll {
+    /// The file is still being read; contains whether more lines have been indexed since
+    /// the last poll
+    Pending(bool),
+    /// The file has been read entirely into the contained text, with the contained hash
+    Finished(Rope, u64),
+    /// The file could not be read entirely
+    Failed(Error),
+}
+
+/// Streams a file into a [`Rope`] on a background thread, while indexing the offsets of its
+/// lines so that they can already be read from the file in the meantime.
+pub struct Loader {
+    /// The file being loaded
+    file: File,
+    /// The size of the file in bytes
+    size: u64,
+    /// The offset at which each indexed line starts, followed by the offset at which the last
+    /// indexed line ends
+    line_starts: Vec<u64>,
+    /// The number of bytes that had been read as of the last poll
+    bytes_read: u64,
+    /// Whether loading the file has failed
+    failed: bool,
+    /// The state shared with the background thread
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Loader {
+    /// Starts loading the specified file.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - the path of the file to load
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if I/O error encountered while attempting to open the file
+    pub fn start(filename: &str) -> Result<Self, Error> {
+        let file = File::open(filename)?;
+        let size = file.metadata()?.len();
+        let reader = file.try_clone()?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let result = load(reader, &thread_shared);
+            if let Ok(mut shared) = thread_shared.lock() {
+                shared.result = Some(result);
+            }
+        });
+
+        Ok(Self {
+            file,
+            size,
+            line_starts: vec![0],
+            bytes_read: 0,
+            failed: false,
+            shared,
+        })
+    }
+
+    /// Takes the progress that the background thread has made since the last poll.
+    pub fn poll(&mut self) -> Poll {
+        if self.failed {
+            return Poll::Pending(false);
+        }
+
+        let Ok(mut shared) = self.shared.lock() else {
+            self.failed = true;
+            return Poll::Failed(Error::other("file loader panicked"));
+        };
+        let changed = !shared.line_ends.is_empty();
+        self.line_starts.append(&mut shared.line_ends);
+        self.bytes_read = shared.bytes_read;
+
+        match shared.result.take() {
+            Some(Ok((text, hash))) => Poll::Finished(text, hash),
+            Some(Err(error)) => {
+                self.failed = true;
+                Poll::Failed(error)
+            }
+            None => Poll::Pending(changed),
+        }
+    }
+
+    /// Gets the number of lines that have been indexed so far.
+    pub fn len(&self) -> usize {
+        self.line_starts.len() - 1
+    }
+
+    /// Gets how much of the file has been read so far, as a percentage.
+    pub fn progress(&self) -> u64 {
+        self.bytes_read
+            .saturating_mul(100)
+            .checked_div(self.size)
+            .unwrap_or(100)
+    }
+
+    /// Reads an indexed line from the file, without its line ending.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the line's index
+    pub fn line(&self, index: usize) -> Option<String> {
+        let start = *self.line_starts.get(index)?;
+        let end = *self.line_starts.get(index.saturating_add(1))?;
+        let mut bytes = vec![0; usize::try_from(end - start).ok()?];
+        self.file.read_exact_at(&mut bytes, start).ok()?;
+
+        let line = String::from_utf8_lossy(&bytes);
+        Some(trim_line_ending(&line).to_string())
+    }
+}
+
+/// Reads the start of a file that is to be loaded lazily, along with whether the file ends
+/// with a newline.
+///
+/// # Arguments
+///
+/// * `filename` - the path of the file to read
+///
+/// # Errors
+///
+/// Will return `Err` if I/O error encountered while attempting to read the file
+pub fn sample(filename: &str) -> Result<(Vec<u8>, bool), Error> {
+    let file = File::open(filename)?;
+    let mut bytes = Vec::new();
+    (&file).take(SAMPLE_SIZE).read_to_end(&mut bytes)?;
+
+    let mut last = [0];
+    let size = file.metadata()?.len();
+    if size > 0 {
+        file.read_exact_at(&mut last, size - 1)?;
+    }
+    Ok((bytes, size == 0 || last[0] == b'\n'))
+}
+
+/// Reads a file line by line into a [`Rope`], publishing the offsets of line ends as it goes.
+///
+/// # Arguments
+///
+/// * `file` - the file to read
+/// * `shared` - the state shared with the [Loader]
+fn load(file: File, shared: &Arc<Mutex<Shared>>) -> Result<(Rope, u64), Error> {
+    let mut reader = BufReader::new(file);
+    let mut text = RopeBuilder::new();
+    let mut hash = HASH_SEED;
+    let mut line = Vec::new();
+    let mut line_ends = Vec::new();
+    let mut bytes_read = 0;
+    let mut last_publish = Instant::now();
+
+    loop {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+
+        hash = extend_hash(hash, &line);
+        let contents =
+            str::from_utf8(&line).map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        text.append(trim_line_ending(contents));
+        text.append("\n");
+        bytes_read += n as u64;
+        line_ends.push(bytes_read);
+
+        if last_publish.elapsed() >= PUBLISH_INTERVAL {
+            // Stop early if the loader has been dropped
+            if Arc::strong_count(shared) == 1 {
+                return Err(Error::new(
+                    ErrorKind::Interrupted,
+                    "file loader was dropped",
+                ));
+            }
+            publish(shared, &mut line_ends, bytes_read);
+            last_publish = Instant::now();
+        }
+    }
+
+    publish(shared, &mut line_ends, bytes_read);
+    Ok((text.finish(), hash))
+}
+
+/// Hands newly indexed line ends over to the [Loader].
+///
+/// # Arguments
+///
+/// * `shared` - the state shared with the [Loader]
+/// * `line_ends` - the line ends to hand over
+/// * `bytes_read` - the number of bytes that have been read so far
+fn publish(shared: &Arc<Mutex<Shared>>, line_ends: &mut Vec<u64>, bytes_read: u64) {
+    if let Ok(mut shared) = shared.lock() {
+        shared.line_ends.append(line_ends);
+        shared.bytes_read = bytes_read;
+    }
+}
+
+/// Removes the line ending (`\n` or `\r\n`) from the end of a line, if there is one.
+///
+/// # Arguments
+///
+/// * `line` - the line to trim
+fn trim_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
@@ -1,21 +1,5 @@
 #![warn(clippy::all, clippy::pedantic)]
-#![allow(clippy::must_use_candidate)]
-mod commands;
-mod document;
-mod editor;
-mod filetype;
-mod highlighting;
-mod row;
-mod terminal;
-
-pub use document::Document;
-use editor::Editor;
-pub use editor::Position;
-pub use editor::SearchDirection;
-pub use filetype::FileType;
-pub use filetype::HighlightingOptions;
-pub use row::Row;
-pub use terminal::Terminal;
+use ferro::Editor;
 
 fn main() {
     Editor::default().run();
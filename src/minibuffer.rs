@@ -0,0 +1,476 @@
+use std::fs;
+
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::keymap::{Action, Context, Keymap, Lookup};
+use crate::prompt::{HistoryKind, LineInput};
+
+/// Provides the candidates that complete the input of a [`Minibuffer`] when `Tab` is pressed.
+pub trait Completer {
+    /// Gets the candidates that complete the input, in the order to offer them in. Each
+    /// candidate replaces the whole input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - the input to complete
+    fn complete(&self, input: &str) -> Vec<String>;
+}
+
+/// Completes paths of files and directories, where directories end in a `/`. Relative paths are
+/// completed from the working directory, and hidden files are only offered for inputs that start
+/// with a `.`.
+pub struct FileCompleter;
+
+impl Completer for FileCompleter {
+    fn complete(&self, input: &str) -> Vec<String> {
+        let (dir, prefix) = match input.rfind('/') {
+            Some(index) => input.split_at(index + 1),
+            None => ("", input),
+        };
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+            return Vec::new();
+        };
+
+        let mut candidates: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let slash = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{dir}{name}{slash}"))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+/// Completes words from a fixed list, e.g. the names of commands.
+pub struct ListCompleter {
+    /// The words to complete, in order
+    words: Vec<String>,
+}
+
+impl ListCompleter {
+    /// Constructs a completer for a list of words.
+    ///
+    /// # Arguments
+    ///
+    /// * `words` - the words to complete
+    pub fn new(words: &[&str]) -> Self {
+        let mut words: Vec<String> = words.iter().map(ToString::to_string).collect();
+        words.sort();
+        Self { words }
+    }
+}
+
+impl Completer for ListCompleter {
+    fn complete(&self, input: &str) -> Vec<String> {
+        self.words
+            .iter()
+            .filter(|word| word.starts_with(input))
+            .cloned()
+            .collect()
+    }
+}
+
+/// What happened when a [`Minibuffer`] handled a key.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Response {
+    /// The input was edited, or the key did nothing
+    Continue,
+    /// The input was entered with `Enter`
+    Submit,
+    /// The prompt was cancelled with `Escape`
+    Cancel,
+    /// The key is bound to an action in the editor that the minibuffer cannot perform itself,
+    /// e.g. pasting
+    Action(Action),
+}
+
+/// The completions that `Tab` cycles through once there are several of them.
+struct Completions {
+    /// The candidates, in order
+    candidates: Vec<String>,
+    /// The index of the candidate that is in the input, if any
+    index: Option<usize>,
+}
+
+/// A prompt in the message bar, in which a line of input is edited. The keys that move the
+/// cursor or delete text in the document do the same in the input (and `Home`/`End` move to its
+/// start/end), `Ctrl+W` or `Alt+Backspace` and `Alt+D` delete the word before or after the
+/// cursor, `Up`/`Down` recall older or newer entries of the prompt's history, and `Tab` completes
+/// the input if the prompt has a [`Completer`].
+pub struct Minibuffer {
+    /// The prompt that is shown before the input
+    prompt: String,
+    /// The input entered so far
+    input: LineInput,
+    /// The kind of history that the prompt keeps, if any
+    history_kind: Option<HistoryKind>,
+    /// The entries of the prompt's history, oldest first
+    history: Vec<String>,
+    /// The provider of completions, if the input can be completed
+    completer: Option<Box<dyn Completer>>,
+    /// The completions that are being cycled through, if any
+    completions: Option<Completions>,
+    /// A message about the completions, e.g. the candidates to choose from
+    message: Option<String>,
+}
+
+impl Minibuffer {
+    /// Constructs a minibuffer with empty input.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - the prompt to show before the input
+    pub fn new(prompt: &str) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+            input: LineInput::default(),
+            history_kind: None,
+            history: Vec::new(),
+            completer: None,
+            completions: None,
+            message: None,
+        }
+    }
+
+    /// Makes the minibuffer keep a history, whose entries can be recalled.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the [`HistoryKind`] of the prompt
+    /// * `entries` - the entries of the history so far, oldest first
+    #[must_use]
+    pub fn with_history(mut self, kind: HistoryKind, entries: &[String]) -> Self {
+        self.history_kind = Some(kind);
+        self.history = entries.to_vec();
+        self
+    }
+
+    /// Makes the minibuffer complete its input with `Tab`.
+    ///
+    /// # Arguments
+    ///
+    /// * `completer` - the [`Completer`] that provides the completions
+    #[must_use]
+    pub fn with_completer(mut self, completer: impl Completer + 'static) -> Self {
+        self.completer = Some(Box::new(completer));
+        self
+    }
+
+    /// Gets the input entered so far.
+    pub fn text(&self) -> &str {
+        self.input.text()
+    }
+
+    /// Gets the kind of history that the prompt keeps, if any.
+    pub fn history_kind(&self) -> Option<HistoryKind> {
+        self.history_kind
+    }
+
+    /// Gets the message about the completions, if any.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Formats the prompt followed by the input, as it is shown in the message bar.
+    pub fn render(&self) -> String {
+        format!("{}{}", self.prompt, self.input.text())
+    }
+
+    /// Gets the column of the cursor in the message bar.
+    pub fn cursor_column(&self) -> usize {
+        self.prompt.graphemes(true).count() + self.input.cursor_column()
+    }
+
+    /// Inserts text at the cursor, e.g. text that was pasted.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to insert
+    pub fn insert(&mut self, text: &str) {
+        self.input.insert(text);
+    }
+
+    /// Clears the input.
+    pub fn clear(&mut self) {
+        self.input.set(String::new());
+    }
+
+    /// Handles a key that was pressed in the prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key that was pressed
+    /// * `keymap` - the [`Keymap`] whose bindings in the editor are used for editing the input
+    pub fn handle(&mut self, key: Key, keymap: &Keymap) -> Response {
+        if !matches!(key, Key::Char('\t') | Key::BackTab) {
+            self.completions = None;
+            self.message = None;
+        }
+
+        match key {
+            Key::Char('\n') => return Response::Submit,
+            Key::Esc => return Response::Cancel,
+            Key::Char('\t') => self.complete(true),
+            Key::BackTab => self.complete(false),
+            Key::Char(c) if !c.is_control() => self.input.insert(c.encode_utf8(&mut [0; 4])),
+            Key::Up | Key::Down => self.input.recall(&self.history, key == Key::Up),
+            Key::Left => self.input.left(),
+            Key::Right => self.input.right(),
+            Key::Home => self.input.home(),
+            Key::End => self.input.end(),
+            Key::Backspace => self.input.backspace(),
+            Key::Delete => self.input.delete(),
+            Key::Ctrl('w') | Key::Alt('\x7f') => self.input.delete_word_left(),
+            Key::Alt('d') => self.input.delete_word_right(),
+            _ => match keymap.lookup(Context::Editor, &[key]) {
+                Lookup::Action(Action::Left) => self.input.left(),
+                Lookup::Action(Action::Right) => self.input.right(),
+                Lookup::Action(Action::WordLeft) => self.input.word_left(),
+                Lookup::Action(Action::WordRight) => self.input.word_right(),
+                Lookup::Action(Action::LineLeft) => self.input.home(),
+                Lookup::Action(Action::LineRight) => self.input.end(),
+                Lookup::Action(Action::Backspace) => self.input.backspace(),
+                Lookup::Action(Action::Delete) => self.input.delete(),
+                Lookup::Action(action) => return Response::Action(action),
+                _ => (),
+            },
+        }
+        Response::Continue
+    }
+
+    /// Completes the input. A single candidate replaces the input, while several candidates
+    /// extend it to their longest common prefix and are listed in the message, after which
+    /// further presses cycle through them.
+    ///
+    /// # Arguments
+    ///
+    /// * `forward` - whether to cycle to the next rather than the previous candidate
+    fn complete(&mut self, forward: bool) {
+        if let Some(completions) = &mut self.completions {
+            let len = completions.candidates.len();
+            let index = match (completions.index, forward) {
+                (None, true) => 0,
+                (None, false) => len - 1,
+                (Some(index), true) => (index + 1) % len,
+                (Some(index), false) => (index + len - 1) % len,
+            };
+            completions.index = Some(index);
+            self.input.set(completions.candidates[index].clone());
+            return;
+        }
+
+        let Some(completer) = &self.completer else {
+            return;
+        };
+        let text = self.input.text();
+        let candidates = completer.complete(text);
+        match candidates.as_slice() {
+            [] => self.message = Some("no completions".to_string()),
+            [candidate] => self.input.set(candidate.clone()),
+            _ => {
+                // Candidates are listed without the directory (or other prefix) they share
+                let shared = text.rfind('/').map_or(0, |index| index + 1);
+                let names: Vec<&str> = candidates
+                    .iter()
+                    .map(|candidate| candidate.get(shared..).unwrap_or(candidate))
+                    .collect();
+                self.message = Some(names.join(" "));
+
+                let prefix = common_prefix(&candidates);
+                if prefix.len() > text.len() {
+                    self.input.set(prefix.to_string());
+                }
+                self.completions = Some(Completions {
+                    candidates,
+                    index: None,
+                });
+            }
+        }
+    }
+}
+
+/// Gets the longest prefix that all candidates start with.
+///
+/// # Arguments
+///
+/// * `candidates` - the candidates, of which there is at least one
+fn common_prefix(candidates: &[String]) -> &str {
+    let first = &candidates[0];
+    let len = candidates[1..].iter().fold(first.len(), |len, candidate| {
+        first[..len]
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(candidate.len()), |((index, _), _)| index)
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use termion::event::Key;
+
+    use super::{Completer, FileCompleter, ListCompleter, Minibuffer, Response};
+    use crate::config::EditingMode;
+    use crate::keymap::{Action, Keymap};
+    use crate::prompt::HistoryKind;
+    use crate::test_util::TempDir;
+
+    /// Feeds keys to a minibuffer, returning the response to the last one.
+    fn feed(minibuffer: &mut Minibuffer, keymap: &Keymap, keys: &[Key]) -> Response {
+        keys.iter()
+            .map(|key| minibuffer.handle(*key, keymap))
+            .last()
+            .unwrap()
+    }
+
+    /// Converts text to the keys that type it.
+    fn typed(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn editing() {
+        let keymap = Keymap::preset(EditingMode::Default);
+        let mut minibuffer = Minibuffer::new("Find: ");
+        feed(&mut minibuffer, &keymap, &typed("hello world"));
+        // Alt+Q moves a word left in the default preset
+        feed(
+            &mut minibuffer,
+            &keymap,
+            &[Key::Alt('q'), Key::Backspace, Key::Char('-'), Key::Home],
+        );
+        assert_eq!(minibuffer.render(), "Find: hello-world");
+        assert_eq!(minibuffer.cursor_column(), 6);
+
+        feed(
+            &mut minibuffer,
+            &keymap,
+            &[Key::Alt('d'), Key::Delete, Key::End],
+        );
+        assert_eq!(minibuffer.text(), "world");
+        feed(&mut minibuffer, &keymap, &[Key::Ctrl('w')]);
+        assert_eq!(minibuffer.text(), "");
+
+        assert_eq!(
+            feed(&mut minibuffer, &keymap, &[Key::Ctrl('v')]),
+            Response::Action(Action::Paste)
+        );
+        minibuffer.insert("pasted\ntext");
+        assert_eq!(
+            feed(&mut minibuffer, &keymap, &[Key::Char('\n')]),
+            Response::Submit
+        );
+        assert_eq!(minibuffer.text(), "pastedtext");
+        assert_eq!(
+            feed(&mut minibuffer, &keymap, &[Key::Esc]),
+            Response::Cancel
+        );
+
+        // Emacs keys edit the input in the Emacs preset
+        let keymap = Keymap::preset(EditingMode::Emacs);
+        let mut minibuffer = Minibuffer::new("Find: ");
+        feed(&mut minibuffer, &keymap, &typed("abc"));
+        feed(
+            &mut minibuffer,
+            &keymap,
+            &[
+                Key::Ctrl('a'),
+                Key::Ctrl('d'),
+                Key::Ctrl('e'),
+                Key::Char('d'),
+            ],
+        );
+        assert_eq!(minibuffer.text(), "bcd");
+    }
+
+    #[test]
+    fn history() {
+        let keymap = Keymap::preset(EditingMode::Default);
+        let entries = vec!["first".to_string(), "second".to_string()];
+        let mut minibuffer = Minibuffer::new("Find: ").with_history(HistoryKind::Search, &entries);
+        assert_eq!(minibuffer.history_kind(), Some(HistoryKind::Search));
+
+        feed(&mut minibuffer, &keymap, &typed("new"));
+        feed(&mut minibuffer, &keymap, &[Key::Up]);
+        assert_eq!(minibuffer.text(), "second");
+        feed(
+            &mut minibuffer,
+            &keymap,
+            &[Key::Up, Key::Up, Key::Backspace],
+        );
+        assert_eq!(minibuffer.text(), "firs");
+        feed(&mut minibuffer, &keymap, &[Key::Down, Key::Down]);
+        assert_eq!(minibuffer.text(), "new");
+    }
+
+    #[test]
+    fn list_completion() {
+        let keymap = Keymap::preset(EditingMode::Default);
+        let completer = ListCompleter::new(&["wq", "w", "q", "q!"]);
+        assert_eq!(completer.complete("q"), vec!["q", "q!"]);
+
+        let mut minibuffer = Minibuffer::new(":").with_completer(completer);
+        feed(&mut minibuffer, &keymap, &[Key::Char('x'), Key::Char('\t')]);
+        assert_eq!(minibuffer.message(), Some("no completions"));
+        feed(&mut minibuffer, &keymap, &[Key::Backspace, Key::Char('w')]);
+        assert_eq!(minibuffer.message(), None);
+
+        feed(&mut minibuffer, &keymap, &[Key::Char('\t')]);
+        assert_eq!(minibuffer.text(), "w");
+        assert_eq!(minibuffer.message(), Some("w wq"));
+        feed(
+            &mut minibuffer,
+            &keymap,
+            &[Key::Char('\t'), Key::Char('\t')],
+        );
+        assert_eq!(minibuffer.text(), "wq");
+        feed(&mut minibuffer, &keymap, &[Key::BackTab]);
+        assert_eq!(minibuffer.text(), "w");
+
+        // Typing ends the cycle, so that the next Tab completes the new input
+        feed(&mut minibuffer, &keymap, &[Key::Backspace, Key::Char('q')]);
+        feed(&mut minibuffer, &keymap, &[Key::Char('\t')]);
+        assert_eq!(minibuffer.text(), "q");
+        assert_eq!(minibuffer.message(), Some("q q!"));
+    }
+
+    #[test]
+    fn file_completion() {
+        let root = TempDir::new("complete");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+        fs::write(root.join("mainly.txt"), "").unwrap();
+        fs::write(root.join(".hidden"), "").unwrap();
+        fs::write(root.join("src").join("lib.rs"), "").unwrap();
+
+        let keymap = Keymap::preset(EditingMode::Default);
+        let dir = format!("{}/", root.path().display());
+        let mut minibuffer = Minibuffer::new("Save as: ").with_completer(FileCompleter);
+        minibuffer.insert(&format!("{dir}s"));
+        feed(&mut minibuffer, &keymap, &[Key::Char('\t')]);
+        assert_eq!(minibuffer.text(), format!("{dir}src/"));
+        feed(&mut minibuffer, &keymap, &[Key::Char('\t')]);
+        assert_eq!(minibuffer.text(), format!("{dir}src/lib.rs"));
+
+        minibuffer.clear();
+        minibuffer.insert(&format!("{dir}m"));
+        feed(&mut minibuffer, &keymap, &[Key::Char('\t')]);
+        assert_eq!(minibuffer.text(), format!("{dir}main"));
+        assert_eq!(minibuffer.message(), Some("main.rs mainly.txt"));
+        assert_eq!(
+            FileCompleter.complete(&format!("{dir}.")),
+            vec![format!("{dir}.hidden")]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use crate::editor::Selection;
+use crate::Position;
+
+/// The smallest share (in percent) of its split that a pane can be resized to.
+const MIN_PERCENT: usize = 10;
+
+/// The areas taken up by panes (along with their indices) and by the lines that separate them.
+pub type Areas = (Vec<(usize, Rect)>, Vec<(SplitDirection, Rect)>);
+
+/// A rectangular area of the terminal screen.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Checks whether a point on the screen lies within this area.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - the column of the point
+    /// * `y` - the row of the point
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// The state of a view onto a document, i.e. where its cursor is and which part is visible.
+#[derive(Clone, Default)]
+pub struct View {
+    /// The position of the cursor in the document
+    pub cursor_position: Position,
+    /// The offset of the visible page
+    pub offset: Position,
+    /// The maximal horizontal position that is used when the user navigates up or down
+    pub max_position: Option<usize>,
+    /// The current selection, if any
+    pub selection: Option<Selection>,
+    /// Whether the next hex digit typed in a binary document sets the low nibble of a byte
+    pub low_nibble: bool,
+}
+
+/// A pane of the editor that shows one of its buffers.
+///
+/// Like buffers, the pane that has focus has its view moved into the
+/// [Editor](crate::Editor) while it is active.
+#[derive(Default)]
+pub struct Pane {
+    /// The index of the buffer shown in this pane
+    pub buffer: usize,
+    /// The view onto the buffer's document
+    pub view: View,
+}
+
+/// The direction in which a pane is split.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SplitDirection {
+    /// The panes are stacked on top of each other, separated by a horizontal line
+    Horizontal,
+    /// The panes are placed side by side, separated by a vertical line
+    Vertical,
+}
+
+/// The arrangement of panes on the screen, as a tree of splits.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// A single pane, identified by its index
+    Pane(usize),
+    /// Two arrangements that share the available space
+    Split {
+        direction: SplitDirection,
+        /// The share of the space (in percent) that is taken up by `first`
+        percent: usize,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// Gets the indices of all panes, from top left to bottom right.
+    pub fn panes(&self) -> Vec<usize> {
+        match self {
+            Layout::Pane(pane) => vec![*pane],
+            Layout::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+
+    /// Computes the area taken up by each pane, along with the areas of the lines that separate
+    /// them.
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - the area to lay the panes out in
+    pub fn areas(&self, area: Rect) -> Areas {
+        let mut panes = Vec::new();
+        let mut separators = Vec::new();
+        self.collect_areas(area, &mut panes, &mut separators);
+        (panes, separators)
+    }
+
+    /// Computes the area taken up by a pane.
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - the area to lay the panes out in
+    /// * `pane` - the index of the pane
+    pub fn area(&self, area: Rect, pane: usize) -> Rect {
+        self.areas(area)
+            .0
+            .into_iter()
+            .find_map(|(index, rect)| (index == pane).then_some(rect))
+            .unwrap_or(area)
+    }
+
+    /// Splits a pane in two, placing a new pane after it.
+    ///
+    /// # Arguments
+    ///
+    /// * `pane` - the index of the pane to split
+    /// * `new_pane` - the index of the new pane
+    /// * `direction` - the direction to split in
+    pub fn split(&mut self, pane: usize, new_pane: usize, direction: SplitDirection) {
+        match self {
+            Layout::Pane(index) if *index == pane => {
+                *self = Layout::Split {
+                    direction,
+                    percent: 50,
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new_pane)),
+                };
+            }
+            Layout::Pane(_) => (),
+            Layout::Split { first, second, .. } => {
+                first.split(pane, new_pane, direction);
+                second.split(pane, new_pane, direction);
+            }
+        }
+    }
+
+    /// Removes a pane, giving its space to its sibling. The indices of the panes after it are
+    /// shifted down by one, so that they keep matching the positions of the panes in a list.
+    ///
+    /// # Arguments
+    ///
+    /// * `pane` - the index of the pane to remove
+    pub fn remove(&mut self, pane: usize) {
+        match self {
+            Layout::Pane(index) => {
+                if *index > pane {
+                    *index -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                let sibling = if **first == Layout::Pane(pane) {
+                    Some(std::mem::replace(second, Box::new(Layout::Pane(0))))
+                } else if **second == Layout::Pane(pane) {
+                    Some(std::mem::replace(first, Box::new(Layout::Pane(0))))
+                } else {
+                    None
+                };
+
+                if let Some(sibling) = sibling {
+                    *self = *sibling;
+                    self.remove(pane);
+                } else {
+                    first.remove(pane);
+                    second.remove(pane);
+                }
+            }
+        }
+    }
+
+    /// Grows or shrinks a pane by changing the share of the innermost split that contains it.
+    /// Returns whether the pane could be resized.
+    ///
+    /// # Arguments
+    ///
+    /// * `pane` - the index of the pane to resize
+    /// * `delta` - the change in percent, which is negative to shrink the pane
+    pub fn resize(&mut self, pane: usize, delta: isize) -> bool {
+        let Layout::Split {
+            percent,
+            first,
+            second,
+            ..
+        } = self
+        else {
+            return false;
+        };
+
+        let in_first = first.panes().contains(&pane);
+        let in_second = second.panes().contains(&pane);
+        if (in_first && first.resize(pane, delta)) || (in_second && second.resize(pane, delta)) {
+            return true;
+        }
+        let delta = if in_first {
+            delta
+        } else if in_second {
+            -delta
+        } else {
+            return false;
+        };
+        *percent = percent
+            .saturating_add_signed(delta)
+            .clamp(MIN_PERCENT, 100 - MIN_PERCENT);
+        true
+    }
+
+    /// Recursively computes the areas of the panes and separators.
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - the area to lay the panes out in
+    /// * `panes` - the areas of the panes computed so far
+    /// * `separators` - the areas of the separators computed so far
+    fn collect_areas(
+        &self,
+        area: Rect,
+        panes: &mut Vec<(usize, Rect)>,
+        separators: &mut Vec<(SplitDirection, Rect)>,
+    ) {
+        match self {
+            Layout::Pane(pane) => panes.push((*pane, area)),
+            Layout::Split {
+                direction,
+                percent,
+                first,
+                second,
+            } => {
+                let (first_area, separator, second_area) = match direction {
+                    SplitDirection::Horizontal => {
+                        let size = area.height.saturating_sub(1) * percent / 100;
+                        (
+                            Rect {
+                                height: size,
+                                ..area
+                            },
+                            Rect {
+                                y: area.y + size,
+                                height: 1.min(area.height),
+                                ..area
+                            },
+                            Rect {
+                                y: area.y + size + 1,
+                                height: area.height.saturating_sub(size + 1),
+                                ..area
+                            },
+                        )
+                    }
+                    SplitDirection::Vertical => {
+                        let size = area.width.saturating_sub(1) * percent / 100;
+                        (
+                            Rect {
+                                width: size,
+                                ..area
+                            },
+                            Rect {
+                                x: area.x + size,
+                                width: 1.min(area.width),
+                                ..area
+                            },
+                            Rect {
+                                x: area.x + size + 1,
+                                width: area.width.saturating_sub(size + 1),
+                                ..area
+                            },
+                        )
+                    }
+                };
+                separators.push((*direction, separator));
+                first.collect_areas(first_area, panes, separators);
+                second.collect_areas(second_area, panes, separators);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Layout, Rect, SplitDirection};
+
+    #[test]
+    fn split_resize_and_remove() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 81,
+            height: 21,
+        };
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(1, 2, SplitDirection::Horizontal);
+        assert_eq!(layout.panes(), vec![0, 1, 2]);
+
+        let (panes, separators) = layout.areas(area);
+        assert_eq!(panes[0].1.width, 40);
+        assert_eq!(separators[0].1.x, 40);
+        assert_eq!(
+            panes[2].1,
+            Rect {
+                x: 41,
+                y: 11,
+                width: 40,
+                height: 10
+            }
+        );
+        assert!(panes[1].1.contains(41, 0));
+        assert!(!panes[1].1.contains(41, 10));
+
+        // Growing the bottom right pane shrinks the top right pane
+        assert!(layout.resize(2, 20));
+        assert_eq!(layout.area(area, 1).height, 6);
+        assert!(!Layout::Pane(0).resize(0, 10));
+
+        layout.remove(1);
+        assert_eq!(layout.panes(), vec![0, 1]);
+        assert_eq!(layout.area(area, 1).width, 40);
+        layout.remove(0);
+        assert_eq!(layout, Layout::Pane(0));
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::path::Path;
+
+use ignore::WalkBuilder;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::search::Pattern;
+use crate::Encoding;
+use crate::Position;
+
+/// The most matches that a project-wide search lists, so that searching for very common text
+/// stays fast
+const MAX_MATCHES: usize = 10_000;
+
+/// A match of a project-wide search.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Match {
+    /// The path of the file that contains the match, relative to the searched directory
+    pub path: String,
+    /// The position of the match in the file
+    pub position: Position,
+    /// The line that contains the match, without leading and trailing whitespace
+    pub snippet: String,
+}
+
+impl Match {
+    /// Formats the match as a line of the result list, e.g. `src/main.rs:12:5: let x = 1;`,
+    /// where lines and columns are counted from 1.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}:{}:{}: {}",
+            self.path,
+            self.position.y + 1,
+            self.position.x + 1,
+            self.snippet
+        )
+    }
+}
+
+/// Searches the files in a directory and its subdirectories for a pattern, in order of their
+/// paths. Files that are ignored by `.gitignore` or `.ignore` files are skipped, as are hidden
+/// files and binary files (i.e. files that contain null bytes). Returns the matches and whether
+/// the search stopped early because there were more than [`MAX_MATCHES`].
+///
+/// # Arguments
+///
+/// * `root` - the directory to search
+/// * `pattern` - the [`Pattern`] to search for
+pub fn search_directory(root: &Path, pattern: &Pattern) -> (Vec<Match>, bool) {
+    let mut matches = Vec::new();
+    let walker = WalkBuilder::new(root)
+        .require_git(false)
+        .sort_by_file_path(Ord::cmp)
+        .build();
+    for entry in walker.filter_map(Result::ok) {
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let Ok(bytes) = fs::read(entry.path()) else {
+            continue;
+        };
+        if bytes.contains(&0) {
+            continue;
+        }
+        let Ok(text) = Encoding::detect(&bytes).decode(&bytes) else {
+            continue;
+        };
+
+        let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let path = path.to_string_lossy();
+        for (y, line) in text.lines().enumerate() {
+            for found in pattern.find_all(line) {
+                if matches.len() == MAX_MATCHES {
+                    return (matches, true);
+                }
+                matches.push(Match {
+                    path: path.to_string(),
+                    position: Position {
+                        x: line[..found.start].graphemes(true).count(),
+                        y,
+                    },
+                    snippet: line.trim().to_string(),
+                });
+            }
+        }
+    }
+    (matches, false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{search_directory, Match};
+    use crate::search::{Pattern, SearchOptions};
+    use crate::test_util::TempDir;
+    use crate::Position;
+
+    #[test]
+    fn search_directory_respects_gitignore() {
+        let root = TempDir::new("project");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(
+            root.join("notes.txt"),
+            "needle\n  a needle, another needle\n",
+        )
+        .unwrap();
+        fs::write(root.join("src").join("lib.rs"), "// é needle\n").unwrap();
+        fs::write(root.join("build.log"), "needle\n").unwrap();
+        fs::write(root.join("data.bin"), b"needle\0").unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("target").join("out.txt"), "needle\n").unwrap();
+
+        let pattern = Pattern::new("needle", SearchOptions::default()).unwrap();
+        let (matches, truncated) = search_directory(root.path(), &pattern);
+
+        assert!(!truncated);
+        let described: Vec<String> = matches.iter().map(Match::describe).collect();
+        assert_eq!(
+            described,
+            vec![
+                "notes.txt:1:1: needle",
+                "notes.txt:2:5: a needle, another needle",
+                "notes.txt:2:21: a needle, another needle",
+                "src/lib.rs:1:6: // é needle",
+            ]
+        );
+        assert_eq!(matches[3].position, Position { x: 5, y: 0 });
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::config;
+
+/// How many entries the history of each kind of prompt keeps before it forgets the oldest ones
+const HISTORY_SIZE: usize = 100;
+
+/// The line of text that is entered in a prompt, with a cursor that can be moved within it.
+#[derive(Default)]
+pub struct LineInput {
+    /// The text entered so far
+    text: String,
+    /// The byte offset of the cursor in the text, which is always at a grapheme boundary
+    cursor: usize,
+    /// How many entries back in the history the text was recalled from, or 0 if it was typed
+    recalled: usize,
+    /// The text that was typed before an entry of the history was recalled
+    draft: String,
+}
+
+impl LineInput {
+    /// Gets the text entered so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the column of the cursor, i.e. the number of graphemes before it.
+    pub fn cursor_column(&self) -> usize {
+        self.text[..self.cursor].graphemes(true).count()
+    }
+
+    /// Replaces the text, moving the cursor to its end.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the new text
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// Inserts text at the cursor and moves the cursor after it. Control characters (e.g. line
+    /// breaks in pasted text) are left out, as a prompt only holds a single line.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to insert
+    pub fn insert(&mut self, text: &str) {
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        self.text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    /// Deletes the grapheme before the cursor.
+    pub fn backspace(&mut self) {
+        let start = self.previous_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the grapheme after the cursor.
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Deletes the text from the start of the word before the cursor up to the cursor.
+    pub fn delete_word_left(&mut self) {
+        let start = self.word_start();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes the text from the cursor up to the end of the word after it.
+    pub fn delete_word_right(&mut self) {
+        let end = self.word_end();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    /// Moves the cursor one grapheme to the left.
+    pub fn left(&mut self) {
+        self.cursor = self.previous_boundary();
+    }
+
+    /// Moves the cursor one grapheme to the right.
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    /// Moves the cursor to the start of the word before it.
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    /// Moves the cursor to the end of the word after it.
+    pub fn word_right(&mut self) {
+        self.cursor = self.word_end();
+    }
+
+    /// Moves the cursor to the start of the text.
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Moves the cursor to the end of the text.
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Replaces the text with an older or newer entry of a history. Going past the newest entry
+    /// restores the text that was typed before the history was recalled.
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - the entries of the history, oldest first
+    /// * `older` - whether to recall the entry before the current one rather than the one after
+    pub fn recall(&mut self, history: &[String], older: bool) {
+        if older {
+            if self.recalled == history.len() {
+                return;
+            }
+            if self.recalled == 0 {
+                self.draft = self.text.clone();
+            }
+            self.recalled += 1;
+        } else {
+            if self.recalled == 0 {
+                return;
+            }
+            self.recalled -= 1;
+        }
+
+        let text = if self.recalled == 0 {
+            std::mem::take(&mut self.draft)
+        } else {
+            history[history.len() - self.recalled].clone()
+        };
+        self.set(text);
+    }
+
+    /// Gets the byte offset of the grapheme before the cursor.
+    fn previous_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Gets the byte offset of the grapheme after the cursor.
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    /// Gets the byte offset of the start of the word before the cursor, or of the start of the
+    /// text if there is no word before it.
+    fn word_start(&self) -> usize {
+        self.text[..self.cursor]
+            .split_word_bound_indices()
+            .rfind(|(_, segment)| is_word(segment))
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Gets the byte offset of the end of the word after the cursor, or of the end of the text
+    /// if there is no word after it.
+    fn word_end(&self) -> usize {
+        self.text[self.cursor..]
+            .split_word_bound_indices()
+            .find(|(_, segment)| is_word(segment))
+            .map_or(self.text.len(), |(index, segment)| {
+                self.cursor + index + segment.len()
+            })
+    }
+}
+
+/// Checks whether a segment of text is a word rather than whitespace or punctuation.
+///
+/// # Arguments
+///
+/// * `segment` - the segment to check
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The kinds of prompt that keep a history of their own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    /// Search queries and patterns
+    Search,
+    /// Replacements of search matches
+    Replace,
+    /// File names that documents were saved as
+    SaveAs,
+    /// Mathematical expressions for the calculator
+    Calculator,
+}
+
+/// The text that was entered in prompts before, which is kept across sessions in
+/// `$XDG_DATA_HOME/ferro/history.json` (or `~/.local/share/ferro/history.json`).
+#[derive(Default, Serialize, Deserialize)]
+pub struct PromptHistory {
+    /// The entries of each kind of prompt, oldest first
+    entries: HashMap<HistoryKind, Vec<String>>,
+}
+
+impl PromptHistory {
+    /// Loads the history of the previous sessions, or returns an empty history if there is none
+    /// or it cannot be read.
+    pub fn load() -> Self {
+        store_path()
+            .and_then(|store| Self::load_from(&store))
+            .unwrap_or_default()
+    }
+
+    /// Persists the history so that it can be recalled in later sessions.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is nowhere to store the history or if I/O error encountered
+    /// while writing it
+    pub fn save(&self) -> Result<(), Error> {
+        let store = store_path()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no directory to store history in"))?;
+        self.save_to(&store)
+    }
+
+    /// Gets the entries of a kind of prompt, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the [`HistoryKind`] of the prompt
+    pub fn entries(&self, kind: HistoryKind) -> &[String] {
+        self.entries.get(&kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds an entry to the history of a kind of prompt, moving it to the end if it was entered
+    /// before.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the [`HistoryKind`] of the prompt
+    /// * `entry` - the text that was entered
+    pub fn add(&mut self, kind: HistoryKind, entry: &str) {
+        let entries = self.entries.entry(kind).or_default();
+        entries.retain(|existing| existing != entry);
+        entries.push(entry.to_string());
+        if entries.len() > HISTORY_SIZE {
+            entries.drain(..entries.len() - HISTORY_SIZE);
+        }
+    }
+
+    /// Loads a history from the given store. See [`PromptHistory::load`].
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - the path of the file that the history is stored in
+    fn load_from(store: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(store).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// Writes the history to the given store. See [`PromptHistory::save`].
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - the path of the file to store the history in
+    fn save_to(&self, store: &Path) -> Result<(), Error> {
+        if let Some(dir) = store.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(store, serde_json::to_string(self)?)
+    }
+}
+
+/// Gets the path of the file that stores the prompt history.
+fn store_path() -> Option<PathBuf> {
+    Some(config::data_dir()?.join("history.json"))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{HistoryKind, LineInput, PromptHistory};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn line_editing() {
+        let mut input = LineInput::default();
+        input.insert("foo bär\nbaz");
+        assert_eq!(input.text(), "foo bärbaz");
+        assert_eq!(input.cursor_column(), 10);
+
+        input.word_left();
+        assert_eq!(input.cursor_column(), 4);
+        input.left();
+        input.backspace();
+        assert_eq!(input.text(), "fo bärbaz");
+        input.home();
+        input.delete();
+        input.word_right();
+        input.insert("x");
+        assert_eq!(input.text(), "ox bärbaz");
+
+        input.end();
+        input.delete_word_left();
+        assert_eq!(input.text(), "ox ");
+        input.home();
+        input.delete_word_right();
+        assert_eq!(input.text(), " ");
+        input.backspace();
+        input.left();
+        assert_eq!((input.text(), input.cursor_column()), (" ", 0));
+    }
+
+    #[test]
+    fn history() {
+        let mut history = PromptHistory::default();
+        history.add(HistoryKind::Search, "one");
+        history.add(HistoryKind::Search, "two");
+        history.add(HistoryKind::Search, "one");
+        history.add(HistoryKind::Replace, "three");
+        assert_eq!(history.entries(HistoryKind::Search), ["two", "one"]);
+        assert!(history.entries(HistoryKind::Calculator).is_empty());
+
+        let mut input = LineInput::default();
+        input.insert("dra");
+        let entries = history.entries(HistoryKind::Search);
+        input.recall(entries, true);
+        assert_eq!(input.text(), "one");
+        input.recall(entries, true);
+        input.recall(entries, true);
+        assert_eq!(input.text(), "two");
+        input.recall(entries, false);
+        input.recall(entries, false);
+        assert_eq!(input.text(), "dra");
+        input.recall(entries, false);
+        assert_eq!(input.text(), "dra");
+
+        let dir = TempDir::new("history");
+        let store = dir.join("history.json");
+        history.save_to(&store).unwrap();
+        let loaded = PromptHistory::load_from(&store).unwrap();
+        assert_eq!(loaded.entries(HistoryKind::Search), ["two", "one"]);
+        assert_eq!(loaded.entries(HistoryKind::Replace), ["three"]);
+    }
+}
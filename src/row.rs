@@ -1,14 +1,32 @@
 use std::vec;
-use termion::color;
+use termion::style;
 use unicode_segmentation::Graphemes;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::highlighting;
+use crate::grammar::Grammar;
+use crate::highlighting::{self, State};
+use crate::search::Pattern;
+use crate::theme::Theme;
 use crate::HighlightingOptions;
 use crate::SearchDirection;
 
+/// The number of graphemes between the [`Checkpoint`]s of a row.
+const CHECKPOINT_STRIDE: usize = 1024;
+
+/// The offsets at which a grapheme of a row starts, so that positions within long rows can be
+/// found without segmenting the row from its start.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+struct Checkpoint {
+    /// The index of the grapheme
+    grapheme: usize,
+    /// The byte offset of the grapheme in the row's content
+    byte: usize,
+    /// The number of chars before the grapheme
+    char: usize,
+}
+
 /// Represents a row of text within the document.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Row {
     /// Whether the row should be highlighted
     pub is_highlighted: bool,
@@ -18,93 +36,115 @@ pub struct Row {
     highlighting: Vec<highlighting::Type>,
     /// The length of the row's content
     len: usize,
+    /// Where every so many graphemes of the row start, ordered by grapheme; the start of the row
+    /// is implied
+    checkpoints: Vec<Checkpoint>,
     /// A list of tuples (start, len) of selections made in the row
     selections: Vec<[usize; 2]>,
+    /// The state of the highlighter at the start of the row, as of when it was highlighted
+    start_state: highlighting::State,
+    /// The state of the highlighter at the end of the row, as of when it was highlighted
+    end_state: highlighting::State,
 }
 
 impl Row {
-    /// Replaces all tabs in the row with spaces.
-    ///
-    /// # Arguments
-    ///
-    /// * `spaces_per_tab` - the number of spaces each tab is replaced iwth
-    pub fn replace_tabs_with_spaces(&mut self, spaces_per_tab: usize) {
-        self.string = self
-            .string
-            .replace("\t", " ".repeat(spaces_per_tab).as_str());
-    }
-
-    /// Renders the row, both the string content of the row and any highlighting.
+    /// Renders the row, both the string content of the row and any highlighting. Tabs are
+    /// expanded to the next tab stop.
     ///
     /// # Arguments
     ///
-    /// * `start` - the index to start rendering from
-    /// * `end` - the index to stop rendering at
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = end.min(self.string.len());
-        let start = start.min(end);
+    /// * `start` - the column to start rendering from
+    /// * `end` - the column to stop rendering at
+    /// * `tab_width` - the number of columns between tab stops
+    /// * `theme` - the [`Theme`] to draw the highlighting in
+    pub fn render(&self, start: usize, end: usize, tab_width: usize, theme: &Theme) -> String {
         let mut result = String::new();
         let mut current_highlighting = &highlighting::Type::Start;
+        let mut column = 0;
 
-        self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-            .for_each(|(index, grapheme)| {
-                if let Some(c) = grapheme.chars().next() {
-                    let highlighting_type = self
-                        .highlighting
-                        .get(index)
-                        .unwrap_or(&highlighting::Type::None);
-
-                    if highlighting_type != current_highlighting {
-                        current_highlighting = highlighting_type;
-                        let start_highlight =
-                            format!("{}", termion::color::Fg(highlighting_type.to_color()));
-                        result.push_str(&start_highlight[..]);
-                    }
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
+            let width = grapheme_width(grapheme, column, tab_width);
+            column += width;
+            if column <= start {
+                continue;
+            }
 
-                    if c == '\t' {
-                        result.push_str("  ");
-                    } else {
-                        result.push(c);
-                    }
+            if let Some(c) = grapheme.chars().next() {
+                let highlighting_type = self
+                    .highlighting
+                    .get(index)
+                    .unwrap_or(&highlighting::Type::None);
+
+                if highlighting_type != current_highlighting {
+                    current_highlighting = highlighting_type;
+                    result.push_str(&theme.escape(*highlighting_type));
                 }
-            });
 
-        let end_highlight = format!("{}", termion::color::Fg(color::Reset));
-        result.push_str(&end_highlight[..]);
+                if c == '\t' {
+                    // Only render the part of the tab that lies within the visible columns
+                    let visible = column.min(end) - (column - width).max(start);
+                    result.push_str(&" ".repeat(visible));
+                } else {
+                    result.push(c);
+                }
+            }
+        }
+
+        result.push_str(style::Reset.as_ref());
         result
     }
 
-    /// Inserts a character at the given position in the row.
+    /// Inserts a character at the given position in the row. The character may combine with the
+    /// graphemes around it (e.g. a combining accent), so the graphemes next to it are segmented
+    /// again to keep the length of the row right.
     ///
     /// # Arguments
     ///
     /// * `at` - the position to insert the character at
     /// * `c` - the character to insert
     pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-            self.len += 1;
-            return;
+        let location = self.locate(at);
+        let start = self.locate(location.grapheme.saturating_sub(1));
+        let end = self.locate(location.grapheme + 1);
+        self.string.insert(location.byte, c);
+        let before = end.grapheme - start.grapheme;
+        let after = self.string[start.byte..end.byte + c.len_utf8()]
+            .graphemes(true)
+            .count();
+        self.len = self.len + after - before;
+
+        // A checkpoint at the insertion no longer starts a grapheme if the character combined
+        // with the grapheme before it
+        if after != before + 1 {
+            self.checkpoints
+                .retain(|checkpoint| checkpoint.grapheme != location.grapheme);
         }
-
-        let mut result: String = String::new();
-        let mut length = 0;
-
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            length += 1;
-            if index == at {
-                length += 1;
-                result.push(c);
-            }
-            result.push_str(grapheme);
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.grapheme <= location.grapheme);
+        for checkpoint in &mut self.checkpoints[index..] {
+            checkpoint.grapheme = checkpoint.grapheme + after - before;
+            checkpoint.byte += c.len_utf8();
+            checkpoint.char += 1;
         }
 
-        self.string = result;
-        self.len = length;
+        // Typing in one place grows the gap between two checkpoints, which is split in two
+        let previous = index
+            .checked_sub(1)
+            .map_or(0, |previous| self.checkpoints[previous].grapheme);
+        let next = self
+            .checkpoints
+            .get(index)
+            .map_or(self.len, |next| next.grapheme);
+        if after == before + 1
+            && next - previous > 2 * CHECKPOINT_STRIDE
+            && location.grapheme > previous
+        {
+            self.checkpoints.insert(index, location);
+        }
     }
 
     /// Appends another row to the current row.
@@ -113,8 +153,28 @@ impl Row {
     ///
     /// * `other` - the row to append to this row
     pub fn append(&mut self, other: &Self) {
-        self.string = format!("{}{}", self.string, other.string);
-        self.len += other.len;
+        let last = self.locate(self.len.saturating_sub(1));
+        let end = self.locate(self.len);
+        let first = other.locate(1);
+        self.string.push_str(&other.string);
+
+        // The other row may start with a character that combines with the end of this row
+        let seam = end.grapheme - last.grapheme + first.grapheme;
+        let joined = seam.saturating_sub(
+            self.string[last.byte..end.byte + first.byte]
+                .graphemes(true)
+                .count(),
+        );
+        if end.grapheme > 0 && joined == 0 {
+            self.checkpoints.push(end);
+        }
+        self.checkpoints
+            .extend(other.checkpoints.iter().map(|checkpoint| Checkpoint {
+                grapheme: end.grapheme + checkpoint.grapheme - joined,
+                byte: end.byte + checkpoint.byte,
+                char: end.char + checkpoint.char,
+            }));
+        self.len = self.len + other.len - joined;
     }
 
     /// Deletes the character at the given position in the row.
@@ -124,18 +184,23 @@ impl Row {
     /// * `at` - the position of the character in the row to delete
     pub fn delete(&mut self, at: usize) {
         if at < self.len() {
-            let mut result: String = String::new();
-            let mut length = 0;
-
-            for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-                if index != at {
-                    length += 1;
-                    result.push_str(grapheme);
+            let start = self.locate(at).byte;
+            let Some(grapheme) = self.string[start..].graphemes(true).next() else {
+                return;
+            };
+            let (bytes, chars) = (grapheme.len(), grapheme.chars().count());
+            self.string.replace_range(start..start + bytes, "");
+            self.len -= 1;
+
+            for checkpoint in &mut self.checkpoints {
+                if checkpoint.grapheme > at {
+                    checkpoint.grapheme -= 1;
+                    checkpoint.byte -= bytes;
+                    checkpoint.char -= chars;
                 }
             }
-
-            self.string = result;
-            self.len = length;
+            self.checkpoints
+                .dedup_by_key(|checkpoint| checkpoint.grapheme);
         }
     }
 
@@ -146,22 +211,23 @@ impl Row {
     ///
     /// * `at` - the index in the row to split at
     pub fn split(&mut self, at: usize) -> Self {
-        let mut row: String = String::new();
-        let mut length = 0;
-        let mut splitted_row: String = String::new();
-        let mut splitted_length = 0;
-
-        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
-            if index < at {
-                length += 1;
-                row.push_str(grapheme);
-            } else {
-                splitted_length += 1;
-                splitted_row.push_str(grapheme);
-            }
-        }
+        let location = self.locate(at);
+        let splitted_row = self.string.split_off(location.byte);
+        let length = at.min(self.len);
+        let splitted_length = self.len - length;
+        let splitted_checkpoints = self
+            .checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.grapheme > location.grapheme)
+            .map(|checkpoint| Checkpoint {
+                grapheme: checkpoint.grapheme - location.grapheme,
+                byte: checkpoint.byte - location.byte,
+                char: checkpoint.char - location.char,
+            })
+            .collect();
+        self.checkpoints
+            .retain(|checkpoint| checkpoint.grapheme < location.grapheme);
 
-        self.string = row;
         self.len = length;
         self.is_highlighted = false;
         Self {
@@ -169,56 +235,132 @@ impl Row {
             string: splitted_row,
             highlighting: Vec::new(),
             len: splitted_length,
+            checkpoints: splitted_checkpoints,
             selections: Vec::new(),
+            start_state: State::Normal,
+            end_state: State::Normal,
         }
     }
 
-    /// Finds the index of a string within the row.
+    /// Gets the byte offset of the grapheme at the given index, or the length of the row's
+    /// content if the index is past the end of the row.
     ///
     /// # Arguments
     ///
-    /// * `query` - the string to find
-    /// * `at` - the index to start finding from
-    /// * `direction` - the [SearchDirection] to use
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
-            return None;
+    /// * `at` - the index of the grapheme
+    fn byte_index(&self, at: usize) -> usize {
+        if at >= self.len {
+            return self.string.len();
         }
+        self.locate(at).byte
+    }
 
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
+    /// Finds where the grapheme at the given index starts, segmenting the row from the closest
+    /// checkpoint before it. Indexes past the end of the row are clamped to the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the index of the grapheme
+    fn locate(&self, at: usize) -> Checkpoint {
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.grapheme <= at);
+        let mut location = index
+            .checked_sub(1)
+            .map_or_else(Checkpoint::default, |index| self.checkpoints[index]);
+        for grapheme in self.string[location.byte..]
+            .graphemes(true)
+            .take(at - location.grapheme)
+        {
+            location.grapheme += 1;
+            location.byte += grapheme.len();
+            location.char += grapheme.chars().count();
+        }
+        location
+    }
 
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
-        };
+    /// Gets the number of chars (Unicode scalar values) that make up the graphemes before the
+    /// given index.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the index of the grapheme
+    pub fn char_index(&self, at: usize) -> usize {
+        self.locate(at).char
+    }
 
-        let substring: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
+    /// Gets the number of graphemes that start before the given byte offset.
+    ///
+    /// # Arguments
+    ///
+    /// * `byte` - the byte offset into the row's content
+    fn grapheme_index(&self, byte: usize) -> usize {
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.byte < byte);
+        let start = index
+            .checked_sub(1)
+            .map_or_else(Checkpoint::default, |index| self.checkpoints[index]);
+        start.grapheme
+            + self.string[start.byte..]
+                .grapheme_indices(true)
+                .take_while(|(index, _)| start.byte + index < byte)
+                .count()
+    }
 
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
+    /// Finds a match of a pattern within the row, returning its index and length.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [Pattern] to find
+    /// * `at` - the index to start finding from; matches found backward start before it
+    /// * `direction` - the [SearchDirection] to use
+    pub fn find(
+        &self,
+        pattern: &Pattern,
+        at: usize,
+        direction: SearchDirection,
+    ) -> Option<(usize, usize)> {
+        if at > self.len {
+            return None;
+        }
+
+        let at = self.byte_index(at);
+        let found = if direction == SearchDirection::Forward {
+            pattern.find_from(&self.string, at)
         } else {
-            substring.rfind(query)
-        };
+            pattern.find_before(&self.string, at)
+        }?;
+        let start = self.grapheme_index(found.start);
+        Some((start, self.grapheme_index(found.end) - start))
+    }
 
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
-            {
-                if matching_byte_index == byte_index {
-                    return Some(start + grapheme_index);
-                }
-            }
-        }
-        None
+    /// Finds every match of a pattern within the row, returning their indices and lengths.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [Pattern] to find
+    pub fn find_all(&self, pattern: &Pattern) -> Vec<(usize, usize)> {
+        pattern
+            .find_all(&self.string)
+            .map(|found| {
+                let start = self.grapheme_index(found.start);
+                (start, self.grapheme_index(found.end) - start)
+            })
+            .collect()
+    }
+
+    /// Expands a replacement for a match of a pattern within the row, see [`Pattern::expand`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - the [Pattern] that matched
+    /// * `at` - the index of the match
+    /// * `matched` - the text that was matched
+    /// * `replacement` - the replacement to expand
+    pub fn expand(&self, pattern: &Pattern, at: usize, matched: &str, replacement: &str) -> String {
+        let start = self.byte_index(at);
+        pattern.expand(&self.string, start..start + matched.len(), replacement)
     }
 
     /// Finds the index of the next word in the row.
@@ -246,7 +388,7 @@ impl Row {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// let row = Row::from("Foo Bar");
     /// assert_eq!(row.find_word_forward(1), Some(4));
     /// ```
@@ -287,7 +429,7 @@ impl Row {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// let row = Row::from("Foo Bar");
     /// assert_eq!(row.find_word_backward(5), Some(3));
     /// ```
@@ -485,27 +627,16 @@ impl Row {
         )
     }
 
-    /// Highlights any matches in the row.
+    /// Highlights every match of a pattern in the row.
     ///
     /// # Arguments
     ///
-    /// * `word` - the word to highlight
-    fn highlight_match(&mut self, word: &Option<String>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
-            }
-
-            let mut index = 0;
-            while let Some(search_match) = self.find(word, index, SearchDirection::Forward) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    for i in search_match..next_index {
-                        self.highlighting[i] = highlighting::Type::Match;
-                    }
-                    index = next_index;
-                } else {
-                    break;
+    /// * `pattern` - the pattern to highlight
+    fn highlight_match(&mut self, pattern: &Option<Pattern>) {
+        if let Some(pattern) = pattern {
+            for (at, len) in self.find_all(pattern) {
+                for i in at..at + len {
+                    self.highlighting[i] = highlighting::Type::Match;
                 }
             }
         }
@@ -589,102 +720,173 @@ impl Row {
         false
     }
 
-    /// Checks whether there is a multiline comment to be highlighted in this row. If so,
-    /// this function returns the delimiter that closes the multiline comment.
+    /// Checks whether a multiline comment opens at the given index, and if so, highlights its
+    /// opening delimiter. Returns the state of the highlighter within the comment.
     ///
     /// # Arguments
     ///
     /// * `index` - the index to check from; this gets updated to the end of the highlight
-    /// * `opts` - the [HighlightingOptions] to use
-    /// * `_` - the character at `chars[index]`
+    /// * `opts` - the [`HighlightingOptions`] to use
     /// * `chars` - the characters in the row
-    pub fn highlight_multiline_comment(
+    fn open_multiline_comment(
         &mut self,
         index: &mut usize,
         opts: &HighlightingOptions,
-        _: char,
         chars: &[char],
-    ) -> Option<String> {
-        if let Some(multiline_comment_delims) = opts.multiline_comments() {
-            // Check for presence of possible opening delims for a multiline comment
-            let substring: String = self.string.graphemes(true).skip(*index).collect();
-            for (opening_delim, closing_delim) in multiline_comment_delims {
-                if let Some(k) = substring.find(opening_delim) {
-                    if k != 0 {
-                        continue;
-                    }
-
-                    // closing_index is the index after the closing delim, or the end of the line (if no closing delim)
-                    let mut multiline_is_closed = false;
-                    let substring_after_delim: String = substring
-                        .graphemes(true)
-                        .skip(opening_delim.len())
-                        .collect();
-                    let closing_index =
-                        if let Some(closing_index) = substring_after_delim.find(closing_delim) {
-                            multiline_is_closed = true;
-                            *index + opening_delim.len() + closing_index + closing_delim.len()
-                        } else {
-                            chars.len()
-                        };
-
-                    for _ in *index..closing_index {
-                        self.highlighting.push(highlighting::Type::MultilineComment);
-                        *index += 1;
-                    }
-
-                    return if multiline_is_closed {
-                        Some(String::new())
-                    } else {
-                        Some(closing_delim.to_string())
-                    };
-                }
+    ) -> Option<State> {
+        for (open, close) in opts.multiline_comments().iter().flatten() {
+            if self.highlight_str(index, open, chars, highlighting::Type::MultilineComment) {
+                return Some(State::Comment {
+                    open: open.clone(),
+                    close: close.clone(),
+                    depth: 0,
+                });
             }
         }
 
         None
     }
 
-    /// Checks whether there is a string literal to be highlighted.
+    /// Checks whether a string literal opens at the given index, and if so, highlights its
+    /// opening delimiter. Returns the state of the highlighter within the string.
     ///
     /// # Arguments
     ///
     /// * `index` - the index to check from; this gets updated to the end of the highlight
-    /// * `opts` - the [HighlightingOptions] to use
+    /// * `opts` - the [`HighlightingOptions`] to use
     /// * `c` - the character at `chars[index]`
     /// * `chars` - the characters in the row
-    pub fn highlight_string(
+    fn open_string(
         &mut self,
         index: &mut usize,
         opts: &HighlightingOptions,
         c: char,
         chars: &[char],
-    ) -> bool {
-        if let Some(string_delims) = opts.strings() {
-            for string_delim in string_delims {
-                if c == *string_delim {
-                    let mut prev_char = c;
-                    loop {
-                        self.highlighting.push(highlighting::Type::String);
-                        *index += 1;
-                        if let Some(next_char) = chars.get(*index) {
-                            if prev_char != '\\' && *next_char == *string_delim {
-                                break;
-                            }
-                            prev_char = *next_char;
-                        } else {
-                            break;
+    ) -> Option<State> {
+        if opts.raw_strings() && (*index == 0 || is_word_separator(chars[*index - 1])) {
+            // e.g. r"...", r#"..."# or br"..."
+            let prefix = if chars[*index..].starts_with(&['b', 'r']) {
+                2
+            } else {
+                usize::from(c == 'r')
+            };
+            let hashes = chars[*index + prefix..]
+                .iter()
+                .take_while(|c| **c == '#')
+                .count();
+            if prefix > 0 && chars.get(*index + prefix + hashes) == Some(&'"') {
+                for _ in 0..=prefix + hashes {
+                    self.highlighting.push(highlighting::Type::String);
+                    *index += 1;
+                }
+                return Some(State::String {
+                    close: format!("\"{}", "#".repeat(hashes)),
+                    raw: true,
+                });
+            }
+        }
+
+        for delim in opts.multiline_strings().iter().flatten() {
+            if delim.chars().count() > 1
+                && self.highlight_str(index, delim, chars, highlighting::Type::String)
+            {
+                return Some(State::String {
+                    close: delim.clone(),
+                    raw: false,
+                });
+            }
+        }
+
+        if opts
+            .strings()
+            .as_ref()
+            .is_some_and(|delims| delims.contains(&c))
+        {
+            self.highlighting.push(highlighting::Type::String);
+            *index += 1;
+            return Some(State::String {
+                close: c.to_string(),
+                raw: false,
+            });
+        }
+
+        None
+    }
+
+    /// Highlights the rest of a multiline comment or string literal that is open at the given
+    /// index, up to where it is closed or to the end of the row. Returns the state of the
+    /// highlighter afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index to continue from; this gets updated to the end of the highlight
+    /// * `opts` - the [`HighlightingOptions`] to use
+    /// * `chars` - the characters in the row
+    /// * `state` - the state of the highlighter at `index`
+    fn continue_state(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        chars: &[char],
+        state: State,
+    ) -> State {
+        match state {
+            State::Normal | State::Grammar(_) => State::Normal,
+            State::Comment {
+                open,
+                close,
+                mut depth,
+            } => {
+                let hl_type = highlighting::Type::MultilineComment;
+                while *index < chars.len() {
+                    if self.highlight_str(index, &close, chars, hl_type) {
+                        if depth == 0 {
+                            return State::Normal;
                         }
+                        depth -= 1;
+                    } else if opts.nested_comments()
+                        && self.highlight_str(index, &open, chars, hl_type)
+                    {
+                        depth += 1;
+                    } else {
+                        self.highlighting.push(hl_type);
+                        *index += 1;
                     }
-
+                }
+                State::Comment { open, close, depth }
+            }
+            State::String { close, raw } => {
+                // Variables are interpolated in double-quoted strings only, like in shells
+                let interpolate = opts.variables() && close == "\"";
+                while *index < chars.len() {
+                    if self.highlight_str(index, &close, chars, highlighting::Type::String) {
+                        return State::Normal;
+                    }
+                    if interpolate && self.highlight_variable(index, opts, chars) {
+                        continue;
+                    }
+                    let escaped = !raw && chars[*index] == '\\';
                     self.highlighting.push(highlighting::Type::String);
                     *index += 1;
-                    return true;
+                    if escaped && *index < chars.len() {
+                        self.highlighting.push(highlighting::Type::String);
+                        *index += 1;
+                    }
+                }
+
+                // Other strings end with the row, even if they are not closed
+                let spans_rows = raw
+                    || opts
+                        .multiline_strings()
+                        .as_ref()
+                        .is_some_and(|delims| delims.contains(&close));
+                if spans_rows {
+                    State::String { close, raw }
+                } else {
+                    State::Normal
                 }
             }
         }
-
-        false
     }
 
     /// Checks whether there is a number literal to be highlighted.
@@ -728,80 +930,294 @@ impl Row {
         }
     }
 
-    /// Computes the highlighting (if any) of every grapheme in this row.
+    /// Checks whether there is a shell-style variable (`$VAR`, `${VAR}`, `$1` or `$?`) to be
+    /// highlighted.
     ///
     /// # Arguments
     ///
-    /// * `opts` - the `HighlightingOptions` to use
-    /// * `word` - the word to highlight (if any)
-    /// * `look_for_multiline_close` - until this delimiter is found, graphemes are highlighted as a multiline comment.
-    ///     This function changes this accordingly whenever a multiline comment opens or closes.
-    pub fn highlight(
+    /// * `index` - the index to check from; this gets updated to the end of the highlight
+    /// * `opts` - the [`HighlightingOptions`] to use
+    /// * `chars` - the characters in the row
+    pub fn highlight_variable(
         &mut self,
+        index: &mut usize,
         opts: &HighlightingOptions,
-        word: &Option<String>,
-        look_for_multiline_close: &mut Option<String>,
-    ) {
-        let chars: Vec<char> = self.string.chars().collect();
-        if self.is_highlighted && word.is_none() {
-            *look_for_multiline_close = None;
-            return;
+        chars: &[char],
+    ) -> bool {
+        if !opts.variables() || chars.get(*index) != Some(&'$') {
+            return false;
         }
 
-        self.highlighting = Vec::new();
-        let mut index = 0;
+        let start = *index + 1;
+        let end = match chars.get(start) {
+            Some('{') => chars[start..]
+                .iter()
+                .position(|c| *c == '}')
+                .map_or(chars.len(), |len| start + len + 1),
+            Some(c) if c.is_alphabetic() || *c == '_' => chars[start..]
+                .iter()
+                .position(|c| !c.is_alphanumeric() && *c != '_')
+                .map_or(chars.len(), |len| start + len),
+            Some(c) if c.is_ascii_digit() || "#?@*!$-".contains(*c) => start + 1,
+            _ => return false,
+        };
 
-        // If the line is part of a multiline comment, we first look for a possible closing delim
-        // on this line. We don't need to do special highlight checks for anything until that point.
-        if let Some(closing_delim) = look_for_multiline_close {
-            let mut multiline_is_closed = false;
-            let closing_index =
-                if let Some(closing_index) = self.string.find(&String::clone(closing_delim)) {
-                    multiline_is_closed = true;
-                    closing_index + closing_delim.len()
-                } else {
-                    chars.len()
-                };
-            for _ in 0..closing_index {
-                self.highlighting.push(highlighting::Type::MultilineComment);
+        for _ in *index..end {
+            self.highlighting.push(highlighting::Type::Variable);
+            *index += 1;
+        }
+        true
+    }
+
+    /// Checks whether there is a key to be highlighted, i.e. a quoted string or a bare word at
+    /// the start of the row that is followed by one of the key separators (e.g. `name =` in
+    /// TOML, `"name":` in JSON or `- name:` in YAML).
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index to check from; this gets updated to the end of the highlight
+    /// * `opts` - the [`HighlightingOptions`] to use
+    /// * `c` - the character at `chars[index]`
+    /// * `chars` - the characters in the row
+    pub fn highlight_key(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        let Some(separators) = opts.key_separators() else {
+            return false;
+        };
+
+        let is_quoted = opts
+            .strings()
+            .as_ref()
+            .is_some_and(|delims| delims.contains(&c));
+        let end = if is_quoted {
+            let mut end = *index + 1;
+            while end < chars.len() && (chars[end] != c || chars[end - 1] == '\\') {
+                end += 1;
+            }
+            if end == chars.len() {
+                return false;
             }
-            index = closing_index;
+            end + 1
+        } else if is_key_char(c)
+            && chars[..*index]
+                .iter()
+                .all(|c| c.is_whitespace() || *c == '-')
+        {
+            chars[*index..]
+                .iter()
+                .position(|c| !is_key_char(*c))
+                .map_or(chars.len(), |len| *index + len)
+        } else {
+            return false;
+        };
+
+        let separator = chars[end..].iter().find(|c| !c.is_whitespace());
+        if !separator.is_some_and(|separator| separators.contains(separator)) {
+            return false;
+        }
+
+        for _ in *index..end {
+            self.highlighting.push(highlighting::Type::Key);
+            *index += 1;
+        }
+        true
+    }
 
-            if multiline_is_closed {
-                *look_for_multiline_close = None;
+    /// Checks whether there is Markdown markup to be highlighted, i.e. a heading (which spans
+    /// the whole row) or text emphasized with `*`, `**`, `_` or `__`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the index to check from; this gets updated to the end of the highlight
+    /// * `opts` - the [`HighlightingOptions`] to use
+    /// * `c` - the character at `chars[index]`
+    /// * `chars` - the characters in the row
+    pub fn highlight_markup(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightingOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.markup() {
+            return false;
+        }
+
+        if *index == 0 && c == '#' {
+            let level = chars.iter().take_while(|c| **c == '#').count();
+            if level <= 6 && chars.get(level).is_none_or(|c| c.is_whitespace()) {
+                for _ in 0..chars.len() {
+                    self.highlighting.push(highlighting::Type::Heading);
+                    *index += 1;
+                }
+                return true;
             }
+            return false;
         }
 
+        if c != '*' && c != '_' {
+            return false;
+        }
+        if *index > 0 && !is_word_separator(chars[*index - 1]) {
+            return false;
+        }
+        let run = if chars.get(*index + 1) == Some(&c) {
+            2
+        } else {
+            1
+        };
+        let text_start = *index + run;
+        if chars
+            .get(text_start)
+            .is_none_or(|c| c.is_whitespace() || *c == '*' || *c == '_')
+        {
+            return false;
+        }
+
+        let closing = (text_start + 1..=chars.len().saturating_sub(run)).find(|&at| {
+            chars[at..at + run].iter().all(|d| *d == c)
+                && !chars[at - 1].is_whitespace()
+                && chars.get(at + run).is_none_or(|next| *next != c)
+                && (c == '*'
+                    || chars
+                        .get(at + run)
+                        .is_none_or(|next| is_word_separator(*next)))
+        });
+        let Some(closing) = closing else {
+            return false;
+        };
+
+        for _ in *index..closing + run {
+            self.highlighting.push(highlighting::Type::Emphasis);
+            *index += 1;
+        }
+        true
+    }
+
+    /// Computes the highlighting (if any) of every grapheme in this row, and returns the state
+    /// of the highlighter at the end of the row. If the row was already highlighted from the
+    /// same state, the highlighting is kept as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - the `HighlightingOptions` to use
+    /// * `pattern` - the search pattern to highlight matches of (if any)
+    /// * `start` - the state of the highlighter at the end of the previous row
+    pub fn highlight(
+        &mut self,
+        opts: &HighlightingOptions,
+        pattern: &Option<Pattern>,
+        start: &State,
+    ) -> State {
+        if self.is_highlighted && pattern.is_none() && self.start_state == *start {
+            return self.end_state.clone();
+        }
+
+        let chars: Vec<char> = self.string.chars().collect();
+        self.highlighting = Vec::new();
+        let mut index = 0;
+
+        // A comment or string that is open at the end of the previous row continues on this
+        // row, so nothing else is highlighted until it is closed
+        let mut state = self.continue_state(&mut index, opts, &chars, start.clone());
+
         while let Some(c) = chars.get(index) {
-            if let Some(closing_delim) =
-                self.highlight_multiline_comment(&mut index, opts, *c, &chars)
-            {
-                *look_for_multiline_close = Some(closing_delim);
+            if let Some(opened) = self.open_multiline_comment(&mut index, opts, &chars) {
+                state = self.continue_state(&mut index, opts, &chars, opened);
                 continue;
             }
-            *look_for_multiline_close = None;
-            if self.highlight_char(&mut index, opts, *c, &chars)
+            if self.highlight_markup(&mut index, opts, *c, &chars)
+                || self.highlight_char(&mut index, opts, *c, &chars)
+                || self.highlight_variable(&mut index, opts, &chars)
                 || self.highlight_comment(&mut index, opts, *c, &chars)
+                || self.highlight_key(&mut index, opts, *c, &chars)
                 || self.highlight_primary_keywords(&mut index, opts, &chars)
                 || self.highlight_secondary_keywords(&mut index, opts, &chars)
-                || self.highlight_string(&mut index, opts, *c, &chars)
-                || self.highlight_number(&mut index, opts, *c, &chars)
             {
                 continue;
             }
+            if let Some(opened) = self.open_string(&mut index, opts, *c, &chars) {
+                state = self.continue_state(&mut index, opts, &chars, opened);
+                continue;
+            }
+            if self.highlight_number(&mut index, opts, *c, &chars) {
+                continue;
+            }
 
             self.highlighting.push(highlighting::Type::None);
             index += 1;
         }
 
-        self.highlight_match(word);
-        self.highlight_selection();
+        self.highlight_match(pattern);
+        self.finish_highlight(start, state)
+    }
 
-        if let Some(_) = look_for_multiline_close {
-            return;
+    /// Computes the highlighting of every grapheme in this row with a [`Grammar`], and returns
+    /// the state of the grammar at the end of the row. If the row was already highlighted from
+    /// the same state, the highlighting is kept as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `grammar` - the [`Grammar`] to use
+    /// * `pattern` - the search pattern to highlight matches of (if any)
+    /// * `start` - the state of the highlighter at the end of the previous row
+    pub fn highlight_with_grammar(
+        &mut self,
+        grammar: Grammar,
+        pattern: &Option<Pattern>,
+        start: &State,
+    ) -> State {
+        if self.is_highlighted && pattern.is_none() && self.start_state == *start {
+            return self.end_state.clone();
         }
 
+        let mut state = match start {
+            State::Grammar(state) => (**state).clone(),
+            _ => grammar.start_state(),
+        };
+        self.highlighting = grammar.highlight_line(&self.string, &mut state);
+        self.highlight_match(pattern);
+        self.finish_highlight(start, State::Grammar(Box::new(state)))
+    }
+
+    /// Highlights the leading columns of the row as the gutter, e.g. the offsets in the hex view,
+    /// unless they are highlighted as search matches or selections.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - the number of graphemes that the gutter takes up
+    pub fn highlight_gutter(&mut self, width: usize) {
+        for hl_type in self.highlighting.iter_mut().take(width) {
+            if *hl_type == highlighting::Type::None {
+                *hl_type = highlighting::Type::Gutter;
+            }
+        }
+    }
+
+    /// Highlights selections on top of the syntax highlighting of the row, and remembers the
+    /// states that the row was highlighted between. Returns the state at the end of the row.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the state of the highlighter at the start of the row
+    /// * `end` - the state of the highlighter at the end of the row
+    fn finish_highlight(&mut self, start: &State, end: State) -> State {
+        self.highlight_selection();
+
+        self.start_state = start.clone();
+        self.end_state = end.clone();
         self.is_highlighted = true;
+        end
+    }
+
+    /// Gets the state of the highlighter at the end of the row, if the row is highlighted.
+    pub fn end_state(&self) -> Option<&State> {
+        self.is_highlighted.then_some(&self.end_state)
     }
 
     /// Gets the length of the row.
@@ -816,18 +1232,51 @@ impl Row {
 
     /// Gets the number of leading spaces in the row.
     pub fn get_leading_spaces(&self) -> Option<usize> {
-        let mut index = 0;
-        let chars: Vec<char> = self.string.chars().collect();
+        leading_spaces(&self.string)
+    }
 
-        while index < self.string.len() && chars[index] == ' ' {
-            index += 1;
+    /// Gets the leading whitespace of the row, which is empty if the row is made up entirely
+    /// of whitespace.
+    pub fn leading_whitespace(&self) -> &str {
+        let trimmed = self.string.trim_start_matches([' ', '\t']);
+        if trimmed.is_empty() {
+            ""
+        } else {
+            &self.string[..self.string.len() - trimmed.len()]
         }
+    }
 
-        return if index == self.string.len() || index == 0 {
-            None
-        } else {
-            Some(index)
-        };
+    /// Gets the column at which the grapheme at the given index is rendered.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - the grapheme index
+    /// * `tab_width` - the number of columns between tab stops
+    pub fn column(&self, at: usize, tab_width: usize) -> usize {
+        self.string
+            .graphemes(true)
+            .take(at)
+            .fold(0, |column, grapheme| {
+                column + grapheme_width(grapheme, column, tab_width)
+            })
+            + at.saturating_sub(self.len)
+    }
+
+    /// Gets the index of the grapheme that is rendered at the given column.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - the column
+    /// * `tab_width` - the number of columns between tab stops
+    pub fn index_at(&self, column: usize, tab_width: usize) -> usize {
+        let mut current = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            current += grapheme_width(grapheme, current, tab_width);
+            if current > column {
+                return index;
+            }
+        }
+        self.len
     }
 
     /// Gets the row's contents as [Graphemes].
@@ -844,33 +1293,104 @@ impl ToString for Row {
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
+        let mut checkpoints = Vec::new();
+        let mut location = Checkpoint::default();
+        for grapheme in slice.graphemes(true) {
+            if location.grapheme > 0 && location.grapheme % CHECKPOINT_STRIDE == 0 {
+                checkpoints.push(location);
+            }
+            location.grapheme += 1;
+            location.byte += grapheme.len();
+            location.char += grapheme.chars().count();
+        }
+
         Self {
             is_highlighted: false,
             string: String::from(slice),
             highlighting: Vec::new(),
-            len: slice.graphemes(true).count(),
+            len: location.grapheme,
+            checkpoints,
             selections: Vec::new(),
+            start_state: State::Normal,
+            end_state: State::Normal,
         }
     }
 }
 
+/// Gets the number of leading spaces in a string, or `None` if the string has no leading spaces
+/// or is made up entirely of spaces.
+///
+/// # Arguments
+///
+/// * `string` - the string to check
+pub fn leading_spaces(string: &str) -> Option<usize> {
+    let count = string.chars().take_while(|c| *c == ' ').count();
+
+    if count == string.len() || count == 0 {
+        None
+    } else {
+        Some(count)
+    }
+}
+
+/// Gets the number of columns that a grapheme is rendered in.
+///
+/// # Arguments
+///
+/// * `grapheme` - the grapheme
+/// * `column` - the column at which the grapheme starts
+/// * `tab_width` - the number of columns between tab stops
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        tab_width - column % tab_width
+    } else {
+        1
+    }
+}
+
 fn is_word_separator(c: char) -> bool {
     (c.is_ascii_punctuation() && c != '_') || c.is_ascii_whitespace()
 }
 
+/// Checks whether a character can be part of a bare key, e.g. `build-dependencies` or
+/// `package.name`.
+///
+/// # Arguments
+///
+/// * `c` - the character to check
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
 #[cfg(test)]
 mod test {
-    use crate::highlighting::Type;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::highlighting::{State, Type};
     use crate::row::Row;
+    use crate::search::{Pattern, SearchOptions};
+    use crate::test_util::runs;
+    use crate::theme::Theme;
     use crate::{FileType, SearchDirection};
 
     #[test]
     fn basics() {
         let mut row = Row::from("Hello, World!");
         assert_eq!(row.len(), "Hello, World!".len());
-        assert_eq!(row.find("ello", 0, SearchDirection::Forward), Some(1));
-        assert_eq!(row.find("ello", 2, SearchDirection::Forward), None);
-        assert_eq!(row.find("ello", 8, SearchDirection::Backward), Some(1));
+        let ello = Pattern::new("ello", SearchOptions::default()).unwrap();
+        assert_eq!(row.find(&ello, 0, SearchDirection::Forward), Some((1, 4)));
+        assert_eq!(row.find(&ello, 2, SearchDirection::Forward), None);
+        assert_eq!(row.find(&ello, 8, SearchDirection::Backward), Some((1, 4)));
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let words = Pattern::new(r"\w+", regex).unwrap();
+        assert_eq!(row.find(&words, 1, SearchDirection::Forward), Some((1, 4)));
+        assert_eq!(row.find(&words, 6, SearchDirection::Forward), Some((7, 5)));
+        assert_eq!(row.find(&words, 7, SearchDirection::Backward), Some((0, 5)));
+        assert_eq!(row.find_all(&words), vec![(0, 5), (7, 5)]);
 
         row = Row::from("    x = 3;");
         assert_eq!(row.get_leading_spaces(), Some(4));
@@ -879,6 +1399,22 @@ mod test {
         assert_eq!(row.get_leading_spaces(), None);
     }
 
+    #[test]
+    fn tabs() {
+        let row = Row::from("\tab\tc");
+        assert_eq!(row.leading_whitespace(), "\t");
+        assert_eq!(row.column(1, 4), 4);
+        assert_eq!(row.column(3, 4), 6);
+        assert_eq!(row.column(4, 4), 8);
+        assert_eq!(row.index_at(2, 4), 0);
+        assert_eq!(row.index_at(7, 4), 3);
+        assert_eq!(row.index_at(9, 4), 5);
+
+        let rendered = row.render(2, 7, 4, &Theme::default());
+        assert!(rendered.contains("  ab "));
+        assert!(!rendered.contains('\t'));
+    }
+
     #[test]
     fn edit() {
         let mut row1 = Row::from("Hello ");
@@ -904,6 +1440,49 @@ mod test {
         assert_eq!(row4.len(), 7);
     }
 
+    #[test]
+    fn edit_long_row() {
+        let naive = |row: &Row, at: usize| {
+            let byte = row
+                .string
+                .grapheme_indices(true)
+                .nth(at)
+                .map_or(row.string.len(), |(index, _)| index);
+            (byte, row.string[..byte].chars().count())
+        };
+        let check = |row: &Row| {
+            assert_eq!(row.len, row.string.graphemes(true).count());
+            for at in (0..row.len).step_by(331).chain([row.len]) {
+                assert_eq!((row.byte_index(at), row.char_index(at)), naive(row, at));
+                assert_eq!(row.grapheme_index(naive(row, at).0), at);
+            }
+        };
+
+        let mut row = Row::from("aé\u{1f600}e\u{301}".repeat(2000).as_str());
+        check(&row);
+        for i in 0..3000 {
+            row.insert(3000 + i, if i % 2 == 0 { 'ß' } else { 'x' });
+        }
+        check(&row);
+        for _ in 0..2500 {
+            row.delete(1000);
+        }
+        check(&row);
+
+        // Combining characters join the grapheme before them, including at checkpoints, and the
+        // one at the start of the row joins the end of `rest` when it is appended to it
+        for at in [0, 1024, 2048, 3001, row.len] {
+            row.insert(at, '\u{301}');
+        }
+        check(&row);
+
+        let mut rest = row.split(4321);
+        check(&row);
+        check(&rest);
+        rest.append(&row);
+        check(&rest);
+    }
+
     #[test]
     fn select_and_edit() {
         let mut row = Row::from("Hello, World!");
@@ -990,13 +1569,9 @@ mod test {
             Type::Number, // 3
             Type::None,   // ;
         ];
-        let mut look_for_multiline_close = None;
-        row.highlight(
-            &filetype.highlighting_options(),
-            &None,
-            &mut look_for_multiline_close,
-        );
+        let state = row.highlight(&filetype.highlighting_options(), &None, &State::Normal);
         assert!(row.highlighting.eq(&base));
+        assert_eq!(state, State::Normal);
 
         row = Row::from("\"a3\"/*3");
         base = vec![
@@ -1008,12 +1583,104 @@ mod test {
             Type::MultilineComment,
             Type::MultilineComment, // /*3
         ];
-        row.highlight(
-            &filetype.highlighting_options(),
-            &None,
-            &mut look_for_multiline_close,
-        );
+        let state = row.highlight(&filetype.highlighting_options(), &None, &state);
         assert!(row.highlighting.eq(&base));
-        assert!(look_for_multiline_close == Some("*/".to_string()));
+        assert_eq!(
+            state,
+            State::Comment {
+                open: "/*".to_string(),
+                close: "*/".to_string(),
+                depth: 0
+            }
+        );
+    }
+
+    /// Highlights a single row as if it were in a file with the given name.
+    fn highlight(file_name: &str, line: &str) -> Vec<Type> {
+        highlight_rows(file_name, &[line]).remove(0)
+    }
+
+    /// Highlights consecutive rows as if they were in a file with the given name.
+    fn highlight_rows(file_name: &str, lines: &[&str]) -> Vec<Vec<Type>> {
+        let filetype = FileType::from(file_name);
+        let mut state = State::Normal;
+        lines
+            .iter()
+            .map(|line| {
+                let mut row = Row::from(*line);
+                state = row.highlight(filetype.highlighting_options(), &None, &state);
+                row.highlighting
+            })
+            .collect()
+    }
+
+    #[test]
+    fn highlight_shell() {
+        assert_eq!(
+            runs(&highlight("a.sh", "echo $HOME \"${x}/$1$?\" '$y' # $z")),
+            "SecondaryKeywords 4, None 1, Variable 5, None 1, String 1, Variable 4, String 1, \
+             Variable 4, String 1, None 1, String 4, None 1, Comment 4"
+        );
+        assert_eq!(
+            runs(&highlight("a.sh", "if [ $# -eq 0 ]; then")),
+            "PrimaryKeywords 2, None 3, Variable 2, None 10, PrimaryKeywords 4"
+        );
+    }
+
+    #[test]
+    fn highlight_keys() {
+        assert_eq!(
+            runs(&highlight("Cargo.toml", "serde.version = \"1.0\" # x = 1")),
+            "Key 13, None 3, String 5, None 1, Comment 7"
+        );
+        assert_eq!(runs(&highlight("Cargo.toml", "[package]")), "None 9");
+        assert_eq!(
+            runs(&highlight("ci.yml", "  - name: true")),
+            "None 4, Key 4, None 2, PrimaryKeywords 4"
+        );
+        assert_eq!(
+            runs(&highlight("a.json", "{\"a\": \"b\", \"c\" : 1}")),
+            "None 1, Key 3, None 2, String 3, None 2, Key 3, None 3, Number 1, None 1"
+        );
+    }
+
+    #[test]
+    fn highlight_markdown() {
+        assert_eq!(runs(&highlight("a.md", "## Usage")), "Heading 8");
+        assert_eq!(runs(&highlight("a.md", "#hashtag")), "None 8");
+        assert_eq!(
+            runs(&highlight("a.md", "* a **b c** snake_case_name _d_ `e`")),
+            "None 4, Emphasis 7, None 17, Emphasis 3, None 1, String 3"
+        );
+    }
+
+    #[test]
+    fn highlight_across_rows() {
+        let rows = highlight_rows(
+            "a.rs",
+            &["/* a /* b */", "c */ 1", "let s = r#\"x\"", "\"# 2"],
+        );
+        let described: Vec<String> = rows.iter().map(|row| runs(row)).collect();
+        assert_eq!(
+            described,
+            [
+                "MultilineComment 12",
+                "MultilineComment 4, None 1, Number 1",
+                "PrimaryKeywords 3, None 5, String 5",
+                "String 2, None 1, Number 1",
+            ]
+        );
+
+        let rows = highlight_rows("a.rs", &["\"a \\\" b", "c\" 3"]);
+        assert_eq!(runs(&rows[1]), "String 2, None 1, Number 1");
+
+        let rows = highlight_rows("a.py", &["doc = '''1", "2 ''' # x", "s = 'a"]);
+        assert_eq!(runs(&rows[0]), "None 6, String 4");
+        assert_eq!(runs(&rows[1]), "String 5, None 1, Comment 3");
+        assert_eq!(runs(&rows[2]), "None 4, String 2");
+
+        // Strings that cannot span rows end with the row
+        let rows = highlight_rows("a.java", &["\"a", "1"]);
+        assert_eq!(runs(&rows[1]), "Number 1");
     }
 }
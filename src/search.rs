@@ -0,0 +1,283 @@
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+/// How the query of a search is matched against the document.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SearchOptions {
+    /// Whether the query is a regular expression rather than literal text
+    pub regex: bool,
+    /// Whether letters of different cases match each other
+    pub case: CaseSensitivity,
+    /// Whether matches must be whole words, i.e. not be next to other word characters
+    pub whole_word: bool,
+}
+
+/// Whether letters of different cases match each other in a search.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaseSensitivity {
+    /// Letters only match letters of the same case
+    #[default]
+    Sensitive,
+    /// Letters match letters of either case
+    Insensitive,
+    /// Letters match letters of either case, unless the query contains an uppercase letter
+    Smart,
+}
+
+impl SearchOptions {
+    /// Describes the options that are enabled, e.g. for the search prompt.
+    pub fn describe(self) -> Vec<&'static str> {
+        let mut options = Vec::new();
+        if self.regex {
+            options.push("regex");
+        }
+        match self.case {
+            CaseSensitivity::Sensitive => (),
+            CaseSensitivity::Insensitive => options.push("ignore case"),
+            CaseSensitivity::Smart => options.push("smart case"),
+        }
+        if self.whole_word {
+            options.push("whole word");
+        }
+        options
+    }
+
+    /// Switches between the given case sensitivity and the default, case-sensitive matching.
+    ///
+    /// # Arguments
+    ///
+    /// * `case` - the [`CaseSensitivity`] to switch to, unless it is already used
+    pub fn toggle_case(&mut self, case: CaseSensitivity) {
+        self.case = if self.case == case {
+            CaseSensitivity::Sensitive
+        } else {
+            case
+        };
+    }
+}
+
+/// A search query, compiled so that it can be matched against rows. Matches never span rows,
+/// and empty matches (e.g. of `^` or `a*`) are skipped, as are matches that are not whole words
+/// if only whole words should match.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    /// The query as a regular expression (which is escaped if the query is literal text)
+    regex: Regex,
+    /// The options that the query was compiled with
+    options: SearchOptions,
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str() && self.options == other.options
+    }
+}
+
+impl Pattern {
+    /// Compiles a search query.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - the text or regular expression to search for
+    /// * `options` - the [`SearchOptions`] to use
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the query is an invalid regular expression
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, String> {
+        let source = if options.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let case_insensitive = match options.case {
+            CaseSensitivity::Sensitive => false,
+            CaseSensitivity::Insensitive => true,
+            CaseSensitivity::Smart => !has_uppercase(query, options.regex),
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|error| error.to_string())?;
+        Ok(Self { regex, options })
+    }
+
+    /// Finds the first match that starts at or after a byte offset into the text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to search
+    /// * `start` - the byte offset to start searching from
+    pub fn find_from(&self, text: &str, mut start: usize) -> Option<Range<usize>> {
+        while start <= text.len() {
+            let found = self.regex.find_at(text, start)?;
+            if !found.is_empty() && (!self.options.whole_word || is_whole_word(text, found.range()))
+            {
+                return Some(found.range());
+            }
+            // Retry from the next character, as a later match may overlap this one
+            start = found.start()
+                + text[found.start()..]
+                    .chars()
+                    .next()
+                    .map_or(1, char::len_utf8);
+        }
+        None
+    }
+
+    /// Finds the last match that starts before a byte offset into the text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to search
+    /// * `end` - the byte offset before which the match starts
+    pub fn find_before(&self, text: &str, end: usize) -> Option<Range<usize>> {
+        self.find_all(text)
+            .take_while(|found| found.start < end)
+            .last()
+    }
+
+    /// Finds all matches in the text, which do not overlap.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text to search
+    pub fn find_all<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut start = 0;
+        std::iter::from_fn(move || {
+            let found = self.find_from(text, start)?;
+            start = found.end;
+            Some(found)
+        })
+    }
+
+    /// Expands a replacement for a match of this pattern. For regular expressions, `$1` or
+    /// `${name}` in the replacement stand for the text of a capture group (and `$$` for a dollar
+    /// sign), while literal queries are replaced with the replacement as is. The captures are
+    /// taken from the whole text, so that matches which depend on the text around them (e.g.
+    /// `\Bb` or `(?m)^a`) expand like they were found.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - the text that the match was found in
+    /// * `range` - the byte range of the match within the text
+    /// * `replacement` - the replacement to expand
+    pub fn expand(&self, text: &str, range: Range<usize>, replacement: &str) -> String {
+        if !self.options.regex {
+            return replacement.to_string();
+        }
+        match self.regex.captures_at(text, range.start) {
+            Some(captures) if captures.get(0).map(|found| found.range()) == Some(range) => {
+                let mut expanded = String::new();
+                captures.expand(replacement, &mut expanded);
+                expanded
+            }
+            _ => replacement.to_string(),
+        }
+    }
+}
+
+/// Checks whether a query contains an uppercase letter, ignoring escape sequences such as `\W` in
+/// regular expressions.
+///
+/// # Arguments
+///
+/// * `query` - the query to check
+/// * `regex` - whether the query is a regular expression
+fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut escaped = false;
+    query.chars().any(|c| {
+        let uppercase = c.is_uppercase() && !escaped;
+        escaped = regex && c == '\\' && !escaped;
+        uppercase
+    })
+}
+
+/// Checks whether a range of text is a whole word, i.e. whether it is neither preceded nor
+/// followed by a word character.
+///
+/// # Arguments
+///
+/// * `text` - the text that contains the range
+/// * `range` - the byte range to check
+fn is_whole_word(text: &str, range: Range<usize>) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    !text[..range.start]
+        .chars()
+        .next_back()
+        .is_some_and(is_word_char)
+        && !text[range.end..].chars().next().is_some_and(is_word_char)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CaseSensitivity, Pattern, SearchOptions};
+
+    #[test]
+    fn literal() {
+        let pattern = Pattern::new("a.b", SearchOptions::default()).unwrap();
+        assert_eq!(pattern.find_from("axb a.b a.b", 0), Some(4..7));
+        assert_eq!(pattern.find_from("axb a.b a.b", 5), Some(8..11));
+        assert_eq!(pattern.find_before("axb a.b a.b", 8), Some(4..7));
+        assert_eq!(pattern.find_before("axb a.b a.b", 4), None);
+        assert_eq!(pattern.expand("a.b", 0..3, "$1"), "$1");
+    }
+
+    #[test]
+    fn regex() {
+        let options = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let pattern = Pattern::new(r"(?P<key>\w+)=(\d+)", options).unwrap();
+        let text = "a=1, bc=23";
+        assert_eq!(
+            pattern.find_all(text).collect::<Vec<_>>(),
+            vec![0..3, 5..10]
+        );
+        assert_eq!(pattern.find_from(text, 1), Some(5..10));
+        assert_eq!(pattern.expand(text, 5..10, "${key}: $2$$"), "bc: 23$");
+        // Text that was not matched is replaced as is
+        assert_eq!(pattern.expand(text, 4..10, "$2"), "$2");
+
+        // Captures are taken from the whole text, as the match may depend on its surroundings
+        let anchored = Pattern::new(r"\Bb(c)", options).unwrap();
+        assert_eq!(anchored.find_from("abc", 0), Some(1..3));
+        assert_eq!(anchored.expand("abc", 1..3, "$1"), "c");
+
+        // Empty matches are skipped
+        let empty = Pattern::new("x*", options).unwrap();
+        assert_eq!(empty.find_from("abxx", 0), Some(2..4));
+        assert_eq!(empty.find_all("éx").collect::<Vec<_>>(), vec![2..3]);
+
+        assert!(Pattern::new("(", options).is_err());
+    }
+
+    #[test]
+    fn options() {
+        let find = |query: &str, options: SearchOptions| {
+            let pattern = Pattern::new(query, options).unwrap();
+            pattern.find_all("Foo foo food (foo)").collect::<Vec<_>>()
+        };
+        let mut options = SearchOptions::default();
+        assert_eq!(find("foo", options), vec![4..7, 8..11, 14..17]);
+
+        options.toggle_case(CaseSensitivity::Insensitive);
+        assert_eq!(find("foo", options), vec![0..3, 4..7, 8..11, 14..17]);
+        options.toggle_case(CaseSensitivity::Smart);
+        assert_eq!(options.describe(), vec!["smart case"]);
+        assert_eq!(find("foo", options), vec![0..3, 4..7, 8..11, 14..17]);
+        assert_eq!(find("Foo", options), vec![0..3]);
+
+        options.whole_word = true;
+        assert_eq!(find("foo", options), vec![0..3, 4..7, 14..17]);
+        assert_eq!(find("(foo)", options), vec![13..18]);
+        options.regex = true;
+        assert_eq!(find(r"\Wfoo", options), vec![13..17]);
+        assert_eq!(
+            options.describe(),
+            vec!["regex", "smart case", "whole word"]
+        );
+    }
+}
@@ -68,23 +68,17 @@ impl Terminal {
         print!("{}", termion::clear::CurrentLine);
     }
 
-    /// Sets (inverts) the terminal foreground color.
-    pub fn set_fg_color() {
-        print!("{}", termion::style::Invert);
-    }
-
-    /// Resets the terminal foreground color.
-    pub fn reset_fg_color() {
-        print!("{}", termion::style::Reset);
-    }
-
-    /// Sets (inverts) the terminal background color.
-    pub fn set_bg_color() {
-        print!("{}", termion::style::Invert);
+    /// Sets the colors and attributes of the text that is printed next.
+    ///
+    /// # Arguments
+    ///
+    /// * `escape` - the escape sequence of the style, as given by a [`Theme`](crate::theme::Theme)
+    pub fn set_style(escape: &str) {
+        print!("{escape}");
     }
 
-    /// Reset the terminal background color.
-    pub fn reset_bg_color() {
+    /// Resets the colors and attributes of the text that is printed next.
+    pub fn reset_style() {
         print!("{}", termion::style::Reset);
     }
 
@@ -0,0 +1,65 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::highlighting::Type;
+
+/// A directory for the files that a test works with, which is removed along with its contents
+/// when it is dropped, even if the test fails.
+pub struct TempDir {
+    /// The path of the directory
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory in the system's temporary directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the directory, which is made unique to the test process
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("ferro-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Gets the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the path of a file within the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the file
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Describes runs of highlighting as e.g. `"None 4, Variable 5"`.
+///
+/// # Arguments
+///
+/// * `highlighting` - the highlighting of each grapheme in a row
+pub fn runs(highlighting: &[Type]) -> String {
+    let mut runs: Vec<(Type, usize)> = Vec::new();
+    for hl_type in highlighting {
+        match runs.last_mut() {
+            Some((last, len)) if last == hl_type => *len += 1,
+            _ => runs.push((*hl_type, 1)),
+        }
+    }
+    runs.iter()
+        .map(|(hl_type, len)| format!("{hl_type:?} {len}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
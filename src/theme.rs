@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use termion::{color, style};
+
+use crate::config;
+use crate::highlighting::Type;
+
+/// The themes that are built into ferro, as pairs of file name and contents
+const BUILT_IN_THEMES: [(&str, &str); 3] = [
+    ("dark.toml", include_str!("../resources/themes/dark.toml")),
+    ("light.toml", include_str!("../resources/themes/light.toml")),
+    (
+        "solarized-dark.toml",
+        include_str!("../resources/themes/solarized-dark.toml"),
+    ),
+];
+
+static THEMES: LazyLock<Themes> = LazyLock::new(|| {
+    Themes::load(
+        config::config_dir()
+            .map(|dir| dir.join("themes"))
+            .as_deref(),
+    )
+});
+
+/// Whether the terminal has a dark background, as far as it can be queried.
+static HAS_DARK_BACKGROUND: LazyLock<bool> = LazyLock::new(|| {
+    !matches!(
+        termbg::theme(Duration::from_millis(100)),
+        Ok(termbg::Theme::Light)
+    )
+});
+
+/// A color of a [`Style`], given in a theme either as a number of the 256-color palette or as a
+/// hex code such as `"#268bd2"`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Color {
+    /// A color of the 256-color palette
+    Ansi(u8),
+    /// A 24-bit color
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Parses a hex code such as `"#268bd2"`.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - the hex code to parse
+    fn from_hex(code: &str) -> Option<Self> {
+        let hex = code.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+        let channel = |at: usize| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok();
+        Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// Gets the color of the 256-color palette that comes closest to this color, for terminals
+    /// that do not support 24-bit colors.
+    fn to_ansi(self) -> u8 {
+        // The palette holds a 6x6x6 color cube, whose channels take these levels, and a ramp of
+        // 24 grays
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        let (r, g, b) = match self {
+            Color::Ansi(value) => return value,
+            Color::Rgb(r, g, b) => (r, g, b),
+        };
+
+        let level = |channel: u8| {
+            (0..LEVELS.len())
+                .min_by_key(|index| LEVELS[*index].abs_diff(channel))
+                .unwrap_or_default()
+        };
+        let distance = |(r2, g2, b2): (u8, u8, u8)| {
+            [(r, r2), (g, g2), (b, b2)]
+                .iter()
+                .map(|(a, b)| u32::from(a.abs_diff(*b)).pow(2))
+                .sum::<u32>()
+        };
+
+        let (ri, gi, bi) = (level(r), level(g), level(b));
+        let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+        let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+        let gray_index = (average.saturating_sub(3) / 10).min(23);
+        let gray = u8::try_from(8 + gray_index * 10).unwrap_or(u8::MAX);
+
+        if distance((gray, gray, gray)) < distance(cube) {
+            232 + u8::try_from(gray_index).unwrap_or_default()
+        } else {
+            u8::try_from(16 + 36 * ri + 6 * gi + bi).unwrap_or_default()
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl Visitor<'_> for ColorVisitor {
+            type Value = Color;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number from 0 to 255 or a hex code such as \"#268bd2\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Color, E> {
+                u8::try_from(value)
+                    .map(Color::Ansi)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Color, E> {
+                u8::try_from(value)
+                    .map(Color::Ansi)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Color, E> {
+                Color::from_hex(value)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+/// How a highlighting type or a part of the editor's interface is drawn.
+#[derive(PartialEq, Eq, Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    /// The foreground color, or the terminal's default if unset
+    pub fg: Option<Color>,
+    /// The background color, or the terminal's default if unset
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    /// Whether the foreground and background colors are swapped
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Gets the escape sequence that switches the terminal to this style.
+    ///
+    /// # Arguments
+    ///
+    /// * `truecolor` - whether the terminal supports 24-bit colors
+    fn escape(&self, truecolor: bool) -> String {
+        let mut escape = style::Reset.to_string();
+        for (enabled, attribute) in [
+            (self.bold, style::Bold.to_string()),
+            (self.italic, style::Italic.to_string()),
+            (self.underline, style::Underline.to_string()),
+            (self.reverse, style::Invert.to_string()),
+        ] {
+            if enabled {
+                escape.push_str(&attribute);
+            }
+        }
+
+        let _ = match self.fg {
+            Some(Color::Rgb(r, g, b)) if truecolor => {
+                write!(escape, "{}", color::Fg(color::Rgb(r, g, b)))
+            }
+            Some(fg) => write!(escape, "{}", color::Fg(color::AnsiValue(fg.to_ansi()))),
+            None => Ok(()),
+        };
+        let _ = match self.bg {
+            Some(Color::Rgb(r, g, b)) if truecolor => {
+                write!(escape, "{}", color::Bg(color::Rgb(r, g, b)))
+            }
+            Some(bg) => write!(escape, "{}", color::Bg(color::AnsiValue(bg.to_ansi()))),
+            None => Ok(()),
+        };
+        escape
+    }
+}
+
+/// A color theme, which sets the [`Style`] of every highlighting type and of the status bar.
+/// Themes are loaded from the `.toml` files in `$XDG_CONFIG_HOME/ferro/themes/` (or
+/// `~/.config/ferro/themes/`) as well as from `resources/themes/`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    /// The name that the theme is chosen by, e.g. "dark"
+    pub name: String,
+    /// The style of the status bar and of the lists drawn over documents, e.g. the buffer list
+    #[serde(default)]
+    status_bar: Style,
+    /// The styles of the highlighting types, where `text` is the style of text that is not
+    /// highlighted otherwise
+    #[serde(default)]
+    styles: HashMap<Type, Style>,
+    /// Whether the terminal supports 24-bit colors, rather than only the 256-color palette
+    #[serde(skip)]
+    truecolor: bool,
+}
+
+impl Theme {
+    /// Parses a theme from TOML. Colors that a style leaves unset are taken from the style of
+    /// `text`.
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - the theme to parse
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a description of the problem if the theme is invalid
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut theme: Self =
+            toml::from_str(contents).map_err(|error| error.message().to_string())?;
+        let text = theme.style(Type::None).clone();
+        for style in theme.styles.values_mut() {
+            style.fg = style.fg.or(text.fg);
+            style.bg = style.bg.or(text.bg);
+        }
+        Ok(theme)
+    }
+
+    /// Gets the style of a highlighting type. Multiline comments fall back to the style of
+    /// comments, and every other type to the style of `text`.
+    ///
+    /// # Arguments
+    ///
+    /// * `hl_type` - the highlighting type
+    pub fn style(&self, hl_type: Type) -> &Style {
+        static UNSTYLED: LazyLock<Style> = LazyLock::new(Style::default);
+        let fallback = match hl_type {
+            Type::MultilineComment => self.styles.get(&Type::Comment),
+            _ => None,
+        };
+        self.styles
+            .get(&hl_type)
+            .or(fallback)
+            .or_else(|| self.styles.get(&Type::None))
+            .unwrap_or(&UNSTYLED)
+    }
+
+    /// Gets the escape sequence that switches the terminal to the style of a highlighting type.
+    ///
+    /// # Arguments
+    ///
+    /// * `hl_type` - the highlighting type
+    pub fn escape(&self, hl_type: Type) -> String {
+        self.style(hl_type).escape(self.truecolor)
+    }
+
+    /// Gets the escape sequence that switches the terminal to the style of the status bar.
+    pub fn status_bar_escape(&self) -> String {
+        self.status_bar.escape(self.truecolor)
+    }
+}
+
+/// The themes that can be chosen from.
+struct Themes {
+    /// The themes, with the user's before the built-in ones so that they take precedence
+    themes: Vec<Theme>,
+    /// Descriptions of the user's themes that could not be loaded
+    errors: Vec<String>,
+}
+
+impl Themes {
+    /// Loads the user's themes from the given directory, followed by the built-in themes.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - the directory of `.toml` themes, if any
+    fn load(dir: Option<&Path>) -> Self {
+        let mut themes = Self {
+            themes: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        for path in config::files_with_extensions(dir, &["toml"]) {
+            match fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|contents| Theme::parse(&contents))
+            {
+                Ok(theme) => themes.themes.push(theme),
+                Err(error) => themes.errors.push(format!("{}: {error}", path.display())),
+            }
+        }
+
+        for (name, contents) in BUILT_IN_THEMES {
+            match Theme::parse(contents) {
+                Ok(theme) => themes.themes.push(theme),
+                Err(error) => themes.errors.push(format!("{name}: {error}")),
+            }
+        }
+        themes
+    }
+
+    /// Finds the theme with the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the theme
+    fn find(&self, name: &str) -> Option<&Theme> {
+        self.themes.iter().find(|theme| theme.name == name)
+    }
+}
+
+/// Gets the names of the themes that can be chosen from, in alphabetical order.
+pub fn names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = THEMES
+        .themes
+        .iter()
+        .map(|theme| theme.name.as_str())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+/// Finds the theme with the given name, set up for the colors that the terminal supports.
+///
+/// # Arguments
+///
+/// * `name` - the name of the theme
+pub fn find(name: &str) -> Option<Theme> {
+    let mut theme = THEMES.find(name)?.clone();
+    theme.truecolor = supports_truecolor();
+    Some(theme)
+}
+
+/// Gets the theme that suits the terminal's background, i.e. "dark" or "light".
+pub fn default_theme() -> Theme {
+    find(if *HAS_DARK_BACKGROUND {
+        "dark"
+    } else {
+        "light"
+    })
+    .unwrap_or_default()
+}
+
+/// Gets why each of the user's themes that was skipped could not be loaded, e.g.
+/// "~/.config/ferro/themes/nord.toml: missing field `name`".
+pub fn load_errors() -> &'static [String] {
+    &THEMES.errors
+}
+
+/// Checks whether the terminal supports 24-bit colors, which it announces through `COLORTERM`.
+fn supports_truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{Color, Style, Theme, Themes};
+    use crate::highlighting::Type;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parse_themes() {
+        let theme = Theme::parse(
+            "name = \"test\"\nstatus_bar = { reverse = true }\n\n[styles]\n\
+             text = { fg = 250, bg = \"#002B36\" }\ncomment = { fg = \"#586e75\", italic = true }\n",
+        )
+        .unwrap();
+        assert_eq!(theme.name, "test");
+        assert_eq!(
+            theme.style(Type::MultilineComment),
+            &Style {
+                fg: Some(Color::Rgb(0x58, 0x6e, 0x75)),
+                bg: Some(Color::Rgb(0x00, 0x2b, 0x36)),
+                italic: true,
+                ..Style::default()
+            }
+        );
+        assert_eq!(theme.style(Type::Number).fg, Some(Color::Ansi(250)));
+
+        assert!(Theme::parse("name = \"bad\"\n[styles]\ntext = { fg = 256 }\n").is_err());
+        assert!(Theme::parse("name = \"bad\"\n[styles]\ntext = { fg = \"red\" }\n").is_err());
+        assert!(Theme::parse("name = \"bad\"\n[styles]\nstart = {}\n").is_err());
+        assert!(Theme::parse("name = \"bad\"\n[styles]\ntext = { blink = true }\n").is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(Color::Rgb(255, 0, 0).to_ansi(), 196);
+        assert_eq!(Color::Rgb(128, 128, 128).to_ansi(), 244);
+        assert_eq!(Color::Rgb(0x26, 0x8b, 0xd2).to_ansi(), 32);
+        assert_eq!(Color::Ansi(42).to_ansi(), 42);
+
+        let style = Style {
+            fg: Some(Color::Rgb(255, 0, 0)),
+            bold: true,
+            ..Style::default()
+        };
+        assert_eq!(style.escape(true), "\x1b[m\x1b[1m\x1b[38;2;255;0;0m");
+        assert_eq!(style.escape(false), "\x1b[m\x1b[1m\x1b[38;5;196m");
+    }
+
+    #[test]
+    fn user_themes() {
+        let dir = TempDir::new("themes");
+        fs::write(
+            dir.join("dark.toml"),
+            "name = \"dark\"\n[styles]\ntext = { fg = 15 }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("broken.toml"), "name = \"broken\"\ncolour = 1\n").unwrap();
+
+        let themes = Themes::load(Some(dir.path()));
+
+        assert_eq!(themes.errors.len(), 1);
+        assert!(themes.errors[0].contains("broken.toml"));
+        assert_eq!(
+            themes.find("dark").unwrap().style(Type::String).fg,
+            Some(Color::Ansi(15))
+        );
+        assert_eq!(
+            themes.find("light").unwrap().style(Type::String).fg,
+            Some(Color::Ansi(210))
+        );
+        assert!(themes.find("solarized-dark").is_some());
+        assert!(themes.find("broken").is_none());
+    }
+}
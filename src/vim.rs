@@ -0,0 +1,781 @@
+use termion::event::Key;
+
+use crate::{Document, Position, SearchDirection};
+
+/// The commands that can be entered on the command line (after `:`), besides line numbers
+pub const COMMANDS: &[&str] = &["q", "q!", "qa!", "w", "wq", "x"];
+/// The largest count that commands which repeat themselves (such as `u` and `p`) use, so that a
+/// mistyped count does not make them run practically forever
+pub const MAX_REPEAT_COUNT: usize = 1000;
+
+/// The mode of vim-style modal editing.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Mode {
+    /// Keys are commands that move the cursor or operate on text
+    #[default]
+    Normal,
+    /// Keys insert text, as in the default editing mode
+    Insert,
+    /// Motions extend a selection of characters that operators then apply to
+    Visual,
+    /// Motions extend a selection of whole lines that operators then apply to
+    VisualLine,
+}
+
+impl Mode {
+    /// Gets the name of this mode, as shown in the status bar.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "V-LINE",
+        }
+    }
+}
+
+/// A motion, which moves the cursor or gives the extent of an operator.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Motion {
+    /// `h`
+    Left,
+    /// `j`
+    Down,
+    /// `k`
+    Up,
+    /// `l`
+    Right,
+    /// `w`, to the start of the next word
+    WordForward,
+    /// `b`, to the start of the previous word
+    WordBackward,
+    /// `e`, to the end of the word
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `gg`, to the first line (or the line given by the count)
+    FirstLine,
+    /// `G`, to the last line (or the line given by the count)
+    LastLine,
+    /// The current line, which is what doubled operators (e.g. `dd`) apply to
+    Line,
+}
+
+impl Motion {
+    /// Checks whether operators apply to whole lines when combined with this motion.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Down | Motion::Up | Motion::FirstLine | Motion::LastLine | Motion::Line
+        )
+    }
+
+    /// Checks whether operators apply to the character that this motion moves onto.
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd)
+    }
+}
+
+/// An operator, which applies to the text covered by a motion or selection.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Operator {
+    /// `d`
+    Delete,
+    /// `c`, which deletes the text and then enters insert mode
+    Change,
+    /// `y`, which copies the text
+    Yank,
+}
+
+/// Where insert mode is entered.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Insertion {
+    /// `i`
+    Before,
+    /// `a`
+    After,
+    /// `I`, before the first non-blank character of the line
+    LineStart,
+    /// `A`
+    LineEnd,
+    /// `o`, on a new line below the current one
+    LineBelow,
+    /// `O`, on a new line above the current one
+    LineAbove,
+}
+
+/// A complete command typed in normal or visual mode.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Operation {
+    /// Moves the cursor
+    Move(Motion),
+    /// Applies an operator to the text from the cursor to the target of a motion
+    Operate(Operator, Motion),
+    /// Applies an operator to the selection of visual mode
+    OperateSelection(Operator),
+    /// Enters insert mode
+    Insert(Insertion),
+    /// Pastes the clipboard after (`p`) or before (`P`) the cursor
+    Paste { before: bool },
+    /// `u`
+    Undo,
+    /// Enters (or leaves) visual mode (`v`) or visual line mode (`V`)
+    Visual { linewise: bool },
+    /// `:`, which prompts for a command such as `w` or `q`
+    CommandLine,
+    /// `.`, which repeats the last change
+    Repeat,
+}
+
+impl Operation {
+    /// Checks whether this operation changes the document, so that it can be repeated.
+    pub fn is_change(self) -> bool {
+        matches!(
+            self,
+            Operation::Operate(Operator::Delete | Operator::Change, _)
+                | Operation::Insert(_)
+                | Operation::Paste { .. }
+        )
+    }
+}
+
+/// The result of parsing the keys typed so far in normal or visual mode.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Parse {
+    /// The keys are the start of a command
+    Incomplete,
+    /// The keys do not make up a command
+    Invalid,
+    /// The keys make up a command, along with its count if one was typed
+    Complete(Operation, Option<usize>),
+}
+
+/// The text that an operator applies to.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Range {
+    /// The characters from the first position up to (but excluding) the second
+    Chars(Position, Position),
+    /// The lines from the first index to the second, inclusive
+    Lines(usize, usize),
+}
+
+/// The state of vim-style modal editing.
+#[derive(Default)]
+pub struct Vim {
+    /// The current mode
+    pub mode: Mode,
+    /// Where the selection started, in the visual modes
+    pub anchor: Position,
+    /// The text that was last yanked or deleted as whole lines, which is pasted as lines too
+    pub linewise_text: Option<String>,
+    /// The keys of the command being typed
+    keys: Vec<char>,
+    /// The keys of the last change (without its count), which `.` repeats
+    last_change: Vec<Key>,
+    /// The count of the last change
+    last_count: Option<usize>,
+    /// The keys of the change being made, until it is complete
+    recording: Option<Vec<Key>>,
+}
+
+impl Vim {
+    /// Checks whether a key is handled by the modal layer, rather than by the editor's keymap.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key that was pressed
+    pub fn handles(&self, key: Key) -> bool {
+        match self.mode {
+            Mode::Insert => key == Key::Esc,
+            _ => !self.keys.is_empty() || key == Key::Esc || Self::command_char(key).is_some(),
+        }
+    }
+
+    /// Gets the character of a command that a key types in normal or visual mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key that was pressed
+    pub fn command_char(key: Key) -> Option<char> {
+        match key {
+            Key::Char('\n') => Some('j'),
+            Key::Char(c) => Some(c),
+            Key::Backspace => Some('h'),
+            Key::Delete => Some('x'),
+            _ => None,
+        }
+    }
+
+    /// Checks whether a visual mode is active.
+    pub fn is_visual(&self) -> bool {
+        matches!(self.mode, Mode::Visual | Mode::VisualLine)
+    }
+
+    /// Adds a character to the command being typed, and parses it. Changes start being
+    /// recorded once they are complete, so that they can be repeated.
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - the typed character
+    pub fn push(&mut self, c: char) -> Parse {
+        self.keys.push(c);
+        let parse = parse(&self.keys, self.is_visual());
+        if let Parse::Complete(operation, count) = parse {
+            if operation.is_change() && !self.is_visual() {
+                let (_, keys) = split_count(&self.keys);
+                self.recording = Some(keys.iter().map(|c| Key::Char(*c)).collect());
+                self.last_count = count;
+            }
+        }
+        if parse != Parse::Incomplete {
+            self.keys.clear();
+        }
+        parse
+    }
+
+    /// Discards the command being typed.
+    pub fn cancel(&mut self) {
+        self.keys.clear();
+    }
+
+    /// Records a key pressed as part of the change being made, e.g. text typed in insert mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - the key that was pressed
+    pub fn record(&mut self, key: Key) {
+        if let Some(recording) = &mut self.recording {
+            recording.push(key);
+        }
+    }
+
+    /// Completes the change being made, which becomes the change that `.` repeats.
+    pub fn finish_change(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            self.last_change = recording;
+        }
+    }
+
+    /// Gets the keys that repeat the last change.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - the count to repeat the change with, instead of its own count
+    pub fn repeat_keys(&self, count: Option<usize>) -> Vec<Key> {
+        let count = count.or(self.last_count).map(|count| count.to_string());
+        count
+            .unwrap_or_default()
+            .chars()
+            .map(Key::Char)
+            .chain(self.last_change.iter().copied())
+            .collect()
+    }
+}
+
+/// Splits the count off the start of a command's keys. A leading `0` is a motion rather than
+/// a count.
+///
+/// # Arguments
+///
+/// * `keys` - the keys of the command
+fn split_count(keys: &[char]) -> (Option<usize>, &[char]) {
+    let digits = keys
+        .iter()
+        .enumerate()
+        .take_while(|(index, c)| c.is_ascii_digit() && (*index > 0 || **c != '0'))
+        .count();
+    let count = keys[..digits].iter().collect::<String>().parse().ok();
+    (count, &keys[digits..])
+}
+
+/// Parses the keys of a motion.
+///
+/// # Arguments
+///
+/// * `keys` - the keys of the motion, without a count
+fn parse_motion(keys: &[char]) -> Result<Motion, Parse> {
+    let motion = match keys {
+        [] | ['g'] => return Err(Parse::Incomplete),
+        ['h'] => Motion::Left,
+        ['j'] => Motion::Down,
+        ['k'] => Motion::Up,
+        ['l' | ' '] => Motion::Right,
+        ['w'] => Motion::WordForward,
+        ['b'] => Motion::WordBackward,
+        ['e'] => Motion::WordEnd,
+        ['0'] => Motion::LineStart,
+        ['$'] => Motion::LineEnd,
+        ['g', 'g'] => Motion::FirstLine,
+        ['G'] => Motion::LastLine,
+        _ => return Err(Parse::Invalid),
+    };
+    Ok(motion)
+}
+
+/// Parses the keys typed so far in normal or visual mode.
+///
+/// # Arguments
+///
+/// * `keys` - the typed keys
+/// * `visual` - whether a visual mode is active, in which operators need no motion
+pub fn parse(keys: &[char], visual: bool) -> Parse {
+    let (count, keys) = split_count(keys);
+    let Some((&first, rest)) = keys.split_first() else {
+        return Parse::Incomplete;
+    };
+
+    let operator = match first {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    };
+    let operation = match (first, rest, operator) {
+        (_, [], Some(operator)) if visual => Operation::OperateSelection(operator),
+        ('x', [], _) if visual => Operation::OperateSelection(Operator::Delete),
+        ('s', [], _) if visual => Operation::OperateSelection(Operator::Change),
+        (_, _, Some(operator)) => {
+            let (motion_count, motion_keys) = split_count(rest);
+            let motion = match motion_keys {
+                [c] if *c == first => Motion::Line,
+                _ => match parse_motion(motion_keys) {
+                    Ok(motion) => motion,
+                    Err(parse) => return parse,
+                },
+            };
+            let count = match (count, motion_count) {
+                (None, None) => None,
+                (count, motion_count) => {
+                    Some(count.unwrap_or(1).saturating_mul(motion_count.unwrap_or(1)))
+                }
+            };
+            return Parse::Complete(Operation::Operate(operator, motion), count);
+        }
+        ('v', [], _) => Operation::Visual { linewise: false },
+        ('V', [], _) => Operation::Visual { linewise: true },
+        (_, _, _) if visual => match parse_motion(keys) {
+            Ok(motion) => Operation::Move(motion),
+            Err(parse) => return parse,
+        },
+        ('i', [], _) => Operation::Insert(Insertion::Before),
+        ('a', [], _) => Operation::Insert(Insertion::After),
+        ('I', [], _) => Operation::Insert(Insertion::LineStart),
+        ('A', [], _) => Operation::Insert(Insertion::LineEnd),
+        ('o', [], _) => Operation::Insert(Insertion::LineBelow),
+        ('O', [], _) => Operation::Insert(Insertion::LineAbove),
+        ('x', [], _) => Operation::Operate(Operator::Delete, Motion::Right),
+        ('X', [], _) => Operation::Operate(Operator::Delete, Motion::Left),
+        ('D', [], _) => Operation::Operate(Operator::Delete, Motion::LineEnd),
+        ('C', [], _) => Operation::Operate(Operator::Change, Motion::LineEnd),
+        ('Y', [], _) => Operation::Operate(Operator::Yank, Motion::Line),
+        ('p', [], _) => Operation::Paste { before: false },
+        ('P', [], _) => Operation::Paste { before: true },
+        ('u', [], _) => Operation::Undo,
+        (':', [], _) => Operation::CommandLine,
+        ('.', [], _) => Operation::Repeat,
+        _ => match parse_motion(keys) {
+            Ok(motion) => Operation::Move(motion),
+            Err(parse) => return parse,
+        },
+    };
+    Parse::Complete(operation, count)
+}
+
+/// The class of a character, where words are made up of characters of the same class.
+#[derive(PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    /// Gets the class of the character at a position, where the end of a row is whitespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - the [Document] to look in
+    /// * `at` - the position of the character
+    fn at(document: &Document, at: Position) -> Self {
+        let c = document
+            .get_char_in_doc(at)
+            .and_then(|grapheme| grapheme.chars().next())
+            .unwrap_or('\n');
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if c.is_alphanumeric() || c == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+/// Gets the position after the given one, moving onto the next row after the end of a row.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] to move in
+/// * `at` - the position to move from
+fn next_position(document: &Document, at: Position) -> Option<Position> {
+    if at.x < document.row_len(at.y)? {
+        Some(Position {
+            x: at.x + 1,
+            y: at.y,
+        })
+    } else if at.y + 1 < document.len() {
+        Some(Position { x: 0, y: at.y + 1 })
+    } else {
+        None
+    }
+}
+
+/// Gets the position of the end of the word after the given position.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] to look in
+/// * `from` - the position to start from
+fn word_end(document: &Document, from: Position) -> Position {
+    let Some(mut position) = next_position(document, from) else {
+        return from;
+    };
+    while CharClass::at(document, position) == CharClass::Whitespace {
+        match next_position(document, position) {
+            Some(next) => position = next,
+            None => return position,
+        }
+    }
+
+    let class = CharClass::at(document, position);
+    while let Some(next) = next_position(document, position) {
+        if CharClass::at(document, next) != class {
+            break;
+        }
+        position = next;
+    }
+    position
+}
+
+/// Gets the index of the first non-blank character of a row.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] that holds the row
+/// * `y` - the index of the row
+pub fn first_non_blank(document: &Document, y: usize) -> usize {
+    document.line(y).map_or(0, |row| {
+        row.to_graphemes()
+            .take_while(|grapheme| grapheme.chars().all(char::is_whitespace))
+            .count()
+            .min(row.len().saturating_sub(1))
+    })
+}
+
+/// Gets the position that a motion moves to.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] to move in
+/// * `from` - the position to move from
+/// * `motion` - the [Motion]
+/// * `count` - how many times to move, if a count was typed
+/// * `operator` - the [Operator] that the motion is combined with, if any
+pub fn target(
+    document: &Document,
+    from: Position,
+    motion: Motion,
+    count: Option<usize>,
+    operator: Option<Operator>,
+) -> Position {
+    let times = count.unwrap_or(1).max(1);
+    let last_line = document.len().saturating_sub(1);
+    let row_len = |y| document.row_len(y).unwrap_or(0);
+    let Position { x, y } = from;
+
+    match motion {
+        Motion::Left => Position {
+            x: x.saturating_sub(times),
+            y,
+        },
+        Motion::Right => {
+            // Operators apply up to the end of the row, while the cursor stays on its last character
+            let limit = if operator.is_some() {
+                row_len(y)
+            } else {
+                row_len(y).saturating_sub(1)
+            };
+            Position {
+                x: x.saturating_add(times).min(limit),
+                y,
+            }
+        }
+        Motion::Up => Position {
+            x,
+            y: y.saturating_sub(times),
+        },
+        Motion::Down | Motion::Line => Position {
+            x,
+            y: (y.saturating_add(times) - usize::from(motion == Motion::Line)).min(last_line),
+        },
+        Motion::WordForward | Motion::WordBackward => {
+            let direction = if motion == Motion::WordForward {
+                SearchDirection::Forward
+            } else {
+                SearchDirection::Backward
+            };
+            let mut position = from;
+            for _ in 0..times {
+                match document.find_next_word(&position, direction) {
+                    Some(next) => position = next,
+                    None => break,
+                }
+            }
+            // An operator stops at the end of the row that the last word is on
+            if operator.is_some() && position.x == 0 && position.y > y {
+                position = Position {
+                    x: row_len(position.y - 1),
+                    y: position.y - 1,
+                };
+            }
+            position
+        }
+        Motion::WordEnd => {
+            let mut position = from;
+            for _ in 0..times {
+                let next = word_end(document, position);
+                // The end of the document has been reached
+                if next == position {
+                    break;
+                }
+                position = next;
+            }
+            position
+        }
+        Motion::LineStart => Position { x: 0, y },
+        Motion::LineEnd => {
+            let y = y.saturating_add(times - 1).min(last_line);
+            Position {
+                x: row_len(y).saturating_sub(1),
+                y,
+            }
+        }
+        Motion::FirstLine | Motion::LastLine => {
+            let default = if motion == Motion::FirstLine {
+                0
+            } else {
+                last_line
+            };
+            let y = count.map_or(default, |line| line.saturating_sub(1).min(last_line));
+            Position {
+                x: first_non_blank(document, y),
+                y,
+            }
+        }
+    }
+}
+
+/// Gets the text that an operator combined with a motion applies to.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] to operate on
+/// * `from` - the position of the cursor
+/// * `motion` - the [Motion]
+/// * `count` - how many times to move, if a count was typed
+/// * `operator` - the [Operator]
+pub fn range(
+    document: &Document,
+    from: Position,
+    motion: Motion,
+    count: Option<usize>,
+    operator: Operator,
+) -> Range {
+    // Like in vim, `cw` changes up to the end of the word rather than up to the next one
+    let motion = if operator == Operator::Change
+        && motion == Motion::WordForward
+        && CharClass::at(document, from) != CharClass::Whitespace
+    {
+        Motion::WordEnd
+    } else {
+        motion
+    };
+
+    let to = target(document, from, motion, count, Some(operator));
+    if motion.is_linewise() {
+        return Range::Lines(from.y.min(to.y), from.y.max(to.y));
+    }
+
+    let (start, mut end) = (from.min(to), from.max(to));
+    if motion.is_inclusive() {
+        end.x = (end.x + 1).min(document.row_len(end.y).unwrap_or(0));
+    }
+    Range::Chars(start, end)
+}
+
+/// Gets the text selected in a visual mode.
+///
+/// # Arguments
+///
+/// * `document` - the [Document] that the selection is made in
+/// * `anchor` - where the selection started
+/// * `cursor` - the position of the cursor
+/// * `linewise` - whether whole lines are selected
+pub fn selection(document: &Document, anchor: Position, cursor: Position, linewise: bool) -> Range {
+    let (start, mut end) = (anchor.min(cursor), anchor.max(cursor));
+    if linewise {
+        return Range::Lines(start.y, end.y);
+    }
+    end.x = (end.x + 1).min(document.row_len(end.y).unwrap_or(0));
+    Range::Chars(start, end)
+}
+
+#[cfg(test)]
+mod test {
+    use termion::event::Key;
+
+    use super::{parse, range, target, Insertion, Motion, Operation, Operator, Parse, Range, Vim};
+    use crate::{Document, Position};
+
+    #[test]
+    fn parse_commands() {
+        let parse_str = |keys: &str, visual| parse(&keys.chars().collect::<Vec<_>>(), visual);
+        assert_eq!(
+            parse_str("3j", false),
+            Parse::Complete(Operation::Move(Motion::Down), Some(3))
+        );
+        assert_eq!(
+            parse_str("0", false),
+            Parse::Complete(Operation::Move(Motion::LineStart), None)
+        );
+        assert_eq!(parse_str("2d", false), Parse::Incomplete);
+        assert_eq!(parse_str("dg", false), Parse::Incomplete);
+        assert_eq!(
+            parse_str("2d3w", false),
+            Parse::Complete(
+                Operation::Operate(Operator::Delete, Motion::WordForward),
+                Some(6)
+            )
+        );
+        assert_eq!(
+            parse_str("99999999999d99999999999w", false),
+            Parse::Complete(
+                Operation::Operate(Operator::Delete, Motion::WordForward),
+                Some(usize::MAX)
+            )
+        );
+        assert_eq!(
+            parse_str("cc", false),
+            Parse::Complete(Operation::Operate(Operator::Change, Motion::Line), None)
+        );
+        assert_eq!(
+            parse_str("dgg", false),
+            Parse::Complete(
+                Operation::Operate(Operator::Delete, Motion::FirstLine),
+                None
+            )
+        );
+        assert_eq!(parse_str("dy", false), Parse::Invalid);
+        assert_eq!(
+            parse_str("O", false),
+            Parse::Complete(Operation::Insert(Insertion::LineAbove), None)
+        );
+        assert_eq!(
+            parse_str("y", true),
+            Parse::Complete(Operation::OperateSelection(Operator::Yank), None)
+        );
+        assert_eq!(parse_str("i", true), Parse::Invalid);
+        assert_eq!(parse_str("q", false), Parse::Invalid);
+    }
+
+    #[test]
+    fn motions() {
+        let document = Document::from_text("fn main() {\n    let x = 1;\n\n}\n");
+        let at = |x, y| Position { x, y };
+        let move_to = |from, motion, count| target(&document, from, motion, count, None);
+
+        assert_eq!(move_to(at(0, 0), Motion::WordForward, Some(2)), at(0, 1));
+        assert_eq!(move_to(at(0, 0), Motion::WordEnd, None), at(1, 0));
+        assert_eq!(move_to(at(1, 0), Motion::WordEnd, None), at(6, 0));
+        assert_eq!(move_to(at(10, 0), Motion::WordEnd, None), at(6, 1));
+        assert_eq!(move_to(at(3, 0), Motion::LineEnd, None), at(10, 0));
+        assert_eq!(move_to(at(3, 0), Motion::Right, Some(20)), at(10, 0));
+        assert_eq!(move_to(at(3, 0), Motion::LastLine, None), at(0, 3));
+        assert_eq!(move_to(at(3, 3), Motion::FirstLine, Some(2)), at(4, 1));
+
+        // Huge counts move as far as they can
+        assert_eq!(
+            move_to(at(3, 0), Motion::Right, Some(usize::MAX)),
+            at(10, 0)
+        );
+        assert_eq!(move_to(at(3, 1), Motion::Down, Some(usize::MAX)), at(3, 3));
+        assert_eq!(
+            move_to(at(3, 1), Motion::LineEnd, Some(usize::MAX)),
+            at(0, 3)
+        );
+        assert_eq!(
+            move_to(at(0, 0), Motion::WordEnd, Some(usize::MAX)),
+            move_to(at(0, 0), Motion::WordEnd, Some(20))
+        );
+
+        assert_eq!(
+            range(
+                &document,
+                at(3, 0),
+                Motion::WordForward,
+                None,
+                Operator::Change
+            ),
+            Range::Chars(at(3, 0), at(7, 0))
+        );
+        assert_eq!(
+            range(
+                &document,
+                at(10, 0),
+                Motion::WordForward,
+                None,
+                Operator::Delete
+            ),
+            Range::Chars(at(10, 0), at(11, 0))
+        );
+        assert_eq!(
+            range(&document, at(2, 1), Motion::Up, None, Operator::Delete),
+            Range::Lines(0, 1)
+        );
+        assert_eq!(
+            range(&document, at(2, 2), Motion::Line, Some(5), Operator::Yank),
+            Range::Lines(2, 3)
+        );
+    }
+
+    #[test]
+    fn repeat() {
+        let mut vim = Vim::default();
+        for c in "2cw".chars() {
+            vim.push(c);
+        }
+        vim.record(Key::Char('x'));
+        vim.record(Key::Esc);
+        vim.finish_change();
+        assert_eq!(
+            vim.repeat_keys(None),
+            vec![
+                Key::Char('2'),
+                Key::Char('c'),
+                Key::Char('w'),
+                Key::Char('x'),
+                Key::Esc
+            ]
+        );
+
+        // Motions are not changes, so they do not replace the change to repeat
+        vim.push('j');
+        vim.finish_change();
+        assert_eq!(
+            vim.repeat_keys(Some(4))[..2],
+            [Key::Char('4'), Key::Char('c')]
+        );
+    }
+}